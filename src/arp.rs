@@ -0,0 +1,155 @@
+//! An arpeggiator, for playing the keys held down one at a time.
+
+/// The most octaves the held keys can be run up through.
+pub const MAX_OCTAVES: usize = 4;
+
+/// The order the arpeggiator steps through the held keys in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArpMode {
+    Up,
+    Down,
+    /// Up and back down again, without playing the top and bottom twice.
+    UpDown,
+    Random,
+}
+
+impl ArpMode {
+    pub const ALL: [ArpMode; 4] = [ArpMode::Up, ArpMode::Down, ArpMode::UpDown, ArpMode::Random];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ArpMode::Up => "Up",
+            ArpMode::Down => "Down",
+            ArpMode::UpDown => "Up and down",
+            ArpMode::Random => "Random",
+        }
+    }
+}
+
+/// The keys held down, lowest first, and how far through them the arpeggiator has got.
+pub struct Arpeggiator {
+    held: Vec<u8>,
+    /// How hard the last key was struck, which every step is played at.
+    velocity: u8,
+    /// The steps taken since the first key went down.
+    step: usize,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Arpeggiator {
+        Arpeggiator {
+            // Room for every key, so holding more down never allocates.
+            held: Vec::with_capacity(::MIDI_NOTES),
+            velocity: 0,
+            step: 0,
+        }
+    }
+
+    /// Let go of every key, and start again from the first step.
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.step = 0;
+    }
+
+    /// Go back to the first step, keeping the keys held.
+    pub fn restart(&mut self) {
+        self.step = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    pub fn velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    /// Hold `note` down, struck at `velocity`.
+    pub fn press(&mut self, note: u8, velocity: u8) {
+        self.velocity = velocity;
+        if let Err(idx) = self.held.binary_search(&note) {
+            self.held.insert(idx, note);
+        }
+    }
+
+    pub fn release(&mut self, note: u8) {
+        if let Ok(idx) = self.held.binary_search(&note) {
+            self.held.remove(idx);
+        }
+        // The next chord is played from its start.
+        if self.held.is_empty() {
+            self.step = 0;
+        }
+    }
+
+    /// The key to play on the next step, running up through `octaves` octaves in `mode`, or
+    /// `None` if nothing is held. `chance`, `0.0..1.0`, picks the key in `ArpMode::Random`.
+    pub fn next(&mut self, mode: ArpMode, octaves: usize, chance: f64) -> Option<u8> {
+        if self.held.is_empty() {
+            return None;
+        }
+        let step = self.step;
+        self.step += 1;
+        // The held keys run up through each octave in turn.
+        let held = &self.held;
+        let len = held.len() * octaves;
+        let up = |i: usize| {
+            let octave = (i / held.len()) as u8;
+            held[i % held.len()].saturating_add(12 * octave).min(127)
+        };
+        Some(match mode {
+            ArpMode::Up => up(step % len),
+            ArpMode::Down => up(len - 1 - step % len),
+            ArpMode::UpDown if len < 2 => up(0),
+            ArpMode::UpDown => {
+                let turn = step % (2 * len - 2);
+                up(if turn < len { turn } else { 2 * len - 2 - turn })
+            }
+            ArpMode::Random => up(((chance * len as f64) as usize).min(len - 1)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arp::{ArpMode, Arpeggiator};
+
+    /// The first `steps` keys played from a C major chord, struck out of order.
+    fn pattern(mode: ArpMode, octaves: usize, steps: usize) -> Vec<u8> {
+        let mut arp = Arpeggiator::new();
+        for &note in [64, 60, 67].iter() {
+            arp.press(note, 100);
+        }
+        (0..steps)
+            .map(|_| arp.next(mode, octaves, 0.5).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_steps_through_held_keys_in_order() {
+        assert_eq!(pattern(ArpMode::Up, 1, 4), [60, 64, 67, 60]);
+        assert_eq!(pattern(ArpMode::Down, 1, 4), [67, 64, 60, 67]);
+        assert_eq!(pattern(ArpMode::UpDown, 1, 6), [60, 64, 67, 64, 60, 64]);
+        assert_eq!(pattern(ArpMode::Up, 2, 7), [60, 64, 67, 72, 76, 79, 60]);
+        // Halfway through the two octaves' keys, every time.
+        let random = pattern(ArpMode::Random, 2, 8);
+        assert!(random.iter().all(|&note| note == 72), "{:?}", random);
+    }
+
+    #[test]
+    fn test_letting_go_starts_over() {
+        let mut arp = Arpeggiator::new();
+        arp.press(60, 100);
+        arp.press(64, 90);
+        assert_eq!(arp.velocity(), 90);
+        assert_eq!(arp.next(ArpMode::Up, 1, 0.0), Some(60));
+        arp.release(60);
+        assert_eq!(arp.next(ArpMode::Up, 1, 0.0), Some(64));
+        arp.release(64);
+        assert!(arp.is_empty());
+        assert_eq!(arp.next(ArpMode::Up, 1, 0.0), None);
+        arp.press(67, 100);
+        arp.press(60, 100);
+        assert_eq!(arp.next(ArpMode::Up, 1, 0.0), Some(60));
+    }
+}
@@ -0,0 +1,135 @@
+//! A stereo chorus, for thickening the output with copies of itself that drift in time.
+
+use lfo::{Phasor, Shape};
+
+/// The fastest the delay can be swept, in hz.
+pub const MAX_CHORUS_RATE: f64 = 5.0;
+
+/// The furthest the delay can be swept either side of `BASE_DELAY`, in seconds.
+pub const MAX_CHORUS_DEPTH: f64 = 0.005;
+
+/// The delay the sweep is centred on, in seconds.
+const BASE_DELAY: f64 = 0.007;
+
+/// The longest the chorus holds a sample back for, in seconds.
+pub const MAX_CHORUS_DELAY: f64 = BASE_DELAY + MAX_CHORUS_DEPTH;
+
+pub struct Chorus {
+    /// One line for each side, swept in opposite directions so the two sides differ.
+    lines: [Vec<f32>; 2],
+    /// Where the next input sample is written.
+    position: usize,
+    lfo: Phasor,
+    sample_rate: f64,
+}
+
+impl Chorus {
+    /// Create a chorus with room for its longest delay at `sample_rate`.
+    pub fn new(sample_rate: f64) -> Chorus {
+        let mut chorus = Chorus {
+            lines: [Vec::new(), Vec::new()],
+            position: 0,
+            lfo: Phasor::new(),
+            sample_rate,
+        };
+        chorus.resize(sample_rate);
+        chorus
+    }
+
+    /// Make room for the longest delay at `sample_rate`, clearing the lines.
+    ///
+    /// Both lines are allocated afresh, as long as the rate needs.
+    pub fn resize(&mut self, sample_rate: f64) {
+        // Two extra samples for the interpolation either side of the longest delay.
+        let len = (MAX_CHORUS_DELAY * sample_rate) as usize + 2;
+        self.lines = [vec![0.0; len], vec![0.0; len]];
+        self.position = 0;
+        self.sample_rate = sample_rate;
+    }
+
+    /// Silence the lines and restart the sweep without reallocating.
+    pub fn clear(&mut self) {
+        for line in self.lines.iter_mut() {
+            for sample in line.iter_mut() {
+                *sample = 0.0;
+            }
+        }
+        self.lfo.reset();
+    }
+
+    /// Feed a stereo `frame` through the chorus and return the result.
+    ///
+    /// The delay is swept at `rate` hz by `depth` seconds either side of its centre, and `mix`
+    /// blends from the dry frame at `0.0` to only the delayed copy at `1.0`.
+    pub fn process(&mut self, frame: [f32; 2], rate: f64, depth: f64, mix: f32) -> [f32; 2] {
+        let len = self.lines[0].len();
+        let sweep = self.lfo.value(Shape::Sine) * depth.min(MAX_CHORUS_DEPTH);
+        self.lfo.advance(rate, self.sample_rate);
+
+        let mut output = frame;
+        for (side, (line, sample)) in self.lines.iter_mut().zip(output.iter_mut()).enumerate() {
+            line[self.position] = *sample;
+            let sweep = if side == 0 { sweep } else { -sweep };
+            let delay = ((BASE_DELAY + sweep) * self.sample_rate).max(1.0);
+            let whole = delay as usize;
+            let fraction = (delay - whole as f64) as f32;
+            let newer = line[(self.position + len - whole) % len];
+            let older = line[(self.position + len - whole - 1) % len];
+            let wet = newer + (older - newer) * fraction;
+            *sample += (wet - *sample) * mix;
+        }
+        self.position = (self.position + 1) % len;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chorus::{Chorus, MAX_CHORUS_DEPTH};
+
+    /// How strongly `signal`, at 1000 samples a second, contains `frequency`.
+    fn magnitude(signal: &[f32], frequency: f64) -> f64 {
+        let (mut sin, mut cos) = (0.0, 0.0);
+        for (i, &sample) in signal.iter().enumerate() {
+            let angle = ::TAU * frequency * i as f64 / 1000.0;
+            sin += f64::from(sample) * angle.sin();
+            cos += f64::from(sample) * angle.cos();
+        }
+        2.0 * (sin * sin + cos * cos).sqrt() / signal.len() as f64
+    }
+
+    /// Run a steady 100hz tone through a chorus at 1000 samples a second for four seconds.
+    fn run(rate: f64, depth: f64, mix: f32) -> (Vec<f32>, Vec<f32>) {
+        let mut chorus = Chorus::new(1000.0);
+        (0..4000)
+            .map(|i| {
+                let tone = (::TAU * 100.0 * f64::from(i) / 1000.0).sin() as f32;
+                let [left, right] = chorus.process([tone, tone], rate, depth, mix);
+                (left, right)
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn test_dry_is_transparent() {
+        let (left, right) = run(2.0, MAX_CHORUS_DEPTH, 0.0);
+        for (i, (&left, &right)) in left.iter().zip(right.iter()).enumerate() {
+            let tone = (::TAU * 100.0 * i as f64 / 1000.0).sin() as f32;
+            assert_eq!((left, right), (tone, tone));
+        }
+    }
+
+    #[test]
+    fn test_sweep_makes_sidebands() {
+        // A 5hz sweep of the delay moves some of the tone out to 95hz and 105hz.
+        // The first second is skipped, while the line fills.
+        let steady = &run(5.0, 0.0, 1.0).0[1000..];
+        let swept = &run(5.0, MAX_CHORUS_DEPTH, 1.0).0[1000..];
+        assert!(magnitude(steady, 105.0) < 1e-3);
+        for &sideband in [95.0, 105.0].iter() {
+            let level = magnitude(swept, sideband);
+            assert!(level > 0.1, "{}: {}", sideband, level);
+        }
+        assert!(magnitude(swept, 100.0) < magnitude(steady, 100.0));
+    }
+}
@@ -0,0 +1,122 @@
+//! A feed-forward compressor, for gluing the voices together and evening out the output.
+
+use gate::coefficient;
+
+/// The lowest threshold the compressor can be set to, in dB.
+pub const MIN_COMP_THRESHOLD_DB: f32 = -60.0;
+
+/// The steepest the compressor can be, as the dB in for each dB out over the threshold.
+pub const MAX_RATIO: f32 = 20.0;
+
+/// The longest the compressor can take to clamp down and to let go, in seconds.
+pub const MAX_COMP_ATTACK: f64 = 0.1;
+pub const MAX_COMP_RELEASE: f64 = 1.0;
+
+/// The most the output can be made back up by, in dB.
+pub const MAX_MAKEUP_DB: f32 = 24.0;
+
+pub struct Compressor {
+    /// The level the compressor compares against its threshold, following the peaks of the
+    /// input.
+    level: f32,
+    /// The threshold as a gain, and how much of each dB over it is taken off.
+    threshold: f32,
+    reduction: f32,
+    makeup: f32,
+    /// How much of the way towards the peak the level is left each sample while it is rising,
+    /// and while it is falling.
+    attack: f32,
+    release: f32,
+}
+
+impl Compressor {
+    /// Create a compressor that leaves everything as it is, until it is given a ratio.
+    pub fn new() -> Compressor {
+        Compressor {
+            level: 0.0,
+            threshold: 1.0,
+            reduction: 0.0,
+            makeup: 1.0,
+            attack: 0.0,
+            release: 0.0,
+        }
+    }
+
+    /// Forget the level heard so far.
+    pub fn reset(&mut self) {
+        self.level = 0.0;
+    }
+
+    /// Turn the level over `threshold_db` down by `ratio`, clamping down over `attack` seconds
+    /// and letting go over `release` seconds at `sample_rate`, then make the output up by
+    /// `makeup_db`.
+    pub fn set(
+        &mut self,
+        threshold_db: f32,
+        ratio: f32,
+        attack: f64,
+        release: f64,
+        makeup_db: f32,
+        sample_rate: f64,
+    ) {
+        self.threshold = ::db_to_gain(threshold_db);
+        self.reduction = 1.0 - 1.0 / ratio.max(1.0);
+        self.makeup = ::db_to_gain(makeup_db);
+        self.attack = coefficient(attack, sample_rate);
+        self.release = coefficient(release, sample_rate);
+    }
+
+    pub fn process(&mut self, frame: [f32; 2]) -> [f32; 2] {
+        // At 1:1 with no makeup, the output is left exactly as it came in.
+        if self.reduction <= 0.0 && self.makeup == 1.0 {
+            return frame;
+        }
+        let peak = frame[0].abs().max(frame[1].abs());
+        let kept = if peak > self.level {
+            self.attack
+        } else {
+            self.release
+        };
+        self.level = ::flush_denormal(peak + (self.level - peak) * kept);
+        let mut gain = self.makeup;
+        if self.level > self.threshold {
+            let over_db = 20.0 * (self.level / self.threshold).log10();
+            gain *= ::db_to_gain(-over_db * self.reduction);
+        }
+        [frame[0] * gain, frame[1] * gain]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use compressor::Compressor;
+
+    /// The level of the compressor's output once it has settled on `seconds` of a steady
+    /// `level` in, at 1000 samples a second.
+    fn settled(compressor: &mut Compressor, level: f32, seconds: f64) -> f32 {
+        let samples = (seconds * 1000.0) as usize;
+        (0..samples).fold(0.0, |_, _| compressor.process([level, -level])[0])
+    }
+
+    #[test]
+    fn test_turns_down_by_ratio() {
+        let mut compressor = Compressor::new();
+        compressor.set(-20.0, 4.0, 0.01, 0.1, 0.0, 1000.0);
+        // 14dB over the threshold comes out 3.5dB over it.
+        let out = 20.0 * settled(&mut compressor, 0.5, 1.0).log10();
+        let expected = -20.0 + (20.0 * 0.5f32.log10() + 20.0) / 4.0;
+        assert!((out - expected).abs() < 0.01, "{} {}", out, expected);
+        // Under the threshold, only the makeup is heard.
+        compressor.set(-20.0, 4.0, 0.01, 0.1, 6.0, 1000.0);
+        let quiet = settled(&mut compressor, 0.05, 2.0);
+        assert!((quiet - 0.05 * ::db_to_gain(6.0)).abs() < 1e-4, "{}", quiet);
+    }
+
+    #[test]
+    fn test_high_threshold_is_transparent() {
+        let mut compressor = Compressor::new();
+        assert_eq!(compressor.process([0.9, -0.3]), [0.9, -0.3]);
+        compressor.set(0.0, 20.0, 0.01, 0.1, 0.0, 1000.0);
+        assert_eq!(settled(&mut compressor, 0.9, 1.0), 0.9);
+    }
+}
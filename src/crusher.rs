@@ -0,0 +1,81 @@
+//! Lo-fi degradation by bit depth reduction and sample-and-hold downsampling.
+
+/// The deepest the crusher can be set, at which point it no longer quantizes at all.
+pub const MAX_BIT_DEPTH: u32 = 24;
+
+/// The most samples the crusher can hold each value for.
+pub const MAX_DOWNSAMPLE: usize = 32;
+
+pub struct BitCrusher {
+    /// The value being held until the next sample is taken.
+    held: f32,
+    /// How many samples the held value has been output for.
+    count: usize,
+}
+
+impl BitCrusher {
+    pub fn new() -> BitCrusher {
+        BitCrusher {
+            held: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Drop the held value so the next sample is taken afresh.
+    pub fn reset(&mut self) {
+        self.held = 0.0;
+        self.count = 0;
+    }
+
+    /// Crush `sample` down to `bits` of resolution, taking a new sample only every `downsample`
+    /// samples and holding it in between.
+    ///
+    /// At `MAX_BIT_DEPTH` and a `downsample` of `1` the input is passed through untouched.
+    pub fn process(&mut self, sample: f32, bits: u32, downsample: usize) -> f32 {
+        if self.count == 0 {
+            self.held = if bits >= MAX_BIT_DEPTH {
+                sample
+            } else {
+                // Full scale is split into 2^bits steps, half either side of zero.
+                let steps = (1u32 << (bits - 1)) as f32;
+                (sample * steps).round() / steps
+            };
+        }
+        self.count += 1;
+        if self.count >= downsample {
+            self.count = 0;
+        }
+        self.held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crusher::{BitCrusher, MAX_BIT_DEPTH};
+
+    #[test]
+    fn test_transparent_at_full_depth() {
+        let mut crusher = BitCrusher::new();
+        for i in 0..100 {
+            let sample = (i as f32 * 0.1).sin() * 0.9;
+            assert_eq!(crusher.process(sample, MAX_BIT_DEPTH, 1), sample);
+        }
+    }
+
+    #[test]
+    fn test_quantizes_to_bit_depth() {
+        let mut crusher = BitCrusher::new();
+        assert_eq!(crusher.process(0.3, 2, 1), 0.5);
+        assert_eq!(crusher.process(-0.2, 2, 1), 0.0);
+        assert_eq!(crusher.process(-0.8, 2, 1), -1.0);
+    }
+
+    #[test]
+    fn test_holds_when_downsampling() {
+        let mut crusher = BitCrusher::new();
+        let output: Vec<f32> = (0..6)
+            .map(|i| crusher.process(i as f32, MAX_BIT_DEPTH, 3))
+            .collect();
+        assert_eq!(output, vec![0.0, 0.0, 0.0, 3.0, 3.0, 3.0]);
+    }
+}
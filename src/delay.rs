@@ -0,0 +1,152 @@
+//! A feedback delay line for echoes.
+
+/// The longest delay time available, in seconds.
+pub const MAX_DELAY: f64 = 2.0;
+
+/// The most feedback allowed, kept below `1.0` so the echoes always die away.
+pub const MAX_FEEDBACK: f32 = 0.95;
+
+pub struct Delay {
+    buffer: Vec<f32>,
+    /// Where the next input sample is written.
+    position: usize,
+    /// The longest delay the line has room for, in seconds.
+    max_delay: f64,
+}
+
+impl Delay {
+    /// Create a delay line with room for `MAX_DELAY` seconds at `sample_rate`.
+    pub fn new(sample_rate: f64) -> Delay {
+        Delay::with_max_delay(MAX_DELAY, sample_rate)
+    }
+
+    /// Create a delay line with room for only `max_delay` seconds at `sample_rate`.
+    pub fn with_max_delay(max_delay: f64, sample_rate: f64) -> Delay {
+        let mut delay = Delay {
+            buffer: Vec::new(),
+            position: 0,
+            max_delay,
+        };
+        delay.resize(sample_rate);
+        delay
+    }
+
+    /// Make room for the line's longest delay at `sample_rate`, clearing it.
+    ///
+    /// This allocates, so it belongs in `set_sample_rate` rather than the audio loop.
+    pub fn resize(&mut self, sample_rate: f64) {
+        self.buffer = vec![0.0; (self.max_delay * sample_rate) as usize + 1];
+        self.position = 0;
+    }
+
+    /// Silence the line without reallocating it.
+    pub fn clear(&mut self) {
+        for sample in self.buffer.iter_mut() {
+            *sample = 0.0;
+        }
+    }
+
+    /// Feed `input` into the line and return the echo from `delay` samples ago.
+    ///
+    /// The echo is fed back into the line scaled by `feedback`, which is capped at
+    /// `MAX_FEEDBACK`.
+    pub fn process(&mut self, input: f32, delay: usize, feedback: f32) -> f32 {
+        let echo = self.echo(delay);
+        self.push(input + echo * feedback.min(MAX_FEEDBACK));
+        echo
+    }
+
+    /// The echo from `delay` samples ago, before the next sample goes in.
+    fn echo(&self, delay: usize) -> f32 {
+        let len = self.buffer.len();
+        let delay = delay.max(1).min(len - 1);
+        self.buffer[(self.position + len - delay) % len]
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.buffer[self.position] = ::flush_denormal(sample);
+        self.position = (self.position + 1) % self.buffer.len();
+    }
+}
+
+/// Feed a stereo `frame` into a pair of lines that bounce its echoes between the two sides,
+/// and return the echoes from `delays` samples ago on each side.
+///
+/// Both sides of the input go into the left line, and each line's echo is fed into the other
+/// scaled by `feedback`, which is capped at `MAX_FEEDBACK`.
+pub fn ping_pong(
+    lines: &mut [Delay; 2],
+    frame: [f32; 2],
+    delays: [usize; 2],
+    feedback: f32,
+) -> [f32; 2] {
+    let feedback = feedback.min(MAX_FEEDBACK);
+    let echoes = [lines[0].echo(delays[0]), lines[1].echo(delays[1])];
+    lines[0].push((frame[0] + frame[1]) * 0.5 + echoes[1] * feedback);
+    lines[1].push(echoes[0] * feedback);
+    echoes
+}
+
+#[cfg(test)]
+mod tests {
+    use delay::{ping_pong, Delay, MAX_FEEDBACK};
+
+    #[test]
+    fn test_impulse_echoes_at_delay_time() {
+        let mut delay = Delay::new(100.0);
+        let output: Vec<f32> = (0..31)
+            .map(|i| delay.process(if i == 0 { 1.0 } else { 0.0 }, 10, 0.5))
+            .collect();
+        for (i, &sample) in output.iter().enumerate() {
+            let expected = match i {
+                10 => 1.0,
+                20 => 0.5,
+                30 => 0.25,
+                _ => 0.0,
+            };
+            assert_eq!(sample, expected, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn test_ping_pong_bounces_between_sides() {
+        let mut lines = [Delay::new(100.0), Delay::new(100.0)];
+        let output: Vec<[f32; 2]> = (0..41)
+            .map(|i| {
+                let input = if i == 0 { 1.0 } else { 0.0 };
+                ping_pong(&mut lines, [input, input], [10, 10], 0.5)
+            })
+            .collect();
+        for (i, &frame) in output.iter().enumerate() {
+            let expected = match i {
+                10 => [1.0, 0.0],
+                20 => [0.0, 0.5],
+                30 => [0.25, 0.0],
+                40 => [0.0, 0.125],
+                _ => [0.0, 0.0],
+            };
+            assert_eq!(frame, expected, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn test_feedback_is_capped() {
+        let mut delay = Delay::new(100.0);
+        delay.process(1.0, 1, 2.0);
+        assert_eq!(delay.process(0.0, 1, 2.0), 1.0);
+        assert_eq!(delay.process(0.0, 1, 2.0), MAX_FEEDBACK);
+    }
+
+    #[test]
+    fn test_decaying_echoes_flush_to_zero() {
+        let mut delay = Delay::new(100.0);
+        delay.process(1.0, 1, 0.5);
+        // Go round the whole line twice so every slot has been rewritten since the impulse.
+        for _ in 0..500 {
+            delay.process(0.0, 1, 0.5);
+            let mut echoes = delay.buffer.iter();
+            assert!(echoes.all(|&sample| sample == 0.0 || sample.is_normal()));
+        }
+        assert!(delay.buffer.iter().all(|&sample| sample == 0.0));
+    }
+}
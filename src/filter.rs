@@ -0,0 +1,95 @@
+//! A resonant high-pass, for carving the low end out of the noise.
+
+use std::f64::consts::PI;
+
+/// The lowest and highest cutoffs the high-pass can be set to, in hz.
+pub const MIN_CUTOFF: f64 = 20.0;
+pub const MAX_CUTOFF: f64 = 20000.0;
+
+/// The range of the filter's Q, from a gentle slope to a sharp peak at the cutoff.
+pub const MIN_Q: f64 = 0.5;
+pub const MAX_Q: f64 = 10.0;
+
+/// A two-pole high-pass, after the RBJ audio EQ cookbook, in transposed direct form II.
+pub struct HighPass {
+    /// `b0, b1, b2, a1, a2`, normalized so `a0` is `1.0`.
+    coefficients: [f32; 5],
+    state: [f32; 2],
+}
+
+impl HighPass {
+    pub fn new() -> HighPass {
+        HighPass {
+            // Until it is set, the filter passes everything through.
+            coefficients: [1.0, 0.0, 0.0, 0.0, 0.0],
+            state: [0.0; 2],
+        }
+    }
+
+    /// Clear the filter's history.
+    pub fn reset(&mut self) {
+        self.state = [0.0; 2];
+    }
+
+    /// Tune the filter to `cutoff` hz with a Q of `q`, at `sample_rate`.
+    pub fn set(&mut self, cutoff: f64, q: f64, sample_rate: f64) {
+        // Kept under nyquist, where the cookbook's formulas fall apart.
+        let omega = 2.0 * PI * cutoff.min(sample_rate * 0.49) / sample_rate;
+        let alpha = omega.sin() / (2.0 * q);
+        let cos = omega.cos();
+        let a0 = 1.0 + alpha;
+        self.coefficients = [
+            ((1.0 + cos) / 2.0 / a0) as f32,
+            (-(1.0 + cos) / a0) as f32,
+            ((1.0 + cos) / 2.0 / a0) as f32,
+            (-2.0 * cos / a0) as f32,
+            ((1.0 - alpha) / a0) as f32,
+        ];
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let output = b0 * input + self.state[0];
+        self.state[0] = ::flush_denormal(b1 * input - a1 * output + self.state[1]);
+        self.state[1] = ::flush_denormal(b2 * input - a2 * output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use filter::HighPass;
+    use std::f64::consts::PI;
+
+    /// The filter's gain at `frequency` hz, at 44100 samples a second.
+    fn gain(filter: &mut HighPass, frequency: f64) -> f64 {
+        filter.reset();
+        let mut peak = 0.0f64;
+        for i in 0..44100 {
+            let input = (2.0 * PI * frequency * f64::from(i) / 44100.0).sin() as f32;
+            let output = filter.process(input);
+            // Only once it has settled.
+            if i > 22050 {
+                peak = peak.max(f64::from(output.abs()));
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn test_cuts_below_cutoff() {
+        let mut filter = HighPass::new();
+        filter.set(1000.0, 0.707, 44100.0);
+        assert!(gain(&mut filter, 50.0) < 0.01);
+        assert!((gain(&mut filter, 10000.0) - 1.0).abs() < 0.05);
+        // At the cutoff a Butterworth Q is 3dB down.
+        assert!((gain(&mut filter, 1000.0) - 0.707).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_resonance_peaks_at_cutoff() {
+        let mut filter = HighPass::new();
+        filter.set(1000.0, 8.0, 44100.0);
+        assert!(gain(&mut filter, 1000.0) > 4.0);
+    }
+}
@@ -0,0 +1,120 @@
+//! A noise gate, for chopping the output off once its level falls away.
+
+/// The quietest threshold the gate can be set to, in dB.
+pub const MIN_THRESHOLD_DB: f32 = -60.0;
+
+/// The longest the gate can take to open and to close, in seconds.
+pub const MAX_GATE_ATTACK: f64 = 0.05;
+pub const MAX_GATE_RELEASE: f64 = 1.0;
+
+/// How quickly the level the gate listens to falls away between peaks, in seconds. Short enough
+/// to follow the shape of a note, long enough not to chatter on every cycle of a low one.
+const DETECTOR_RELEASE: f64 = 0.01;
+
+pub struct Gate {
+    /// The level the gate compares against its threshold, following the peaks of the input.
+    level: f32,
+    /// The gain the gate is applying, from `0.0` when shut to `1.0` when open.
+    gain: f32,
+    threshold: f32,
+    /// How much of the way towards its target the gain is left each sample while opening, while
+    /// closing, and how much of the level is left.
+    opening: f32,
+    closing: f32,
+    falling: f32,
+}
+
+/// How much of a one-pole smoother's distance to its target is left after each sample, for it
+/// to come within `1/e` of the target in `seconds`.
+pub fn coefficient(seconds: f64, sample_rate: f64) -> f32 {
+    if seconds > 0.0 {
+        (-1.0 / (seconds * sample_rate)).exp() as f32
+    } else {
+        0.0
+    }
+}
+
+impl Gate {
+    /// Create a gate that is open, and off until it is given a threshold.
+    pub fn new() -> Gate {
+        Gate {
+            level: 0.0,
+            gain: 1.0,
+            threshold: 0.0,
+            opening: 0.0,
+            closing: 0.0,
+            falling: 0.0,
+        }
+    }
+
+    /// Forget the level heard so far, and open up.
+    pub fn reset(&mut self) {
+        self.level = 0.0;
+        self.gain = 1.0;
+    }
+
+    /// Shut the gate whenever the level is under `threshold`, a gain, or never for `0.0`. It
+    /// opens over `attack` seconds and closes over `release` seconds at `sample_rate`.
+    pub fn set(&mut self, threshold: f32, attack: f64, release: f64, sample_rate: f64) {
+        self.threshold = threshold;
+        self.opening = coefficient(attack, sample_rate);
+        self.closing = coefficient(release, sample_rate);
+        self.falling = coefficient(DETECTOR_RELEASE, sample_rate);
+    }
+
+    pub fn process(&mut self, frame: [f32; 2]) -> [f32; 2] {
+        if self.threshold <= 0.0 {
+            return frame;
+        }
+        let peak = frame[0].abs().max(frame[1].abs());
+        self.level = ::flush_denormal(peak.max(self.level * self.falling));
+        let (target, kept) = if self.level >= self.threshold {
+            (1.0, self.opening)
+        } else {
+            (0.0, self.closing)
+        };
+        self.gain = ::flush_denormal(target + (self.gain - target) * kept);
+        [frame[0] * self.gain, frame[1] * self.gain]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gate::Gate;
+
+    /// The gate's gain through `seconds` of a steady 100hz tone at `level`, at 1000 samples a
+    /// second.
+    fn gains(gate: &mut Gate, level: f32, seconds: f64) -> Vec<f32> {
+        (0..(seconds * 1000.0) as usize)
+            .map(|i| {
+                let tone = level * (::TAU * f64::from(i as u32) / 10.0).sin() as f32;
+                gate.process([tone, tone]);
+                gate.gain
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_passes_above_threshold() {
+        let mut gate = Gate::new();
+        gate.set(0.1, 0.01, 0.1, 1000.0);
+        let open = gains(&mut gate, 0.5, 1.0);
+        assert!(open.iter().all(|&gain| gain > 0.99));
+    }
+
+    #[test]
+    fn test_closes_and_opens_in_time() {
+        let mut gate = Gate::new();
+        gate.set(0.1, 0.01, 0.1, 1000.0);
+        let closing = gains(&mut gate, 0.05, 1.0);
+        // A tone under the threshold shuts the gate, to within `1/e` after one release.
+        let shut = closing[100] - (-1.0f32).exp();
+        assert!(shut.abs() < 0.1, "{}", closing[100]);
+        assert!(closing[999] < 1e-3);
+
+        let opening = gains(&mut gate, 0.5, 0.1);
+        assert!(opening[0] < 0.5);
+        // And open again within a few attacks.
+        assert!(opening[40] > 0.95, "{}", opening[40]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Low frequency oscillators for moving parameters over time.
+
+/// The waveform traced out by a `Phasor`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Sine,
+    Triangle,
+    Square,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 3] = [Shape::Sine, Shape::Triangle, Shape::Square];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Sine => "Sine",
+            Shape::Triangle => "Triangle",
+            Shape::Square => "Square",
+        }
+    }
+}
+
+/// A free-running oscillator whose output swings between `-1.0` and `1.0`, behind every LFO
+/// and sweep.
+///
+/// It keeps its place as a fraction of a cycle rather than in samples, so it carries on at the
+/// same rate when the sample rate changes under it.
+pub struct Phasor {
+    /// Position within the current cycle, `0.0..1.0`.
+    phase: f64,
+}
+
+impl Phasor {
+    pub fn new() -> Phasor {
+        Phasor { phase: 0.0 }
+    }
+
+    /// The oscillator's output at its current phase.
+    pub fn value(&self, shape: Shape) -> f64 {
+        match shape {
+            Shape::Sine => (self.phase * ::TAU).sin(),
+            Shape::Triangle => 1.0 - 4.0 * (self.phase - 0.25).abs().min((self.phase - 1.25).abs()),
+            Shape::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+
+    /// Return to the start of the cycle.
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    /// Move the oscillator on by one sample at `rate` hz.
+    pub fn advance(&mut self, rate: f64, sample_rate: f64) {
+        self.phase = (self.phase + rate / sample_rate).fract();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lfo::{Phasor, Shape};
+
+    #[test]
+    fn test_one_cycle_per_period() {
+        for &sample_rate in [44100.0, 48000.0].iter() {
+            // 441hz divides 44100 exactly but not 48000, so one period there is a fraction.
+            let frequency = 441.0;
+            let mut phasor = Phasor::new();
+            let mut cycles = 0;
+            let samples = sample_rate as usize;
+            for _ in 0..samples {
+                let before = phasor.phase;
+                phasor.advance(frequency, sample_rate);
+                if phasor.phase < before {
+                    cycles += 1;
+                }
+            }
+            // A second at any rate is 441 cycles, landing back at the start.
+            assert_eq!(cycles, 441, "{}", sample_rate);
+            let off = phasor.phase.min(1.0 - phasor.phase);
+            assert!(off < 1e-6, "{}: {}", sample_rate, phasor.phase);
+        }
+    }
+
+    #[test]
+    fn test_shapes_stay_in_range() {
+        for &shape in Shape::ALL.iter() {
+            let mut lfo = Phasor::new();
+            for _ in 0..1000 {
+                let value = lfo.value(shape);
+                assert!((-1.0..=1.0).contains(&value), "{}: {}", shape.name(), value);
+                lfo.advance(3.0, 1000.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_triangle_peaks() {
+        let mut lfo = Phasor::new();
+        let mut values = Vec::new();
+        for _ in 0..4 {
+            values.push(lfo.value(Shape::Triangle));
+            lfo.advance(1.0, 4.0);
+        }
+        assert_eq!(values, vec![0.0, 1.0, 0.0, -1.0]);
+    }
+}
@@ -2,14 +2,60 @@
 
 #[macro_use]
 extern crate vst;
-extern crate karplus;
+extern crate noise;
+extern crate rand;
+#[cfg(feature = "diagnostics")]
+#[macro_use]
+extern crate log;
+
+mod arp;
+mod chorus;
+mod compressor;
+mod crusher;
+mod delay;
+mod filter;
+mod gate;
+mod lfo;
+mod oversample;
+mod ramp;
+mod reverb;
+mod scale;
+mod shaper;
+mod sources;
+mod string;
+mod tempo;
 
-use vst::api::{Events, Supported};
+use vst::api::{Events, Supported, TimeInfoFlags};
 use vst::buffer::AudioBuffer;
 use vst::event::Event;
-use vst::plugin::{CanDo, Category, HostCallback, Info, Plugin};
-use karplus::Karplus;
-use std::f64::consts::PI;
+use vst::plugin::{CanDo, Category, Host, HostCallback, Info, Plugin, PluginParameters};
+#[cfg(not(test))]
+use vst::util::AtomicFloat;
+#[cfg(test)]
+use tests::AtomicFloat;
+use arp::{ArpMode, Arpeggiator, MAX_OCTAVES};
+use chorus::{Chorus, MAX_CHORUS_DELAY, MAX_CHORUS_DEPTH, MAX_CHORUS_RATE};
+use compressor::{Compressor, MAX_COMP_ATTACK, MAX_COMP_RELEASE, MAX_MAKEUP_DB};
+use compressor::{MAX_RATIO, MIN_COMP_THRESHOLD_DB};
+use crusher::{BitCrusher, MAX_BIT_DEPTH, MAX_DOWNSAMPLE};
+use delay::{ping_pong, Delay, MAX_DELAY, MAX_FEEDBACK};
+use filter::{HighPass, MAX_CUTOFF, MAX_Q, MIN_CUTOFF, MIN_Q};
+use gate::{Gate, MAX_GATE_ATTACK, MAX_GATE_RELEASE, MIN_THRESHOLD_DB};
+use lfo::{Phasor, Shape};
+use oversample::{Oversampler, Oversampling, MAX_OVERSAMPLING};
+use ramp::Ramp;
+use reverb::Reverb;
+use scale::Scale;
+use shaper::{Curve, MAX_DRIVE};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use sources::{NoiseGenerators, NoiseSource, NOISE_SOURCES};
+use std::f64::consts::{PI, SQRT_2};
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use string::{KarplusString, Tuning, DEFAULT_DAMPING, MAX_DAMPING, MAX_DECAY, MAX_EXCITE_LENGTH};
+use string::{MIN_DAMPING, MIN_DECAY};
+use tempo::{Division, MidiClock};
 
 /// Convert the midi note's pitch into the equivalent frequency.
 ///
@@ -22,147 +68,6684 @@ fn midi_pitch_to_freq(pitch: u8) -> f64 {
     ((f64::from(pitch as i8 - A4_PITCH)) / 12.).exp2() * A4_FREQ
 }
 
-struct SineSynth {
-    sample_rate: f64,
-    time: f64,
-    note_duration: f64,
-    note: Option<u8>,
+/// The frequency ratio corresponding to a pitch offset in cents.
+fn cents_to_ratio(cents: f64) -> f64 {
+    (cents / 1200.0).exp2()
 }
 
-impl SineSynth {
-    fn time_per_sample(&self) -> f64 {
-        1.0 / self.sample_rate
+/// The gain corresponding to a level in dB.
+fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Anything smaller than this is flushed to zero before it is fed back into a delay line.
+const DENORMAL_THRESHOLD: f32 = 1e-15;
+
+/// Snap `value` to exactly zero once it has decayed below `DENORMAL_THRESHOLD`.
+///
+/// Left alone, a dying tail sinks into the denormal range, where some CPUs take many times
+/// longer over every operation.
+fn flush_denormal(value: f32) -> f32 {
+    if value.abs() < DENORMAL_THRESHOLD {
+        0.0
+    } else {
+        value
     }
+}
 
-    /// Process an incoming midi event.
-    ///
-    /// The midi data is split up like so:
-    ///
-    /// `data[0]`: Contains the status and the channel. Source: [source]
-    /// `data[1]`: Contains the supplemental data for the message - so, if this was a NoteOn then
-    ///            this would contain the note.
-    /// `data[2]`: Further supplemental data. Would be velocity in the case of a NoteOn message.
-    ///
-    /// [source]: http://www.midimountain.com/midi/midi_status.htm
-    fn process_midi_event(&mut self, data: [u8; 3]) {
-        match data[0] {
-            128 => self.note_off(data[1]),
-            144 => self.note_on(data[1]),
-            _ => (),
+/// Map a normalized parameter value onto one of `count` evenly sized steps.
+fn parameter_to_step(value: f32, count: usize) -> usize {
+    ((value * count as f32) as usize).min(count - 1)
+}
+
+/// The normalized parameter value at the centre of step `index` out of `count`.
+fn step_to_parameter(index: usize, count: usize) -> f32 {
+    (index as f32 + 0.5) / count as f32
+}
+
+/// What to do with a note-on for a key that is already held.
+///
+/// Some controllers, or a stuck key, send a second note-on without a note-off in between.
+/// The default is `Retrigger`, which restarts the held voice so the level stays the same
+/// as a single strike.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DuplicateNotes {
+    /// Restart the held voice from a fresh pluck.
+    Retrigger,
+    /// Drop the extra note-on and let the held voice carry on.
+    Ignore,
+    /// Start another voice alongside the held one.
+    Stack,
+}
+
+impl DuplicateNotes {
+    const ALL: [DuplicateNotes; 3] = [
+        DuplicateNotes::Retrigger,
+        DuplicateNotes::Ignore,
+        DuplicateNotes::Stack,
+    ];
+
+    fn from_parameter(value: f32) -> DuplicateNotes {
+        DuplicateNotes::ALL[parameter_to_step(value, DuplicateNotes::ALL.len())]
+    }
+
+    fn to_parameter(self) -> f32 {
+        step_to_parameter(self as usize, DuplicateNotes::ALL.len())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DuplicateNotes::Retrigger => "Retrigger",
+            DuplicateNotes::Ignore => "Ignore",
+            DuplicateNotes::Stack => "Stack",
+        }
+    }
+}
+
+/// How the voices are laid out across the two outputs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputMode {
+    /// One signal, written to both outputs.
+    Mono,
+    /// Each output gets its own draw of every noise source around the shared strings.
+    Stereo,
+    /// The `Stereo` channels summed back down and written to both outputs.
+    MonoSum,
+}
+
+impl OutputMode {
+    const ALL: [OutputMode; 3] = [OutputMode::Mono, OutputMode::Stereo, OutputMode::MonoSum];
+
+    fn from_parameter(value: f32) -> OutputMode {
+        OutputMode::ALL[parameter_to_step(value, OutputMode::ALL.len())]
+    }
+
+    fn to_parameter(self) -> f32 {
+        step_to_parameter(self as usize, OutputMode::ALL.len())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            OutputMode::Mono => "Mono",
+            OutputMode::Stereo => "Stereo",
+            OutputMode::MonoSum => "Mono sum",
         }
     }
 
-    fn note_on(&mut self, note: u8) {
-        self.note_duration = 0.0;
-        self.note = Some(note)
+    /// How many channels have to be rendered for this mode.
+    fn channels(self) -> usize {
+        if self == OutputMode::Mono {
+            1
+        } else {
+            2
+        }
     }
 
-    fn note_off(&mut self, note: u8) {
-        if self.note == Some(note) {
-            self.note = None
+    /// The left and right outputs for the rendered `channels`.
+    fn frame(self, channels: [f32; 2]) -> [f32; 2] {
+        match self {
+            OutputMode::Mono => [channels[0]; 2],
+            OutputMode::Stereo => channels,
+            OutputMode::MonoSum => [(channels[0] + channels[1]) * 0.5; 2],
         }
     }
 }
 
-pub const TAU: f64 = PI * 2.0;
+/// How the noise sources are chosen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SourceMode {
+    /// Every source is mixed in by its own amount.
+    Blend,
+    /// Only the selected source is heard, at its full amount, and the rest are never run.
+    Single,
+}
 
-impl Plugin for SineSynth {
-    fn new(_host: HostCallback) -> Self {
-        SineSynth {
-            sample_rate: 44100.0,
-            note_duration: 0.0,
-            time: 0.0,
-            note: None,
+impl SourceMode {
+    const ALL: [SourceMode; 2] = [SourceMode::Blend, SourceMode::Single];
+
+    fn from_parameter(value: f32) -> SourceMode {
+        SourceMode::ALL[parameter_to_step(value, SourceMode::ALL.len())]
+    }
+
+    fn to_parameter(self) -> f32 {
+        step_to_parameter(self as usize, SourceMode::ALL.len())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SourceMode::Blend => "Blend",
+            SourceMode::Single => "Single",
         }
     }
+}
 
-    fn get_info(&self) -> Info {
-        Info {
-            name: "SineSynth".to_string(),
-            vendor: "DeathDisco".to_string(),
-            unique_id: 6667,
-            category: Category::Synth,
-            inputs: 2,
-            outputs: 2,
-            parameters: 0,
-            initial_delay: 0,
-            ..Info::default()
+/// How many midi notes there are.
+const MIDI_NOTES: usize = 128;
+
+/// The name of midi `note` in scientific pitch notation, where middle C, note 60, is C4.
+fn note_name(note: u8) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[note as usize % 12], i32::from(note) / 12 - 1)
+}
+
+/// How a note's velocity sets the level of its voice.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VelocityCurve {
+    /// Level in proportion to velocity.
+    Linear,
+    /// Gentle playing comes out louder, for a light touch.
+    Soft,
+    /// It takes a harder strike to get the same level, for a heavy touch.
+    Hard,
+    /// Every note at full level, whatever its velocity, like an organ.
+    Fixed,
+}
+
+impl VelocityCurve {
+    const ALL: [VelocityCurve; 4] = [
+        VelocityCurve::Linear,
+        VelocityCurve::Soft,
+        VelocityCurve::Hard,
+        VelocityCurve::Fixed,
+    ];
+
+    fn from_parameter(value: f32) -> VelocityCurve {
+        VelocityCurve::ALL[parameter_to_step(value, VelocityCurve::ALL.len())]
+    }
+
+    fn to_parameter(self) -> f32 {
+        step_to_parameter(self as usize, VelocityCurve::ALL.len())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            VelocityCurve::Linear => "Linear",
+            VelocityCurve::Soft => "Soft",
+            VelocityCurve::Hard => "Hard",
+            VelocityCurve::Fixed => "Fixed",
         }
     }
 
-    #[allow(unused_variables)]
-    #[allow(clippy::single_match)]
-    fn process_events(&mut self, events: &Events) {
-        for event in events.events() {
-            match event {
-                Event::Midi(ev) => self.process_midi_event(ev.data),
-                // More events can be handled here.
-                _ => (),
-            }
+    /// The gain, `0.0..=1.0`, of a voice struck at midi `velocity`.
+    fn gain(self, velocity: u8) -> f64 {
+        let velocity = f64::from(velocity.min(127)) / 127.0;
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => velocity.sqrt(),
+            VelocityCurve::Hard => velocity * velocity,
+            VelocityCurve::Fixed => 1.0,
         }
     }
+}
 
-    fn set_sample_rate(&mut self, rate: f32) {
-        self.sample_rate = f64::from(rate);
+/// How sharply the exponential and logarithmic envelope curves bend.
+const ENVELOPE_CURVATURE: f64 = 5.0;
+
+/// The shape a voice's level follows as its envelope runs through `0.0..=1.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnvelopeCurve {
+    /// Level in proportion to the envelope.
+    Linear,
+    /// Slow to leave silence and quick near the top: a swell as it rises, and a sharp drop
+    /// with a long tail as it falls.
+    Exponential,
+    /// Quick to leave silence and slow near the top: a snap as it rises, and a long hold before
+    /// a sudden cut as it falls.
+    Logarithmic,
+}
+
+impl EnvelopeCurve {
+    const ALL: [EnvelopeCurve; 3] = [
+        EnvelopeCurve::Linear,
+        EnvelopeCurve::Exponential,
+        EnvelopeCurve::Logarithmic,
+    ];
+
+    fn from_parameter(value: f32) -> EnvelopeCurve {
+        EnvelopeCurve::ALL[parameter_to_step(value, EnvelopeCurve::ALL.len())]
     }
 
-    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
-        let samples = buffer.samples();
-        let (_, mut outputs) = buffer.split();
-        let output_count = outputs.len();
-        let per_sample = self.time_per_sample();
-        let mut output_sample;
-
-        if let Some(current_note) = self.note {
-            let time = self.time;
-            let note_duration = self.note_duration;
-            let mut k = Karplus::new(midi_pitch_to_freq(current_note) as f32, self.sample_rate as u32);
-            for sample_idx in 0..samples {
-                let signal = k.sample(0.996);
-                // Apply a quick envelope to the attack of the signal to avoid popping.
-                let attack = 0.5;
-                let alpha = if note_duration < attack {
-                    note_duration / attack
-                } else {
-                    1.0
-                };
-                output_sample = signal; //(signal * alpha) as f32;
-                for buf_idx in 0..output_count {
-                    let buff = outputs.get_mut(buf_idx);
-                    buff[sample_idx] = output_sample;
-                }
-                self.time += per_sample;
-                self.note_duration += per_sample;
-            }
-        } else {
-            for sample_idx in 0..samples {
-                for buf_idx in 0..output_count {
-                    let buff = outputs.get_mut(buf_idx);
-                    buff[sample_idx] = 0.0;
-                }
-            }
+    fn to_parameter(self) -> f32 {
+        step_to_parameter(self as usize, EnvelopeCurve::ALL.len())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            EnvelopeCurve::Linear => "Linear",
+            EnvelopeCurve::Exponential => "Exponential",
+            EnvelopeCurve::Logarithmic => "Logarithmic",
         }
     }
 
-    fn can_do(&self, can_do: CanDo) -> Supported {
-        match can_do {
-            CanDo::ReceiveMidiEvent => Supported::Yes,
-            _ => Supported::Maybe,
+    /// The level, `0.0..=1.0`, at `position` along the envelope.
+    fn apply(self, position: f64) -> f64 {
+        let grow = |x: f64| (ENVELOPE_CURVATURE * x).exp_m1() / ENVELOPE_CURVATURE.exp_m1();
+        match self {
+            EnvelopeCurve::Linear => position,
+            EnvelopeCurve::Exponential => grow(position),
+            EnvelopeCurve::Logarithmic => 1.0 - grow(1.0 - position),
+        }
+    }
+
+    /// The position along the envelope where it reaches `level`, the inverse of `apply`.
+    fn invert(self, level: f64) -> f64 {
+        let shrink = |y: f64| (y * ENVELOPE_CURVATURE.exp_m1()).ln_1p() / ENVELOPE_CURVATURE;
+        match self {
+            EnvelopeCurve::Linear => level,
+            EnvelopeCurve::Exponential => shrink(level),
+            EnvelopeCurve::Logarithmic => 1.0 - shrink(1.0 - level),
         }
     }
 }
 
-plugin_main!(SineSynth);
+/// Push a normalized `value` towards its maximum by `amount`, `0.0..=1.0`.
+fn open_up(value: f64, amount: f64) -> f64 {
+    value + (1.0 - value) * amount
+}
 
-#[cfg(test)]
-mod tests {
-    use midi_pitch_to_freq;
+/// A setting that a performance controller, such as the mod wheel, can be routed to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Destination {
+    /// Boosts the level of each voice by up to 6dB.
+    Amplitude,
+    VibratoDepth,
+    LfoDepth,
+    Amount(NoiseSource),
+}
 
-    #[test]
-    fn test_midi_pitch_to_freq() {
-        for i in 0..127 {
-            // expect no panics
-            midi_pitch_to_freq(i);
+impl Destination {
+    /// How many destinations there are to choose between.
+    const COUNT: usize = 3 + NOISE_SOURCES;
+
+    fn from_parameter(value: f32) -> Destination {
+        match parameter_to_step(value, Destination::COUNT) {
+            0 => Destination::Amplitude,
+            1 => Destination::VibratoDepth,
+            2 => Destination::LfoDepth,
+            step => Destination::Amount(NoiseSource::ALL[step - 3]),
+        }
+    }
+
+    fn to_parameter(self) -> f32 {
+        let step = match self {
+            Destination::Amplitude => 0,
+            Destination::VibratoDepth => 1,
+            Destination::LfoDepth => 2,
+            Destination::Amount(source) => 3 + source as usize,
+        };
+        step_to_parameter(step, Destination::COUNT)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Destination::Amplitude => "Amplitude",
+            Destination::VibratoDepth => "Vibrato depth",
+            Destination::LfoDepth => "LFO depth",
+            Destination::Amount(source) => source.name(),
+        }
+    }
+}
+
+/// The fastest the LFO can run, in hz.
+const LFO_MAX_RATE: f64 = 20.0;
+
+/// The widest the vibrato can swing either side of the played pitch, in cents.
+const VIBRATO_MAX_DEPTH: f64 = 100.0;
+
+/// The range of the ring modulator's carrier, in hz.
+const RING_MIN_FREQ: f64 = 20.0;
+const RING_MAX_FREQ: f64 = 5000.0;
+
+/// The choices for the `midi_channel` parameter: omni, then the sixteen midi channels.
+const MIDI_CHANNEL_STEPS: usize = 17;
+
+/// How many parameters the plugin has.
+const PARAMETER_COUNT: i32 = 132;
+
+/// The furthest a noise source's rate can be scaled either way from the voice's pitch.
+const MAX_NOISE_RATIO: f64 = 4.0;
+
+/// How much longer than the left the right echo line can be made to run, as a fraction of the
+/// delay time.
+const MAX_DELAY_SPREAD: f64 = 0.5;
+
+/// The longest the right side can be held back by for the Haas effect, in seconds. Any longer
+/// and it is heard as an echo rather than as width.
+const MAX_HAAS_DELAY: f64 = 0.03;
+
+/// The range of the output's safety ceiling, in dB, and where it starts, just over full scale
+/// so it never touches a healthy signal.
+const MIN_CEILING_DB: f32 = -24.0;
+const MAX_CEILING_DB: f32 = 24.0;
+const DEFAULT_CEILING_DB: f32 = 0.5;
+
+/// The most the side signal can be boosted by the `width` parameter.
+const MAX_WIDTH: f32 = 2.0;
+
+/// The high-pass's Q by default, which gives the flattest passband.
+const BUTTERWORTH_Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+/// The furthest, in cycles, cross-modulation can push the noise source it targets.
+const MAX_FM_DEPTH: f64 = 4.0;
+
+/// The choices for the `midi_learn` parameter: off, then every parameter in index order.
+const MIDI_LEARN_STEPS: usize = PARAMETER_COUNT as usize + 1;
+
+/// How many midi CCs there are to bind parameters to.
+const CONTROLLERS: usize = 128;
+
+/// The factory presets, each a name and the parameters it changes from their defaults.
+const PRESETS: [(&str, &[(ParamId, f32)]); 5] = [
+    ("Init", &[]),
+    // Long damping, with the top end dying away first.
+    ("Bright pluck", &[(ParamId::Damping, 0.99), (ParamId::DampingKeyTrack, 0.8)]),
+    // Perlin and fbm noise with the LFO slowly moving the perlin.
+    (
+        "Noisy drone",
+        &[
+            (ParamId::PerlinAmount, 0.3),
+            (ParamId::FbmAmount, 0.4),
+            (ParamId::LfoRate, 0.1),
+            (ParamId::LfoDepth, 0.3),
+            (ParamId::LfoTarget, 0.125),
+            (ParamId::Damping, 1.0),
+        ],
+    ),
+    // 6 bits, a quarter of the rate, and some drive.
+    ("Crushed", &[(ParamId::BitDepth, 0.22), (ParamId::Downsample, 0.1), (ParamId::Drive, 0.5)]),
+    // 750ms echoes in stereo.
+    (
+        "Echo space",
+        &[
+            (ParamId::DelayTime, 0.375),
+            (ParamId::DelayFeedback, 0.6),
+            (ParamId::DelayMix, 0.4),
+            (ParamId::OutputMode, 0.5),
+        ],
+    ),
+];
+
+/// The state worked out from the parameters once a block, only when one of them has changed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Derived {
+    /// The high-pass's coefficients.
+    Filter,
+    Gate,
+    Compressor,
+}
+
+impl Derived {
+    const ALL: [Derived; 3] = [Derived::Filter, Derived::Gate, Derived::Compressor];
+
+    /// This group's bit in `SynthParameters::dirty`.
+    fn bit(self) -> usize {
+        1 << self as usize
+    }
+}
+
+/// Every parameter, numbered as the host sees it. Hosts save automation and presets by these
+/// numbers, so a parameter keeps its number for good and new ones only go on the end.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ParamId {
+    DuplicateNotes = 0,
+    WhiteAmount = 1,
+    PerlinAmount = 2,
+    ValueAmount = 3,
+    WorleyAmount = 4,
+    OpenSimplexAmount = 5,
+    SuperSimplexAmount = 6,
+    BillowAmount = 7,
+    FbmAmount = 8,
+    RidgedMultiAmount = 9,
+    CylindersAmount = 10,
+    LfoRate = 11,
+    LfoDepth = 12,
+    LfoShape = 13,
+    LfoTarget = 14,
+    VibratoRate = 15,
+    VibratoDepth = 16,
+    ModWheelTarget = 17,
+    PressureTarget = 18,
+    MidiChannel = 19,
+    PinkAmount = 20,
+    BrownAmount = 21,
+    BitDepth = 22,
+    Downsample = 23,
+    DelayTime = 24,
+    DelayFeedback = 25,
+    DelayMix = 26,
+    Oversampling = 27,
+    Drive = 28,
+    Curve = 29,
+    RingMod = 30,
+    RingFreq = 31,
+    PeakLevel = 32,
+    RmsLevel = 33,
+    Panic = 34,
+    Damping = 35,
+    DampingKeyTrack = 36,
+    OutputMode = 37,
+    MidiLearn = 38,
+    KeyLow = 39,
+    KeyHigh = 40,
+    VelocityCurve = 41,
+    Attack = 42,
+    Retrigger = 43,
+    LfoSync = 44,
+    DelaySync = 45,
+    VoiceOffset = 46,
+    Noise3d = 47,
+    ChorusRate = 48,
+    ChorusDepth = 49,
+    ChorusMix = 50,
+    WhiteRate = 51,
+    PerlinRate = 52,
+    ValueRate = 53,
+    WorleyRate = 54,
+    OpenSimplexRate = 55,
+    SuperSimplexRate = 56,
+    BillowRate = 57,
+    FbmRate = 58,
+    RidgedMultiRate = 59,
+    CylindersRate = 60,
+    PinkRate = 61,
+    BrownRate = 62,
+    TremoloRate = 63,
+    TremoloDepth = 64,
+    TremoloShape = 65,
+    FmSource = 66,
+    FmTarget = 67,
+    FmAmount = 68,
+    Bypass = 69,
+    SourceMode = 70,
+    SelectedSource = 71,
+    Normalize = 72,
+    Freeze = 73,
+    Width = 74,
+    HpCutoff = 75,
+    HpResonance = 76,
+    AttackCurve = 77,
+    ReleaseCurve = 78,
+    Humanize = 79,
+    ReverbSize = 80,
+    ReverbDamp = 81,
+    ReverbMix = 82,
+    GateThreshold = 83,
+    GateAttack = 84,
+    GateRelease = 85,
+    DelaySpread = 86,
+    Ceiling = 87,
+    ScaleSnap = 88,
+    ExciteLength = 89,
+    RelVelAmount = 90,
+    Transpose = 91,
+    GlobalMix = 92,
+    Drift = 93,
+    TuningMode = 94,
+    HaasDelay = 95,
+    ArpOn = 96,
+    ArpRate = 97,
+    ArpMode = 98,
+    ArpOctaves = 99,
+    VelToTimbre = 100,
+    DelayPingPong = 101,
+    AutoGain = 102,
+    OneShot = 103,
+    NoiseSmoothing = 104,
+    WhiteOn = 105,
+    PerlinOn = 106,
+    ValueOn = 107,
+    WorleyOn = 108,
+    OpenSimplexOn = 109,
+    SuperSimplexOn = 110,
+    BillowOn = 111,
+    FbmOn = 112,
+    RidgedMultiOn = 113,
+    CylindersOn = 114,
+    PinkOn = 115,
+    BrownOn = 116,
+    Exciter = 117,
+    VelToBrightness = 118,
+    InvertLeft = 119,
+    InvertRight = 120,
+    Drone = 121,
+    KeyboardPan = 122,
+    Mpe = 123,
+    CompThreshold = 124,
+    CompRatio = 125,
+    CompAttack = 126,
+    CompRelease = 127,
+    CompMakeup = 128,
+    StringLevel = 129,
+    NoiseLevel = 130,
+    DecayTime = 131,
+}
+
+impl ParamId {
+    /// Every parameter in number order.
+    const ALL: [ParamId; PARAMETER_COUNT as usize] = [
+        ParamId::DuplicateNotes,
+        ParamId::WhiteAmount,
+        ParamId::PerlinAmount,
+        ParamId::ValueAmount,
+        ParamId::WorleyAmount,
+        ParamId::OpenSimplexAmount,
+        ParamId::SuperSimplexAmount,
+        ParamId::BillowAmount,
+        ParamId::FbmAmount,
+        ParamId::RidgedMultiAmount,
+        ParamId::CylindersAmount,
+        ParamId::LfoRate,
+        ParamId::LfoDepth,
+        ParamId::LfoShape,
+        ParamId::LfoTarget,
+        ParamId::VibratoRate,
+        ParamId::VibratoDepth,
+        ParamId::ModWheelTarget,
+        ParamId::PressureTarget,
+        ParamId::MidiChannel,
+        ParamId::PinkAmount,
+        ParamId::BrownAmount,
+        ParamId::BitDepth,
+        ParamId::Downsample,
+        ParamId::DelayTime,
+        ParamId::DelayFeedback,
+        ParamId::DelayMix,
+        ParamId::Oversampling,
+        ParamId::Drive,
+        ParamId::Curve,
+        ParamId::RingMod,
+        ParamId::RingFreq,
+        ParamId::PeakLevel,
+        ParamId::RmsLevel,
+        ParamId::Panic,
+        ParamId::Damping,
+        ParamId::DampingKeyTrack,
+        ParamId::OutputMode,
+        ParamId::MidiLearn,
+        ParamId::KeyLow,
+        ParamId::KeyHigh,
+        ParamId::VelocityCurve,
+        ParamId::Attack,
+        ParamId::Retrigger,
+        ParamId::LfoSync,
+        ParamId::DelaySync,
+        ParamId::VoiceOffset,
+        ParamId::Noise3d,
+        ParamId::ChorusRate,
+        ParamId::ChorusDepth,
+        ParamId::ChorusMix,
+        ParamId::WhiteRate,
+        ParamId::PerlinRate,
+        ParamId::ValueRate,
+        ParamId::WorleyRate,
+        ParamId::OpenSimplexRate,
+        ParamId::SuperSimplexRate,
+        ParamId::BillowRate,
+        ParamId::FbmRate,
+        ParamId::RidgedMultiRate,
+        ParamId::CylindersRate,
+        ParamId::PinkRate,
+        ParamId::BrownRate,
+        ParamId::TremoloRate,
+        ParamId::TremoloDepth,
+        ParamId::TremoloShape,
+        ParamId::FmSource,
+        ParamId::FmTarget,
+        ParamId::FmAmount,
+        ParamId::Bypass,
+        ParamId::SourceMode,
+        ParamId::SelectedSource,
+        ParamId::Normalize,
+        ParamId::Freeze,
+        ParamId::Width,
+        ParamId::HpCutoff,
+        ParamId::HpResonance,
+        ParamId::AttackCurve,
+        ParamId::ReleaseCurve,
+        ParamId::Humanize,
+        ParamId::ReverbSize,
+        ParamId::ReverbDamp,
+        ParamId::ReverbMix,
+        ParamId::GateThreshold,
+        ParamId::GateAttack,
+        ParamId::GateRelease,
+        ParamId::DelaySpread,
+        ParamId::Ceiling,
+        ParamId::ScaleSnap,
+        ParamId::ExciteLength,
+        ParamId::RelVelAmount,
+        ParamId::Transpose,
+        ParamId::GlobalMix,
+        ParamId::Drift,
+        ParamId::TuningMode,
+        ParamId::HaasDelay,
+        ParamId::ArpOn,
+        ParamId::ArpRate,
+        ParamId::ArpMode,
+        ParamId::ArpOctaves,
+        ParamId::VelToTimbre,
+        ParamId::DelayPingPong,
+        ParamId::AutoGain,
+        ParamId::OneShot,
+        ParamId::NoiseSmoothing,
+        ParamId::WhiteOn,
+        ParamId::PerlinOn,
+        ParamId::ValueOn,
+        ParamId::WorleyOn,
+        ParamId::OpenSimplexOn,
+        ParamId::SuperSimplexOn,
+        ParamId::BillowOn,
+        ParamId::FbmOn,
+        ParamId::RidgedMultiOn,
+        ParamId::CylindersOn,
+        ParamId::PinkOn,
+        ParamId::BrownOn,
+        ParamId::Exciter,
+        ParamId::VelToBrightness,
+        ParamId::InvertLeft,
+        ParamId::InvertRight,
+        ParamId::Drone,
+        ParamId::KeyboardPan,
+        ParamId::Mpe,
+        ParamId::CompThreshold,
+        ParamId::CompRatio,
+        ParamId::CompAttack,
+        ParamId::CompRelease,
+        ParamId::CompMakeup,
+        ParamId::StringLevel,
+        ParamId::NoiseLevel,
+        ParamId::DecayTime,
+    ];
+
+    /// The parameter for each noise source's amount, indexed by `NoiseSource`.
+    const AMOUNTS: [ParamId; NOISE_SOURCES] = [
+        ParamId::WhiteAmount,
+        ParamId::PerlinAmount,
+        ParamId::ValueAmount,
+        ParamId::WorleyAmount,
+        ParamId::OpenSimplexAmount,
+        ParamId::SuperSimplexAmount,
+        ParamId::BillowAmount,
+        ParamId::FbmAmount,
+        ParamId::RidgedMultiAmount,
+        ParamId::CylindersAmount,
+        ParamId::PinkAmount,
+        ParamId::BrownAmount,
+    ];
+
+    /// The parameter for each noise source's rate, indexed by `NoiseSource`.
+    const RATES: [ParamId; NOISE_SOURCES] = [
+        ParamId::WhiteRate,
+        ParamId::PerlinRate,
+        ParamId::ValueRate,
+        ParamId::WorleyRate,
+        ParamId::OpenSimplexRate,
+        ParamId::SuperSimplexRate,
+        ParamId::BillowRate,
+        ParamId::FbmRate,
+        ParamId::RidgedMultiRate,
+        ParamId::CylindersRate,
+        ParamId::PinkRate,
+        ParamId::BrownRate,
+    ];
+
+    /// The parameter for whether each noise source is on at all, indexed by `NoiseSource`.
+    const ENABLES: [ParamId; NOISE_SOURCES] = [
+        ParamId::WhiteOn,
+        ParamId::PerlinOn,
+        ParamId::ValueOn,
+        ParamId::WorleyOn,
+        ParamId::OpenSimplexOn,
+        ParamId::SuperSimplexOn,
+        ParamId::BillowOn,
+        ParamId::FbmOn,
+        ParamId::RidgedMultiOn,
+        ParamId::CylindersOn,
+        ParamId::PinkOn,
+        ParamId::BrownOn,
+    ];
+
+    /// The noise source this is the amount of, if it is one of `AMOUNTS`.
+    fn amount(self) -> Option<NoiseSource> {
+        ParamId::AMOUNTS.iter().position(|&id| id == self).map(|i| NoiseSource::ALL[i])
+    }
+
+    /// The noise source this is the rate of, if it is one of `RATES`.
+    fn rate(self) -> Option<NoiseSource> {
+        ParamId::RATES.iter().position(|&id| id == self).map(|i| NoiseSource::ALL[i])
+    }
+
+    /// The noise source this turns on and off, if it is one of `ENABLES`.
+    fn enable(self) -> Option<NoiseSource> {
+        ParamId::ENABLES.iter().position(|&id| id == self).map(|i| NoiseSource::ALL[i])
+    }
+
+    /// Whether the parameter picks one of a set of choices, with nothing in between them.
+    /// Stepped numbers, like the bit depth or the transpose, are not switches.
+    fn is_switch(self) -> bool {
+        if self.enable().is_some() {
+            return true;
+        }
+        matches!(
+            self,
+            ParamId::DuplicateNotes
+                | ParamId::LfoShape
+                | ParamId::LfoTarget
+                | ParamId::ModWheelTarget
+                | ParamId::PressureTarget
+                | ParamId::MidiChannel
+                | ParamId::Oversampling
+                | ParamId::Curve
+                | ParamId::Panic
+                | ParamId::OutputMode
+                | ParamId::MidiLearn
+                | ParamId::VelocityCurve
+                | ParamId::Retrigger
+                | ParamId::LfoSync
+                | ParamId::DelaySync
+                | ParamId::VoiceOffset
+                | ParamId::Noise3d
+                | ParamId::TremoloShape
+                | ParamId::FmSource
+                | ParamId::FmTarget
+                | ParamId::Bypass
+                | ParamId::SourceMode
+                | ParamId::SelectedSource
+                | ParamId::Normalize
+                | ParamId::Freeze
+                | ParamId::AttackCurve
+                | ParamId::ReleaseCurve
+                | ParamId::ScaleSnap
+                | ParamId::TuningMode
+                | ParamId::ArpOn
+                | ParamId::ArpRate
+                | ParamId::ArpMode
+                | ParamId::DelayPingPong
+                | ParamId::OneShot
+                | ParamId::NoiseSmoothing
+                | ParamId::Exciter
+                | ParamId::InvertLeft
+                | ParamId::InvertRight
+                | ParamId::Drone
+                | ParamId::Mpe
+        )
+    }
+
+    /// Whether the parameter is part of the sound, and so something a preset sets. The meters
+    /// are measured, and the panic switch, MIDI learn, bypass and freeze are for playing the
+    /// plugin, so a change of program leaves them where they are.
+    fn is_setting(self) -> bool {
+        !matches!(
+            self,
+            ParamId::PeakLevel
+                | ParamId::RmsLevel
+                | ParamId::Panic
+                | ParamId::MidiLearn
+                | ParamId::Bypass
+                | ParamId::Freeze
+        )
+    }
+
+    /// The state to work out again when the parameter changes, if any is worked out from it.
+    fn derived(self) -> Option<Derived> {
+        match self {
+            ParamId::HpCutoff | ParamId::HpResonance => Some(Derived::Filter),
+            ParamId::GateThreshold | ParamId::GateAttack | ParamId::GateRelease => {
+                Some(Derived::Gate)
+            }
+            ParamId::CompThreshold
+            | ParamId::CompRatio
+            | ParamId::CompAttack
+            | ParamId::CompRelease
+            | ParamId::CompMakeup => Some(Derived::Compressor),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<i32> for ParamId {
+    type Error = ();
+
+    fn try_from(index: i32) -> Result<ParamId, ()> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| ParamId::ALL.get(index))
+            .copied()
+            .ok_or(())
+    }
+}
+
+/// The value of every setting, as the host sees it, from `0.0` to `1.0`.
+///
+/// This is for building a synth with particular settings by name instead of going through
+/// `set_parameter` by index. The meters, the panic switch and MIDI learn are not settings and
+/// are left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SynthConfig {
+    pub duplicate_notes: f32,
+    pub amounts: [f32; NOISE_SOURCES],
+    pub lfo_rate: f32,
+    pub lfo_depth: f32,
+    pub lfo_shape: f32,
+    pub lfo_target: f32,
+    pub vibrato_rate: f32,
+    pub vibrato_depth: f32,
+    pub mod_wheel_target: f32,
+    pub pressure_target: f32,
+    pub midi_channel: f32,
+    pub bit_depth: f32,
+    pub downsample: f32,
+    pub delay_time: f32,
+    pub delay_feedback: f32,
+    pub delay_mix: f32,
+    pub oversampling: f32,
+    pub drive: f32,
+    pub curve: f32,
+    pub ring_mod: f32,
+    pub ring_freq: f32,
+    pub damping: f32,
+    pub damping_key_track: f32,
+    pub output_mode: f32,
+    pub key_low: f32,
+    pub key_high: f32,
+    pub velocity_curve: f32,
+    pub attack: f32,
+    pub retrigger: f32,
+    pub lfo_sync: f32,
+    pub delay_sync: f32,
+    pub voice_offset: f32,
+    pub noise_3d: f32,
+    pub chorus_rate: f32,
+    pub chorus_depth: f32,
+    pub chorus_mix: f32,
+    pub ratios: [f32; NOISE_SOURCES],
+    pub enabled: [f32; NOISE_SOURCES],
+    pub tremolo_rate: f32,
+    pub tremolo_depth: f32,
+    pub tremolo_shape: f32,
+    pub fm_source: f32,
+    pub fm_target: f32,
+    pub fm_amount: f32,
+    pub bypass: f32,
+    pub source_mode: f32,
+    pub selected_source: f32,
+    pub normalize: f32,
+    pub freeze: f32,
+    pub width: f32,
+    pub hp_cutoff: f32,
+    pub hp_resonance: f32,
+    pub attack_curve: f32,
+    pub release_curve: f32,
+    pub humanize: f32,
+    pub reverb_size: f32,
+    pub reverb_damp: f32,
+    pub reverb_mix: f32,
+    pub gate_threshold: f32,
+    pub gate_attack: f32,
+    pub gate_release: f32,
+    pub delay_spread: f32,
+    pub ceiling: f32,
+    pub scale_snap: f32,
+    pub excite_length: f32,
+    pub rel_vel_amount: f32,
+    pub transpose: f32,
+    pub global_mix: f32,
+    pub drift: f32,
+    pub tuning_mode: f32,
+    pub haas_delay: f32,
+    pub arp_on: f32,
+    pub arp_rate: f32,
+    pub arp_mode: f32,
+    pub arp_octaves: f32,
+    pub vel_to_timbre: f32,
+    pub delay_pingpong: f32,
+    pub auto_gain: f32,
+    pub one_shot: f32,
+    pub noise_smoothing: f32,
+    pub exciter: f32,
+    pub vel_to_brightness: f32,
+    pub invert_left: f32,
+    pub invert_right: f32,
+    pub drone: f32,
+    pub keyboard_pan: f32,
+    pub mpe: f32,
+    pub comp_threshold: f32,
+    pub comp_ratio: f32,
+    pub comp_attack: f32,
+    pub comp_release: f32,
+    pub comp_makeup: f32,
+    pub string_level: f32,
+    pub noise_level: f32,
+    pub decay_time: f32,
+}
+
+impl Default for SynthConfig {
+    fn default() -> SynthConfig {
+        SynthParameters::default().config()
+    }
+}
+
+/// The plugin's automatable parameters, shared with the host.
+pub struct SynthParameters {
+    duplicate_notes: AtomicFloat,
+    /// How much of each noise source, indexed by `NoiseSource`, is mixed into every voice.
+    amounts: [AtomicFloat; NOISE_SOURCES],
+    lfo_rate: AtomicFloat,
+    lfo_depth: AtomicFloat,
+    lfo_shape: AtomicFloat,
+    /// Which noise amount the LFO modulates.
+    lfo_target: AtomicFloat,
+    vibrato_rate: AtomicFloat,
+    vibrato_depth: AtomicFloat,
+    /// Where the mod wheel is routed.
+    mod_wheel_target: AtomicFloat,
+    /// Where channel and poly aftertouch are routed.
+    pressure_target: AtomicFloat,
+    /// The midi channel to listen on, where the lowest step is omni.
+    midi_channel: AtomicFloat,
+    bit_depth: AtomicFloat,
+    downsample: AtomicFloat,
+    delay_time: AtomicFloat,
+    delay_feedback: AtomicFloat,
+    /// How loud the echoes are against the dry signal.
+    delay_mix: AtomicFloat,
+    oversampling: AtomicFloat,
+    drive: AtomicFloat,
+    curve: AtomicFloat,
+    /// How much of the signal is replaced by its ring modulated copy.
+    ring_mod: AtomicFloat,
+    ring_freq: AtomicFloat,
+    /// The output's peak and RMS level over the last block, written by `process` for display
+    /// and reported to the host as read-only parameters.
+    peak_level: AtomicFloat,
+    rms_level: AtomicFloat,
+    /// A momentary switch: set it above `0.5` to silence everything, after which it turns
+    /// itself back off.
+    panic: AtomicFloat,
+    damping: AtomicFloat,
+    /// How much higher notes are damped than lower ones, where the middle is not at all.
+    damping_key_track: AtomicFloat,
+    output_mode: AtomicFloat,
+    /// The parameter to bind to the next CC that comes in, where the lowest step is none.
+    /// It turns itself back off once the CC is bound.
+    midi_learn: AtomicFloat,
+    /// The lowest and highest notes that start a voice.
+    key_low: AtomicFloat,
+    key_high: AtomicFloat,
+    velocity_curve: AtomicFloat,
+    attack: AtomicFloat,
+    /// Above `0.5`, a new strike restarts the attack from silence. Below it, the new voice
+    /// carries on from the level of the one it takes over.
+    retrigger: AtomicFloat,
+    /// The note divisions the LFO cycle and the echo time are locked to, where the lowest step
+    /// leaves them free running.
+    lfo_sync: AtomicFloat,
+    delay_sync: AtomicFloat,
+    /// Above `0.5`, each voice starts from its own random point in the coherent noise sources.
+    /// Below it, every voice reads them from the same place, so the output is repeatable.
+    voice_offset: AtomicFloat,
+    /// Above `0.5`, the coherent noise sources are read in three dimensions, with the third
+    /// drifting over the life of each voice so the texture never settles into a loop.
+    noise_3d: AtomicFloat,
+    /// The chorus sweep, with `chorus_mix` blending from dry at `0.0` to only the swept copy at
+    /// `1.0`.
+    chorus_rate: AtomicFloat,
+    chorus_depth: AtomicFloat,
+    chorus_mix: AtomicFloat,
+    /// How fast each coherent noise source, indexed by `NoiseSource`, is read relative to the
+    /// voice's pitch. The stochastic sources have no coordinate to scale and ignore it.
+    ratios: [AtomicFloat; NOISE_SOURCES],
+    /// Above `0.5`, each noise source, indexed by `NoiseSource`, is heard. Below it the source
+    /// is not read at all and any state it keeps is left where it is, whatever its amount.
+    enabled: [AtomicFloat; NOISE_SOURCES],
+    /// The tremolo, which dips the output level by up to `tremolo_depth` once a cycle.
+    tremolo_rate: AtomicFloat,
+    tremolo_depth: AtomicFloat,
+    tremolo_shape: AtomicFloat,
+    /// Cross-modulation, where `fm_source` is added by up to `MAX_FM_DEPTH` cycles, scaled by
+    /// `fm_amount`, to the coordinate `fm_target` is read at. The source need not be audible
+    /// itself.
+    fm_source: AtomicFloat,
+    fm_target: AtomicFloat,
+    fm_amount: AtomicFloat,
+    /// Above `0.5`, the inputs are passed straight through to the outputs and nothing is rendered.
+    bypass: AtomicFloat,
+    /// Whether the noise sources are blended by their amounts, or only `selected_source` is
+    /// heard.
+    source_mode: AtomicFloat,
+    selected_source: AtomicFloat,
+    /// Above `0.5`, the amounts are weights, scaled to add up to `1.0` so the overall level holds
+    /// steady as the blend changes.
+    normalize: AtomicFloat,
+    /// Above `0.5`, the noise texture is held where it is: the voices stop moving through the
+    /// coherent sources, the filtered sources stop changing, and the LFO and vibrato stop. The
+    /// strings and envelopes carry on.
+    freeze: AtomicFloat,
+    /// How wide the stereo image is, from mono at `0.0` through as rendered at `0.5` to twice as
+    /// wide at `1.0`.
+    width: AtomicFloat,
+    /// The high-pass on each channel, which is off with the cutoff all the way down.
+    hp_cutoff: AtomicFloat,
+    hp_resonance: AtomicFloat,
+    attack_curve: AtomicFloat,
+    release_curve: AtomicFloat,
+    humanize: AtomicFloat,
+    /// The reverb send, with `reverb_size` and `reverb_damp` setting how long it rings and how
+    /// dark it gets as it does.
+    reverb_size: AtomicFloat,
+    reverb_damp: AtomicFloat,
+    reverb_mix: AtomicFloat,
+    /// The output gate, which shuts once the level falls under `gate_threshold`, and opens and
+    /// closes over `gate_attack` and `gate_release`.
+    gate_threshold: AtomicFloat,
+    gate_attack: AtomicFloat,
+    gate_release: AtomicFloat,
+    /// How much longer the right echo line runs than the left, so the echoes come apart even from
+    /// a mono source.
+    delay_spread: AtomicFloat,
+    /// The hard limit on the output level, a safety net against runaway feedback rather than a
+    /// clipper to be driven.
+    ceiling: AtomicFloat,
+    scale_snap: AtomicFloat,
+    excite_length: AtomicFloat,
+    rel_vel_amount: AtomicFloat,
+    transpose: AtomicFloat,
+    /// The blend from the voices alone at `0.0` to the voices through every effect at `1.0`.
+    global_mix: AtomicFloat,
+    /// How far each held voice wanders about its pitch, slowly and at random, as an analog
+    /// oscillator would.
+    drift: AtomicFloat,
+    tuning_mode: AtomicFloat,
+    /// How far the right side is held back behind the left, for width out of a mono output.
+    haas_delay: AtomicFloat,
+    /// The arpeggiator, which plays the keys held down one at a time at `arp_rate` rather than
+    /// all at once, in the order `arp_mode` picks and up through `arp_octaves` octaves.
+    arp_on: AtomicFloat,
+    arp_rate: AtomicFloat,
+    arp_mode: AtomicFloat,
+    arp_octaves: AtomicFloat,
+    /// How far harder notes lean on the brighter noise sources in the blend, and softer notes on
+    /// the darker ones.
+    vel_to_timbre: AtomicFloat,
+    /// Whether the echoes bounce from side to side, each line feeding the other, rather than
+    /// each side echoing itself.
+    delay_pingpong: AtomicFloat,
+    /// How far the voices are turned down as more of them sound, from not at all to by the
+    /// square root of how many there are, so chords sit as loud as single notes.
+    auto_gain: AtomicFloat,
+    /// Whether each voice lets itself go as soon as its attack is done, however long its key is
+    /// held, for hits. Note-offs are ignored.
+    one_shot: AtomicFloat,
+    /// Above `0.5`, the stepped noise sources are low-passed a few harmonics above each voice's
+    /// pitch, so a low note does not step audibly from one lattice point to the next.
+    noise_smoothing: AtomicFloat,
+    /// Above `0.5`, the strings are plucked silent and the audio input is fed into them instead,
+    /// so each held note rings with whatever is coming in at its pitch.
+    exciter: AtomicFloat,
+    /// How much duller a softer strike plucks the string, `0.0..=1.0`.
+    vel_to_brightness: AtomicFloat,
+    /// Whether each side of the output has its polarity flipped.
+    invert_left: AtomicFloat,
+    invert_right: AtomicFloat,
+    /// Whether voices are held at full level through their note-offs, until all sound is cut
+    /// off.
+    drone: AtomicFloat,
+    /// How far across the stereo field the voices are spread by their keys, lowest on the left,
+    /// `0.0..=1.0`. Only the stereo output mode is panned.
+    keyboard_pan: AtomicFloat,
+    /// Whether a pitch bend only bends the voices struck on its own channel, as MPE
+    /// controllers send them, one note to a channel. It wants the midi channel left on omni.
+    mpe: AtomicFloat,
+    /// The output compressor, which turns the level over `comp_threshold` down by `comp_ratio`,
+    /// clamping down and letting go over `comp_attack` and `comp_release`, then makes it back up
+    /// by `comp_makeup`.
+    comp_threshold: AtomicFloat,
+    comp_ratio: AtomicFloat,
+    comp_attack: AtomicFloat,
+    comp_release: AtomicFloat,
+    comp_makeup: AtomicFloat,
+    /// The level of each of the voices' two layers, `0.0..=1.0`: the plucked string, and the
+    /// noise sources blended in around it.
+    string_level: AtomicFloat,
+    noise_level: AtomicFloat,
+    /// How long the strings take to fall 60 dB, in place of `damping`, so they ring as long in
+    /// seconds at any pitch and sample rate. At `0.0` it is off and `damping` is used.
+    decay_time: AtomicFloat,
+    /// The parameter each CC has been bound to by `midi_learn`, or `-1`. These are saved with
+    /// the preset.
+    cc_bindings: Vec<AtomicIsize>,
+    /// The last factory preset chosen.
+    preset: AtomicIsize,
+    /// A bit for each `Derived` group whose parameters have been set since `process` last
+    /// worked it out.
+    dirty: AtomicUsize,
+    /// The last sample `process` wrote to the left and right outputs, for a scope to poll.
+    last_output: [AtomicFloat; 2],
+    /// Every parameter's default value, for presets to start from.
+    defaults: Vec<f32>,
+}
+
+impl SynthParameters {
+    /// The parameters set as in `config`, with everything else at its default.
+    ///
+    /// Each is set through `set_parameter`, so a config is held to the same ranges as the host.
+    pub fn with_config(config: &SynthConfig) -> SynthParameters {
+        let params = SynthParameters::default();
+        let set = |id: ParamId, value: f32| params.set_parameter(id as i32, value);
+        set(ParamId::DuplicateNotes, config.duplicate_notes);
+        for (&id, &value) in ParamId::AMOUNTS.iter().zip(config.amounts.iter()) {
+            set(id, value);
         }
+        set(ParamId::LfoRate, config.lfo_rate);
+        set(ParamId::LfoDepth, config.lfo_depth);
+        set(ParamId::LfoShape, config.lfo_shape);
+        set(ParamId::LfoTarget, config.lfo_target);
+        set(ParamId::VibratoRate, config.vibrato_rate);
+        set(ParamId::VibratoDepth, config.vibrato_depth);
+        set(ParamId::ModWheelTarget, config.mod_wheel_target);
+        set(ParamId::PressureTarget, config.pressure_target);
+        set(ParamId::MidiChannel, config.midi_channel);
+        set(ParamId::BitDepth, config.bit_depth);
+        set(ParamId::Downsample, config.downsample);
+        set(ParamId::DelayTime, config.delay_time);
+        set(ParamId::DelayFeedback, config.delay_feedback);
+        set(ParamId::DelayMix, config.delay_mix);
+        set(ParamId::Oversampling, config.oversampling);
+        set(ParamId::Drive, config.drive);
+        set(ParamId::Curve, config.curve);
+        set(ParamId::RingMod, config.ring_mod);
+        set(ParamId::RingFreq, config.ring_freq);
+        set(ParamId::Damping, config.damping);
+        set(ParamId::DampingKeyTrack, config.damping_key_track);
+        set(ParamId::OutputMode, config.output_mode);
+        set(ParamId::KeyLow, config.key_low);
+        set(ParamId::KeyHigh, config.key_high);
+        set(ParamId::VelocityCurve, config.velocity_curve);
+        set(ParamId::Attack, config.attack);
+        set(ParamId::Retrigger, config.retrigger);
+        set(ParamId::LfoSync, config.lfo_sync);
+        set(ParamId::DelaySync, config.delay_sync);
+        set(ParamId::VoiceOffset, config.voice_offset);
+        set(ParamId::Noise3d, config.noise_3d);
+        set(ParamId::ChorusRate, config.chorus_rate);
+        set(ParamId::ChorusDepth, config.chorus_depth);
+        set(ParamId::ChorusMix, config.chorus_mix);
+        for (&id, &value) in ParamId::RATES.iter().zip(config.ratios.iter()) {
+            set(id, value);
+        }
+        for (&id, &value) in ParamId::ENABLES.iter().zip(config.enabled.iter()) {
+            set(id, value);
+        }
+        set(ParamId::TremoloRate, config.tremolo_rate);
+        set(ParamId::TremoloDepth, config.tremolo_depth);
+        set(ParamId::TremoloShape, config.tremolo_shape);
+        set(ParamId::FmSource, config.fm_source);
+        set(ParamId::FmTarget, config.fm_target);
+        set(ParamId::FmAmount, config.fm_amount);
+        set(ParamId::Bypass, config.bypass);
+        set(ParamId::SourceMode, config.source_mode);
+        set(ParamId::SelectedSource, config.selected_source);
+        set(ParamId::Normalize, config.normalize);
+        set(ParamId::Freeze, config.freeze);
+        set(ParamId::Width, config.width);
+        set(ParamId::HpCutoff, config.hp_cutoff);
+        set(ParamId::HpResonance, config.hp_resonance);
+        set(ParamId::AttackCurve, config.attack_curve);
+        set(ParamId::ReleaseCurve, config.release_curve);
+        set(ParamId::Humanize, config.humanize);
+        set(ParamId::ReverbSize, config.reverb_size);
+        set(ParamId::ReverbDamp, config.reverb_damp);
+        set(ParamId::ReverbMix, config.reverb_mix);
+        set(ParamId::GateThreshold, config.gate_threshold);
+        set(ParamId::GateAttack, config.gate_attack);
+        set(ParamId::GateRelease, config.gate_release);
+        set(ParamId::DelaySpread, config.delay_spread);
+        set(ParamId::Ceiling, config.ceiling);
+        set(ParamId::ScaleSnap, config.scale_snap);
+        set(ParamId::ExciteLength, config.excite_length);
+        set(ParamId::RelVelAmount, config.rel_vel_amount);
+        set(ParamId::Transpose, config.transpose);
+        set(ParamId::GlobalMix, config.global_mix);
+        set(ParamId::Drift, config.drift);
+        set(ParamId::TuningMode, config.tuning_mode);
+        set(ParamId::HaasDelay, config.haas_delay);
+        set(ParamId::ArpOn, config.arp_on);
+        set(ParamId::ArpRate, config.arp_rate);
+        set(ParamId::ArpMode, config.arp_mode);
+        set(ParamId::ArpOctaves, config.arp_octaves);
+        set(ParamId::VelToTimbre, config.vel_to_timbre);
+        set(ParamId::DelayPingPong, config.delay_pingpong);
+        set(ParamId::AutoGain, config.auto_gain);
+        set(ParamId::OneShot, config.one_shot);
+        set(ParamId::NoiseSmoothing, config.noise_smoothing);
+        set(ParamId::Exciter, config.exciter);
+        set(ParamId::VelToBrightness, config.vel_to_brightness);
+        set(ParamId::InvertLeft, config.invert_left);
+        set(ParamId::InvertRight, config.invert_right);
+        set(ParamId::Drone, config.drone);
+        set(ParamId::KeyboardPan, config.keyboard_pan);
+        set(ParamId::Mpe, config.mpe);
+        set(ParamId::CompThreshold, config.comp_threshold);
+        set(ParamId::CompRatio, config.comp_ratio);
+        set(ParamId::CompAttack, config.comp_attack);
+        set(ParamId::CompRelease, config.comp_release);
+        set(ParamId::CompMakeup, config.comp_makeup);
+        set(ParamId::StringLevel, config.string_level);
+        set(ParamId::NoiseLevel, config.noise_level);
+        set(ParamId::DecayTime, config.decay_time);
+        params
+    }
+
+    /// The current value of every setting.
+    pub fn config(&self) -> SynthConfig {
+        SynthConfig {
+            duplicate_notes: self.duplicate_notes.get(),
+            amounts: [
+                self.amounts[0].get(),
+                self.amounts[1].get(),
+                self.amounts[2].get(),
+                self.amounts[3].get(),
+                self.amounts[4].get(),
+                self.amounts[5].get(),
+                self.amounts[6].get(),
+                self.amounts[7].get(),
+                self.amounts[8].get(),
+                self.amounts[9].get(),
+                self.amounts[10].get(),
+                self.amounts[11].get(),
+            ],
+            lfo_rate: self.lfo_rate.get(),
+            lfo_depth: self.lfo_depth.get(),
+            lfo_shape: self.lfo_shape.get(),
+            lfo_target: self.lfo_target.get(),
+            vibrato_rate: self.vibrato_rate.get(),
+            vibrato_depth: self.vibrato_depth.get(),
+            mod_wheel_target: self.mod_wheel_target.get(),
+            pressure_target: self.pressure_target.get(),
+            midi_channel: self.midi_channel.get(),
+            bit_depth: self.bit_depth.get(),
+            downsample: self.downsample.get(),
+            delay_time: self.delay_time.get(),
+            delay_feedback: self.delay_feedback.get(),
+            delay_mix: self.delay_mix.get(),
+            oversampling: self.oversampling.get(),
+            drive: self.drive.get(),
+            curve: self.curve.get(),
+            ring_mod: self.ring_mod.get(),
+            ring_freq: self.ring_freq.get(),
+            damping: self.damping.get(),
+            damping_key_track: self.damping_key_track.get(),
+            output_mode: self.output_mode.get(),
+            key_low: self.key_low.get(),
+            key_high: self.key_high.get(),
+            velocity_curve: self.velocity_curve.get(),
+            attack: self.attack.get(),
+            retrigger: self.retrigger.get(),
+            lfo_sync: self.lfo_sync.get(),
+            delay_sync: self.delay_sync.get(),
+            voice_offset: self.voice_offset.get(),
+            noise_3d: self.noise_3d.get(),
+            chorus_rate: self.chorus_rate.get(),
+            chorus_depth: self.chorus_depth.get(),
+            chorus_mix: self.chorus_mix.get(),
+            ratios: [
+                self.ratios[0].get(),
+                self.ratios[1].get(),
+                self.ratios[2].get(),
+                self.ratios[3].get(),
+                self.ratios[4].get(),
+                self.ratios[5].get(),
+                self.ratios[6].get(),
+                self.ratios[7].get(),
+                self.ratios[8].get(),
+                self.ratios[9].get(),
+                self.ratios[10].get(),
+                self.ratios[11].get(),
+            ],
+            enabled: [
+                self.enabled[0].get(),
+                self.enabled[1].get(),
+                self.enabled[2].get(),
+                self.enabled[3].get(),
+                self.enabled[4].get(),
+                self.enabled[5].get(),
+                self.enabled[6].get(),
+                self.enabled[7].get(),
+                self.enabled[8].get(),
+                self.enabled[9].get(),
+                self.enabled[10].get(),
+                self.enabled[11].get(),
+            ],
+            tremolo_rate: self.tremolo_rate.get(),
+            tremolo_depth: self.tremolo_depth.get(),
+            tremolo_shape: self.tremolo_shape.get(),
+            fm_source: self.fm_source.get(),
+            fm_target: self.fm_target.get(),
+            fm_amount: self.fm_amount.get(),
+            bypass: self.bypass.get(),
+            source_mode: self.source_mode.get(),
+            selected_source: self.selected_source.get(),
+            normalize: self.normalize.get(),
+            freeze: self.freeze.get(),
+            width: self.width.get(),
+            hp_cutoff: self.hp_cutoff.get(),
+            hp_resonance: self.hp_resonance.get(),
+            attack_curve: self.attack_curve.get(),
+            release_curve: self.release_curve.get(),
+            humanize: self.humanize.get(),
+            reverb_size: self.reverb_size.get(),
+            reverb_damp: self.reverb_damp.get(),
+            reverb_mix: self.reverb_mix.get(),
+            gate_threshold: self.gate_threshold.get(),
+            gate_attack: self.gate_attack.get(),
+            gate_release: self.gate_release.get(),
+            delay_spread: self.delay_spread.get(),
+            ceiling: self.ceiling.get(),
+            scale_snap: self.scale_snap.get(),
+            excite_length: self.excite_length.get(),
+            rel_vel_amount: self.rel_vel_amount.get(),
+            transpose: self.transpose.get(),
+            global_mix: self.global_mix.get(),
+            drift: self.drift.get(),
+            tuning_mode: self.tuning_mode.get(),
+            haas_delay: self.haas_delay.get(),
+            arp_on: self.arp_on.get(),
+            arp_rate: self.arp_rate.get(),
+            arp_mode: self.arp_mode.get(),
+            arp_octaves: self.arp_octaves.get(),
+            vel_to_timbre: self.vel_to_timbre.get(),
+            delay_pingpong: self.delay_pingpong.get(),
+            auto_gain: self.auto_gain.get(),
+            one_shot: self.one_shot.get(),
+            noise_smoothing: self.noise_smoothing.get(),
+            exciter: self.exciter.get(),
+            vel_to_brightness: self.vel_to_brightness.get(),
+            invert_left: self.invert_left.get(),
+            invert_right: self.invert_right.get(),
+            drone: self.drone.get(),
+            keyboard_pan: self.keyboard_pan.get(),
+            mpe: self.mpe.get(),
+            comp_threshold: self.comp_threshold.get(),
+            comp_ratio: self.comp_ratio.get(),
+            comp_attack: self.comp_attack.get(),
+            comp_release: self.comp_release.get(),
+            comp_makeup: self.comp_makeup.get(),
+            string_level: self.string_level.get(),
+            noise_level: self.noise_level.get(),
+            decay_time: self.decay_time.get(),
+        }
+    }
+
+    /// Set every setting `t`, `0.0..=1.0`, of the way from factory preset `preset_a` to
+    /// `preset_b`. Switches have no values in between, and change over halfway.
+    pub fn morph(&self, preset_a: usize, preset_b: usize, t: f32) {
+        let t = t.clamp(0.0, 1.0);
+        for &id in ParamId::ALL.iter().filter(|id| id.is_setting()) {
+            let a = self.preset_value(preset_a, id);
+            let b = self.preset_value(preset_b, id);
+            let value = if !id.is_switch() {
+                a * (1.0 - t) + b * t
+            } else if t < 0.5 {
+                a
+            } else {
+                b
+            };
+            self.set_parameter(id as i32, value);
+        }
+    }
+
+    /// The value factory preset `preset`, clamped to the ones there are, gives `id`.
+    fn preset_value(&self, preset: usize, id: ParamId) -> f32 {
+        let changes = PRESETS[preset.min(PRESETS.len() - 1)].1;
+        match changes.iter().find(|&&(changed, _)| changed == id) {
+            Some(&(_, value)) => value,
+            None => self.defaults[id as usize],
+        }
+    }
+
+    /// The LFO rate in hz. The parameter is squared to give finer control over slow rates.
+    fn lfo_rate(&self) -> f64 {
+        LFO_MAX_RATE * f64::from(self.lfo_rate.get()).powi(2)
+    }
+
+    fn lfo_shape(&self) -> Shape {
+        Shape::ALL[parameter_to_step(self.lfo_shape.get(), Shape::ALL.len())]
+    }
+
+    fn lfo_target(&self) -> NoiseSource {
+        NoiseSource::ALL[parameter_to_step(self.lfo_target.get(), NOISE_SOURCES)]
+    }
+
+    /// The vibrato rate in hz, scaled the same way as the LFO rate.
+    fn vibrato_rate(&self) -> f64 {
+        LFO_MAX_RATE * f64::from(self.vibrato_rate.get()).powi(2)
+    }
+
+    /// The vibrato depth in cents.
+    fn vibrato_depth(&self) -> f64 {
+        VIBRATO_MAX_DEPTH * f64::from(self.vibrato_depth.get())
+    }
+
+    fn mod_wheel_target(&self) -> Destination {
+        Destination::from_parameter(self.mod_wheel_target.get())
+    }
+
+    fn pressure_target(&self) -> Destination {
+        Destination::from_parameter(self.pressure_target.get())
+    }
+
+    /// The zero-based midi channel to listen on, or `None` to listen on all of them.
+    fn midi_channel(&self) -> Option<u8> {
+        match parameter_to_step(self.midi_channel.get(), MIDI_CHANNEL_STEPS) {
+            0 => None,
+            channel => Some(channel as u8 - 1),
+        }
+    }
+
+    fn key_low(&self) -> u8 {
+        parameter_to_step(self.key_low.get(), MIDI_NOTES) as u8
+    }
+
+    fn key_high(&self) -> u8 {
+        parameter_to_step(self.key_high.get(), MIDI_NOTES) as u8
+    }
+
+    /// How long a voice takes to fade in, in seconds. The parameter is squared to give finer
+    /// control over short attacks.
+    fn attack(&self) -> f64 {
+        MAX_ATTACK * f64::from(self.attack.get()).powi(2)
+    }
+
+    fn attack_curve(&self) -> EnvelopeCurve {
+        EnvelopeCurve::from_parameter(self.attack_curve.get())
+    }
+
+    fn release_curve(&self) -> EnvelopeCurve {
+        EnvelopeCurve::from_parameter(self.release_curve.get())
+    }
+
+    /// The most the output can reach, in dB.
+    fn ceiling_db(&self) -> f32 {
+        MIN_CEILING_DB + (MAX_CEILING_DB - MIN_CEILING_DB) * self.ceiling.get()
+    }
+
+    /// How much faster than `RELEASE` a voice let go at `velocity` fades out. Harder releases are
+    /// quicker, and the middle velocity is left as it is.
+    fn release_speed(&self, velocity: u8) -> f64 {
+        let amount = f64::from(self.rel_vel_amount.get());
+        let offset = (f64::from(velocity.min(127)) - 64.0) / 63.0;
+        (RELEASE_VELOCITY_RANGE * amount * offset).exp2()
+    }
+
+    /// The level the output gate shuts under, in dB, or `None` with the gate off.
+    fn gate_threshold(&self) -> Option<f32> {
+        let threshold = self.gate_threshold.get();
+        if threshold > 0.0 {
+            Some(MIN_THRESHOLD_DB * (1.0 - threshold))
+        } else {
+            None
+        }
+    }
+
+    /// How long the gate takes to open, in seconds. The parameter is squared to give finer
+    /// control over short times, as is the release's.
+    fn gate_attack(&self) -> f64 {
+        MAX_GATE_ATTACK * f64::from(self.gate_attack.get()).powi(2)
+    }
+
+    /// How long the gate takes to close, in seconds.
+    fn gate_release(&self) -> f64 {
+        MAX_GATE_RELEASE * f64::from(self.gate_release.get()).powi(2)
+    }
+
+    /// The level the output compressor turns down over, in dB.
+    fn comp_threshold_db(&self) -> f32 {
+        MIN_COMP_THRESHOLD_DB * (1.0 - self.comp_threshold.get())
+    }
+
+    /// The dB over the threshold going in for each coming out. The parameter is squared to
+    /// give finer control over the gentler ratios.
+    fn comp_ratio(&self) -> f32 {
+        1.0 + (MAX_RATIO - 1.0) * self.comp_ratio.get().powi(2)
+    }
+
+    /// How long the compressor takes to clamp down and to let go, in seconds, squared as the
+    /// gate's are.
+    fn comp_attack(&self) -> f64 {
+        MAX_COMP_ATTACK * f64::from(self.comp_attack.get()).powi(2)
+    }
+
+    fn comp_release(&self) -> f64 {
+        MAX_COMP_RELEASE * f64::from(self.comp_release.get()).powi(2)
+    }
+
+    fn comp_makeup_db(&self) -> f32 {
+        MAX_MAKEUP_DB * self.comp_makeup.get()
+    }
+
+    fn retrigger(&self) -> bool {
+        self.retrigger.get() > 0.5
+    }
+
+    /// How much the side signal is scaled by, where `1.0` leaves the image alone.
+    fn width(&self) -> f32 {
+        MAX_WIDTH * self.width.get()
+    }
+
+    /// The high-pass cutoff in hz, or `None` if it is off.
+    fn hp_cutoff(&self) -> Option<f64> {
+        match f64::from(self.hp_cutoff.get()) {
+            value if value <= 0.0 => None,
+            value => Some(MIN_CUTOFF * (MAX_CUTOFF / MIN_CUTOFF).powf(value)),
+        }
+    }
+
+    fn hp_q(&self) -> f64 {
+        MIN_Q * (MAX_Q / MIN_Q).powf(f64::from(self.hp_resonance.get()))
+    }
+
+    fn freeze(&self) -> bool {
+        self.freeze.get() > 0.5
+    }
+
+    fn voice_offset(&self) -> bool {
+        self.voice_offset.get() > 0.5
+    }
+
+    fn noise_3d(&self) -> bool {
+        self.noise_3d.get() > 0.5
+    }
+
+    fn noise_smoothing(&self) -> bool {
+        self.noise_smoothing.get() > 0.5
+    }
+
+    fn chorus_rate(&self) -> f64 {
+        MAX_CHORUS_RATE * f64::from(self.chorus_rate.get()).powi(2)
+    }
+
+    /// How far the chorus sweeps its delay either side of the centre, in seconds.
+    fn chorus_depth(&self) -> f64 {
+        MAX_CHORUS_DEPTH * f64::from(self.chorus_depth.get())
+    }
+
+    /// How fast `source` is read relative to the voice's pitch, from `1 / MAX_NOISE_RATIO` up
+    /// to `MAX_NOISE_RATIO`.
+    fn noise_ratio(&self, source: NoiseSource) -> f64 {
+        let value = f64::from(self.ratios[source as usize].get());
+        MAX_NOISE_RATIO.powf(value * 2.0 - 1.0)
+    }
+
+    fn source_enabled(&self, source: NoiseSource) -> bool {
+        self.enabled[source as usize].get() > 0.5
+    }
+
+    fn tremolo_rate(&self) -> f64 {
+        LFO_MAX_RATE * f64::from(self.tremolo_rate.get()).powi(2)
+    }
+
+    fn tremolo_shape(&self) -> Shape {
+        Shape::ALL[parameter_to_step(self.tremolo_shape.get(), Shape::ALL.len())]
+    }
+
+    /// The one noise source heard in `SourceMode::Single`.
+    fn selected_source(&self) -> NoiseSource {
+        NoiseSource::ALL[parameter_to_step(self.selected_source.get(), NOISE_SOURCES)]
+    }
+
+    fn fm_source(&self) -> NoiseSource {
+        NoiseSource::ALL[parameter_to_step(self.fm_source.get(), NOISE_SOURCES)]
+    }
+
+    fn fm_target(&self) -> NoiseSource {
+        NoiseSource::ALL[parameter_to_step(self.fm_target.get(), NOISE_SOURCES)]
+    }
+
+    fn one_shot(&self) -> bool {
+        self.one_shot.get() > 0.5
+    }
+
+    fn drone(&self) -> bool {
+        self.drone.get() > 0.5
+    }
+
+    fn mpe(&self) -> bool {
+        self.mpe.get() > 0.5
+    }
+
+    fn arp_on(&self) -> bool {
+        self.arp_on.get() > 0.5
+    }
+
+    /// How long each of the arpeggiator's steps lasts. It is always locked to the tempo, so it
+    /// skips `Division::Free`.
+    fn arp_rate(&self) -> Division {
+        Division::ALL[1 + parameter_to_step(self.arp_rate.get(), Division::ALL.len() - 1)]
+    }
+
+    fn arp_mode(&self) -> ArpMode {
+        ArpMode::ALL[parameter_to_step(self.arp_mode.get(), ArpMode::ALL.len())]
+    }
+
+    /// How many octaves the arpeggiator runs up through, from `1` to `MAX_OCTAVES`.
+    fn arp_octaves(&self) -> usize {
+        1 + parameter_to_step(self.arp_octaves.get(), MAX_OCTAVES)
+    }
+
+    fn lfo_sync(&self) -> Division {
+        Division::ALL[parameter_to_step(self.lfo_sync.get(), Division::ALL.len())]
+    }
+
+    /// How many semitones new notes are shifted by.
+    fn transpose(&self) -> i32 {
+        parameter_to_step(self.transpose.get(), TRANSPOSE_STEPS) as i32 - MAX_TRANSPOSE
+    }
+
+    fn exciter(&self) -> bool {
+        self.exciter.get() > 0.5
+    }
+
+    /// Whether the left and right sides of the output are flipped upside down.
+    fn invert(&self) -> [bool; 2] {
+        [self.invert_left.get() > 0.5, self.invert_right.get() > 0.5]
+    }
+
+    /// How long the pluck keeps feeding noise into its string, in seconds.
+    fn excite_length(&self) -> f64 {
+        MAX_EXCITE_LENGTH * f64::from(self.excite_length.get())
+    }
+
+    /// The scale keys are snapped onto before they are tuned.
+    fn scale_snap(&self) -> Scale {
+        Scale::ALL[parameter_to_step(self.scale_snap.get(), Scale::ALL.len())]
+    }
+
+    /// How the strings of new notes are fitted to their pitch.
+    fn tuning_mode(&self) -> Tuning {
+        Tuning::ALL[parameter_to_step(self.tuning_mode.get(), Tuning::ALL.len())]
+    }
+
+    fn delay_sync(&self) -> Division {
+        Division::ALL[parameter_to_step(self.delay_sync.get(), Division::ALL.len())]
+    }
+
+    /// The bit-crusher's resolution, from 1 bit up to `MAX_BIT_DEPTH` where it is off.
+    fn bit_depth(&self) -> u32 {
+        1 + parameter_to_step(self.bit_depth.get(), MAX_BIT_DEPTH as usize) as u32
+    }
+
+    /// How many samples the bit-crusher holds each value for, where `1` is off.
+    fn downsample(&self) -> usize {
+        1 + parameter_to_step(self.downsample.get(), MAX_DOWNSAMPLE)
+    }
+
+    /// The time between echoes in seconds, at least a millisecond.
+    fn delay_time(&self) -> f64 {
+        (MAX_DELAY * f64::from(self.delay_time.get())).max(0.001)
+    }
+
+    /// How far the right side is held back, in seconds, or `0.0` for not at all.
+    fn haas_delay(&self) -> f64 {
+        MAX_HAAS_DELAY * f64::from(self.haas_delay.get())
+    }
+
+    /// The ratio of the right echo line's time to the left's.
+    fn delay_spread(&self) -> f64 {
+        1.0 + MAX_DELAY_SPREAD * f64::from(self.delay_spread.get())
+    }
+
+    fn delay_feedback(&self) -> f32 {
+        MAX_FEEDBACK * self.delay_feedback.get()
+    }
+
+    fn delay_pingpong(&self) -> bool {
+        self.delay_pingpong.get() > 0.5
+    }
+
+    fn oversampling(&self) -> Oversampling {
+        Oversampling::ALL[parameter_to_step(self.oversampling.get(), Oversampling::ALL.len())]
+    }
+
+    /// How much the waveshaper boosts its input, from `1.0` where it is off up to `MAX_DRIVE`.
+    /// The boost grows exponentially so each step of the parameter adds about as many dB.
+    fn drive(&self) -> f32 {
+        MAX_DRIVE.powf(self.drive.get())
+    }
+
+    fn curve(&self) -> Curve {
+        Curve::ALL[parameter_to_step(self.curve.get(), Curve::ALL.len())]
+    }
+
+    /// The highest absolute sample in the last block.
+    fn current_peak(&self) -> f32 {
+        self.peak_level.get()
+    }
+
+    /// The root mean square level of the last block.
+    fn current_rms(&self) -> f32 {
+        self.rms_level.get()
+    }
+
+    /// The last sample written to the left, `0`, or right, `1`, output.
+    pub fn last_output(&self, channel: usize) -> f32 {
+        self.last_output[channel.min(1)].get()
+    }
+
+    /// How much of a string's signal survives each trip round its loop.
+    fn damping(&self) -> f32 {
+        MIN_DAMPING + (MAX_DAMPING - MIN_DAMPING) * self.damping.get()
+    }
+
+    /// How long the strings take to fall 60 dB in seconds, or `None` if it is off and they are
+    /// damped by `damping`.
+    fn decay_time(&self) -> Option<f64> {
+        match f64::from(self.decay_time.get()) {
+            value if value <= 0.0 => None,
+            value => Some(MIN_DECAY * (MAX_DECAY / MIN_DECAY).powf(value)),
+        }
+    }
+
+    /// The damping key tracking, `-1.0..=1.0`. At `1.0` the loss doubles with every octave up
+    /// from middle C, and at `-1.0` it halves.
+    fn damping_key_track(&self) -> f32 {
+        self.damping_key_track.get() * 2.0 - 1.0
+    }
+
+    /// The parameter `midi_learn` is waiting to bind, if any.
+    fn midi_learn(&self) -> Option<i32> {
+        match parameter_to_step(self.midi_learn.get(), MIDI_LEARN_STEPS) {
+            0 => None,
+            step => Some(step as i32 - 1),
+        }
+    }
+
+    /// Wait for the next CC that comes in, and bind it to the parameter at `index`.
+    fn learn(&self, index: i32) {
+        let step = if (0..PARAMETER_COUNT).contains(&index) { index as usize + 1 } else { 0 };
+        self.midi_learn.set(step_to_parameter(step, MIDI_LEARN_STEPS));
+    }
+
+    /// The parameter `controller` drives, if any.
+    ///
+    /// If a parameter is waiting to be learned, it is bound to `controller` first.
+    fn cc_binding(&self, controller: u8) -> Option<i32> {
+        let binding = &self.cc_bindings[controller as usize];
+        if let Some(index) = self.midi_learn() {
+            binding.store(index as isize, Ordering::Relaxed);
+            self.learn(-1);
+        }
+        match binding.load(Ordering::Relaxed) {
+            -1 => None,
+            index => Some(index as i32),
+        }
+    }
+
+    /// The ring modulator's carrier frequency in hz, spread exponentially over its range.
+    fn ring_freq(&self) -> f64 {
+        RING_MIN_FREQ * (RING_MAX_FREQ / RING_MIN_FREQ).powf(f64::from(self.ring_freq.get()))
+    }
+}
+
+impl Default for SynthParameters {
+    fn default() -> SynthParameters {
+        let mut params = SynthParameters {
+            duplicate_notes: AtomicFloat::new(DuplicateNotes::Retrigger.to_parameter()),
+            amounts: [
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+                AtomicFloat::new(0.0),
+            ],
+            lfo_rate: AtomicFloat::new(0.5),
+            lfo_depth: AtomicFloat::new(0.0),
+            lfo_shape: AtomicFloat::new(step_to_parameter(0, Shape::ALL.len())),
+            lfo_target: AtomicFloat::new(step_to_parameter(0, NOISE_SOURCES)),
+            vibrato_rate: AtomicFloat::new(0.5),
+            vibrato_depth: AtomicFloat::new(0.0),
+            mod_wheel_target: AtomicFloat::new(Destination::VibratoDepth.to_parameter()),
+            pressure_target: AtomicFloat::new(Destination::Amplitude.to_parameter()),
+            midi_channel: AtomicFloat::new(step_to_parameter(0, MIDI_CHANNEL_STEPS)),
+            bit_depth: AtomicFloat::new(1.0),
+            downsample: AtomicFloat::new(0.0),
+            delay_time: AtomicFloat::new(0.25),
+            delay_feedback: AtomicFloat::new(0.5),
+            delay_mix: AtomicFloat::new(0.0),
+            oversampling: AtomicFloat::new(step_to_parameter(0, Oversampling::ALL.len())),
+            drive: AtomicFloat::new(0.0),
+            curve: AtomicFloat::new(step_to_parameter(0, Curve::ALL.len())),
+            ring_mod: AtomicFloat::new(0.0),
+            ring_freq: AtomicFloat::new(0.5),
+            peak_level: AtomicFloat::new(0.0),
+            rms_level: AtomicFloat::new(0.0),
+            panic: AtomicFloat::new(0.0),
+            damping: AtomicFloat::new(
+                (DEFAULT_DAMPING - MIN_DAMPING) / (MAX_DAMPING - MIN_DAMPING),
+            ),
+            damping_key_track: AtomicFloat::new(0.5),
+            output_mode: AtomicFloat::new(OutputMode::Mono.to_parameter()),
+            midi_learn: AtomicFloat::new(step_to_parameter(0, MIDI_LEARN_STEPS)),
+            key_low: AtomicFloat::new(step_to_parameter(0, MIDI_NOTES)),
+            key_high: AtomicFloat::new(step_to_parameter(MIDI_NOTES - 1, MIDI_NOTES)),
+            velocity_curve: AtomicFloat::new(VelocityCurve::Linear.to_parameter()),
+            attack: AtomicFloat::new(0.0),
+            retrigger: AtomicFloat::new(1.0),
+            lfo_sync: AtomicFloat::new(step_to_parameter(0, Division::ALL.len())),
+            delay_sync: AtomicFloat::new(step_to_parameter(0, Division::ALL.len())),
+            voice_offset: AtomicFloat::new(1.0),
+            noise_3d: AtomicFloat::new(0.0),
+            chorus_rate: AtomicFloat::new(0.5),
+            chorus_depth: AtomicFloat::new(0.5),
+            chorus_mix: AtomicFloat::new(0.0),
+            ratios: [
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+                AtomicFloat::new(0.5),
+            ],
+            enabled: [
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+                AtomicFloat::new(1.0),
+            ],
+            tremolo_rate: AtomicFloat::new(0.5),
+            tremolo_depth: AtomicFloat::new(0.0),
+            tremolo_shape: AtomicFloat::new(step_to_parameter(0, Shape::ALL.len())),
+            fm_source: AtomicFloat::new(step_to_parameter(
+                NoiseSource::Perlin as usize,
+                NOISE_SOURCES,
+            )),
+            fm_target: AtomicFloat::new(step_to_parameter(
+                NoiseSource::Worley as usize,
+                NOISE_SOURCES,
+            )),
+            fm_amount: AtomicFloat::new(0.0),
+            bypass: AtomicFloat::new(0.0),
+            source_mode: AtomicFloat::new(SourceMode::Blend.to_parameter()),
+            selected_source: AtomicFloat::new(step_to_parameter(0, NOISE_SOURCES)),
+            normalize: AtomicFloat::new(0.0),
+            freeze: AtomicFloat::new(0.0),
+            width: AtomicFloat::new(0.5),
+            hp_cutoff: AtomicFloat::new(0.0),
+            hp_resonance: AtomicFloat::new(
+                ((BUTTERWORTH_Q / MIN_Q).ln() / (MAX_Q / MIN_Q).ln()) as f32,
+            ),
+            attack_curve: AtomicFloat::new(EnvelopeCurve::Linear.to_parameter()),
+            release_curve: AtomicFloat::new(EnvelopeCurve::Linear.to_parameter()),
+            humanize: AtomicFloat::new(0.0),
+            reverb_size: AtomicFloat::new(0.5),
+            reverb_damp: AtomicFloat::new(0.5),
+            reverb_mix: AtomicFloat::new(0.0),
+            gate_threshold: AtomicFloat::new(0.0),
+            gate_attack: AtomicFloat::new(0.2),
+            gate_release: AtomicFloat::new(0.3),
+            delay_spread: AtomicFloat::new(0.0),
+            ceiling: AtomicFloat::new(
+                (DEFAULT_CEILING_DB - MIN_CEILING_DB) / (MAX_CEILING_DB - MIN_CEILING_DB),
+            ),
+            scale_snap: AtomicFloat::new(step_to_parameter(Scale::Off as usize, Scale::ALL.len())),
+            excite_length: AtomicFloat::new(0.0),
+            rel_vel_amount: AtomicFloat::new(0.0),
+            transpose: AtomicFloat::new(step_to_parameter(MAX_TRANSPOSE as usize, TRANSPOSE_STEPS)),
+            global_mix: AtomicFloat::new(1.0),
+            drift: AtomicFloat::new(0.0),
+            tuning_mode: AtomicFloat::new(step_to_parameter(
+                Tuning::Exact as usize,
+                Tuning::ALL.len(),
+            )),
+            haas_delay: AtomicFloat::new(0.0),
+            arp_on: AtomicFloat::new(0.0),
+            arp_rate: AtomicFloat::new(step_to_parameter(
+                Division::Sixteenth as usize - 1,
+                Division::ALL.len() - 1,
+            )),
+            arp_mode: AtomicFloat::new(step_to_parameter(ArpMode::Up as usize, ArpMode::ALL.len())),
+            arp_octaves: AtomicFloat::new(0.0),
+            vel_to_timbre: AtomicFloat::new(0.0),
+            delay_pingpong: AtomicFloat::new(0.0),
+            auto_gain: AtomicFloat::new(0.0),
+            one_shot: AtomicFloat::new(0.0),
+            noise_smoothing: AtomicFloat::new(0.0),
+            exciter: AtomicFloat::new(0.0),
+            vel_to_brightness: AtomicFloat::new(0.0),
+            invert_left: AtomicFloat::new(0.0),
+            invert_right: AtomicFloat::new(0.0),
+            drone: AtomicFloat::new(0.0),
+            keyboard_pan: AtomicFloat::new(0.0),
+            mpe: AtomicFloat::new(0.0),
+            comp_threshold: AtomicFloat::new(1.0),
+            comp_ratio: AtomicFloat::new(0.0),
+            comp_attack: AtomicFloat::new(0.3),
+            comp_release: AtomicFloat::new(0.3),
+            comp_makeup: AtomicFloat::new(0.0),
+            string_level: AtomicFloat::new(1.0),
+            noise_level: AtomicFloat::new(1.0),
+            decay_time: AtomicFloat::new(0.0),
+            cc_bindings: (0..CONTROLLERS).map(|_| AtomicIsize::new(-1)).collect(),
+            preset: AtomicIsize::new(0),
+            // Everything is worked out on the first block.
+            dirty: AtomicUsize::new(usize::MAX),
+            last_output: [AtomicFloat::new(0.0), AtomicFloat::new(0.0)],
+            defaults: Vec::new(),
+        };
+        params.defaults = (0..PARAMETER_COUNT).map(|i| params.get_parameter(i)).collect();
+        params
+    }
+}
+
+impl PluginParameters for SynthParameters {
+    /// Switch to factory preset `preset`, clamped to the ones there are. Every setting it does
+    /// not set goes back to its default, but the CC bindings and the controls for playing the
+    /// plugin are kept.
+    fn change_preset(&self, preset: i32) {
+        let preset = preset.clamp(0, PRESETS.len() as i32 - 1);
+        for &id in ParamId::ALL.iter().filter(|id| id.is_setting()) {
+            self.set_parameter(id as i32, self.defaults[id as usize]);
+        }
+        for &(id, value) in PRESETS[preset as usize].1.iter() {
+            self.set_parameter(id as i32, value);
+        }
+        self.preset.store(preset as isize, Ordering::Relaxed);
+    }
+
+    fn get_preset_num(&self) -> i32 {
+        self.preset.load(Ordering::Relaxed) as i32
+    }
+
+    fn get_preset_name(&self, preset: i32) -> String {
+        match PRESETS.get(preset as usize) {
+            Some(&(name, _)) => name.to_string(),
+            None => "".to_string(),
+        }
+    }
+
+    fn get_parameter(&self, index: i32) -> f32 {
+        let id = match ParamId::try_from(index) {
+            Ok(id) => id,
+            Err(()) => return 0.0,
+        };
+        if let Some(source) = id.amount() {
+            return self.amounts[source as usize].get();
+        }
+        if let Some(source) = id.rate() {
+            return self.ratios[source as usize].get();
+        }
+        if let Some(source) = id.enable() {
+            return self.enabled[source as usize].get();
+        }
+        match id {
+            ParamId::DuplicateNotes => self.duplicate_notes.get(),
+            ParamId::LfoRate => self.lfo_rate.get(),
+            ParamId::LfoDepth => self.lfo_depth.get(),
+            ParamId::LfoShape => self.lfo_shape.get(),
+            ParamId::LfoTarget => self.lfo_target.get(),
+            ParamId::VibratoRate => self.vibrato_rate.get(),
+            ParamId::VibratoDepth => self.vibrato_depth.get(),
+            ParamId::ModWheelTarget => self.mod_wheel_target.get(),
+            ParamId::PressureTarget => self.pressure_target.get(),
+            ParamId::MidiChannel => self.midi_channel.get(),
+            ParamId::BitDepth => self.bit_depth.get(),
+            ParamId::Downsample => self.downsample.get(),
+            ParamId::DelayTime => self.delay_time.get(),
+            ParamId::DelayFeedback => self.delay_feedback.get(),
+            ParamId::DelayMix => self.delay_mix.get(),
+            ParamId::Oversampling => self.oversampling.get(),
+            ParamId::Drive => self.drive.get(),
+            ParamId::Curve => self.curve.get(),
+            ParamId::RingMod => self.ring_mod.get(),
+            ParamId::RingFreq => self.ring_freq.get(),
+            ParamId::PeakLevel => self.current_peak().min(1.0),
+            ParamId::RmsLevel => self.current_rms().min(1.0),
+            ParamId::Panic => self.panic.get(),
+            ParamId::Damping => self.damping.get(),
+            ParamId::DampingKeyTrack => self.damping_key_track.get(),
+            ParamId::OutputMode => self.output_mode.get(),
+            ParamId::MidiLearn => self.midi_learn.get(),
+            ParamId::KeyLow => self.key_low.get(),
+            ParamId::KeyHigh => self.key_high.get(),
+            ParamId::VelocityCurve => self.velocity_curve.get(),
+            ParamId::Attack => self.attack.get(),
+            ParamId::Retrigger => self.retrigger.get(),
+            ParamId::LfoSync => self.lfo_sync.get(),
+            ParamId::DelaySync => self.delay_sync.get(),
+            ParamId::VoiceOffset => self.voice_offset.get(),
+            ParamId::Noise3d => self.noise_3d.get(),
+            ParamId::ChorusRate => self.chorus_rate.get(),
+            ParamId::ChorusDepth => self.chorus_depth.get(),
+            ParamId::ChorusMix => self.chorus_mix.get(),
+            ParamId::TremoloRate => self.tremolo_rate.get(),
+            ParamId::TremoloDepth => self.tremolo_depth.get(),
+            ParamId::TremoloShape => self.tremolo_shape.get(),
+            ParamId::FmSource => self.fm_source.get(),
+            ParamId::FmTarget => self.fm_target.get(),
+            ParamId::FmAmount => self.fm_amount.get(),
+            ParamId::Bypass => self.bypass.get(),
+            ParamId::SourceMode => self.source_mode.get(),
+            ParamId::SelectedSource => self.selected_source.get(),
+            ParamId::Normalize => self.normalize.get(),
+            ParamId::Freeze => self.freeze.get(),
+            ParamId::Width => self.width.get(),
+            ParamId::HpCutoff => self.hp_cutoff.get(),
+            ParamId::HpResonance => self.hp_resonance.get(),
+            ParamId::AttackCurve => self.attack_curve.get(),
+            ParamId::ReleaseCurve => self.release_curve.get(),
+            ParamId::Humanize => self.humanize.get(),
+            ParamId::ReverbSize => self.reverb_size.get(),
+            ParamId::ReverbDamp => self.reverb_damp.get(),
+            ParamId::ReverbMix => self.reverb_mix.get(),
+            ParamId::GateThreshold => self.gate_threshold.get(),
+            ParamId::GateAttack => self.gate_attack.get(),
+            ParamId::GateRelease => self.gate_release.get(),
+            ParamId::DelaySpread => self.delay_spread.get(),
+            ParamId::Ceiling => self.ceiling.get(),
+            ParamId::ScaleSnap => self.scale_snap.get(),
+            ParamId::ExciteLength => self.excite_length.get(),
+            ParamId::RelVelAmount => self.rel_vel_amount.get(),
+            ParamId::Transpose => self.transpose.get(),
+            ParamId::GlobalMix => self.global_mix.get(),
+            ParamId::Drift => self.drift.get(),
+            ParamId::TuningMode => self.tuning_mode.get(),
+            ParamId::HaasDelay => self.haas_delay.get(),
+            ParamId::ArpOn => self.arp_on.get(),
+            ParamId::ArpRate => self.arp_rate.get(),
+            ParamId::ArpMode => self.arp_mode.get(),
+            ParamId::ArpOctaves => self.arp_octaves.get(),
+            ParamId::VelToTimbre => self.vel_to_timbre.get(),
+            ParamId::DelayPingPong => self.delay_pingpong.get(),
+            ParamId::AutoGain => self.auto_gain.get(),
+            ParamId::OneShot => self.one_shot.get(),
+            ParamId::NoiseSmoothing => self.noise_smoothing.get(),
+            ParamId::Exciter => self.exciter.get(),
+            ParamId::VelToBrightness => self.vel_to_brightness.get(),
+            ParamId::InvertLeft => self.invert_left.get(),
+            ParamId::InvertRight => self.invert_right.get(),
+            ParamId::Drone => self.drone.get(),
+            ParamId::KeyboardPan => self.keyboard_pan.get(),
+            ParamId::Mpe => self.mpe.get(),
+            ParamId::CompThreshold => self.comp_threshold.get(),
+            ParamId::CompRatio => self.comp_ratio.get(),
+            ParamId::CompAttack => self.comp_attack.get(),
+            ParamId::CompRelease => self.comp_release.get(),
+            ParamId::CompMakeup => self.comp_makeup.get(),
+            ParamId::StringLevel => self.string_level.get(),
+            ParamId::NoiseLevel => self.noise_level.get(),
+            ParamId::DecayTime => self.decay_time.get(),
+            // The amounts, rates and enables, which are read above.
+            _ => 0.0,
+        }
+    }
+
+    /// VST 2.4 hands parameter changes over one at a time, with no position in the block, so
+    /// what the last block ended on and what is set for the next are the two points to ramp
+    /// between. The levels in `Ramps` are carried from one to the other across the block, and
+    /// the rest are heard from its start.
+    fn set_parameter(&self, index: i32, value: f32) {
+        let id = match ParamId::try_from(index) {
+            Ok(id) => id,
+            Err(()) => return,
+        };
+        // Automation or a damaged preset could send anything, and a NaN let in here would
+        // spread through every sample after it.
+        if !value.is_finite() {
+            return;
+        }
+        if let Some(group) = id.derived() {
+            self.dirty.fetch_or(group.bit(), Ordering::Relaxed);
+        }
+        if let Some(source) = id.amount() {
+            self.amounts[source as usize].set(value);
+            return;
+        }
+        if let Some(source) = id.rate() {
+            self.ratios[source as usize].set(value);
+            return;
+        }
+        if let Some(source) = id.enable() {
+            self.enabled[source as usize].set(value);
+            return;
+        }
+        match id {
+            ParamId::DuplicateNotes => self.duplicate_notes.set(value),
+            ParamId::LfoRate => self.lfo_rate.set(value),
+            ParamId::LfoDepth => self.lfo_depth.set(value),
+            ParamId::LfoShape => self.lfo_shape.set(value),
+            ParamId::LfoTarget => self.lfo_target.set(value),
+            ParamId::VibratoRate => self.vibrato_rate.set(value),
+            ParamId::VibratoDepth => self.vibrato_depth.set(value),
+            ParamId::ModWheelTarget => self.mod_wheel_target.set(value),
+            ParamId::PressureTarget => self.pressure_target.set(value),
+            ParamId::MidiChannel => self.midi_channel.set(value),
+            ParamId::BitDepth => self.bit_depth.set(value),
+            ParamId::Downsample => self.downsample.set(value),
+            ParamId::DelayTime => self.delay_time.set(value),
+            ParamId::DelayFeedback => self.delay_feedback.set(value),
+            ParamId::DelayMix => self.delay_mix.set(value),
+            ParamId::Oversampling => self.oversampling.set(value),
+            ParamId::Drive => self.drive.set(value),
+            ParamId::Curve => self.curve.set(value),
+            ParamId::RingMod => self.ring_mod.set(value),
+            ParamId::RingFreq => self.ring_freq.set(value),
+            // The meters are measured, not set, and the amounts and rates are set above.
+            ParamId::Panic => self.panic.set(value),
+            // Kept inside the range so the coefficient can never leave `MIN_DAMPING..=MAX_DAMPING`
+            // and let a string ring forever.
+            ParamId::Damping => self.damping.set(value.clamp(0.0, 1.0)),
+            ParamId::DampingKeyTrack => self.damping_key_track.set(value),
+            ParamId::OutputMode => self.output_mode.set(value),
+            ParamId::MidiLearn => self.midi_learn.set(value),
+            ParamId::KeyLow => self.key_low.set(value),
+            ParamId::KeyHigh => self.key_high.set(value),
+            ParamId::VelocityCurve => self.velocity_curve.set(value),
+            ParamId::Attack => self.attack.set(value),
+            ParamId::Retrigger => self.retrigger.set(value),
+            ParamId::LfoSync => self.lfo_sync.set(value),
+            ParamId::DelaySync => self.delay_sync.set(value),
+            ParamId::VoiceOffset => self.voice_offset.set(value),
+            ParamId::Noise3d => self.noise_3d.set(value),
+            ParamId::ChorusRate => self.chorus_rate.set(value),
+            ParamId::ChorusDepth => self.chorus_depth.set(value),
+            ParamId::ChorusMix => self.chorus_mix.set(value),
+            ParamId::TremoloRate => self.tremolo_rate.set(value),
+            ParamId::TremoloDepth => self.tremolo_depth.set(value),
+            ParamId::TremoloShape => self.tremolo_shape.set(value),
+            ParamId::FmSource => self.fm_source.set(value),
+            ParamId::FmTarget => self.fm_target.set(value),
+            ParamId::FmAmount => self.fm_amount.set(value),
+            ParamId::Bypass => self.bypass.set(value),
+            ParamId::SourceMode => self.source_mode.set(value),
+            ParamId::SelectedSource => self.selected_source.set(value),
+            ParamId::Normalize => self.normalize.set(value),
+            ParamId::Freeze => self.freeze.set(value),
+            ParamId::Width => self.width.set(value),
+            ParamId::HpCutoff => self.hp_cutoff.set(value),
+            ParamId::HpResonance => self.hp_resonance.set(value),
+            ParamId::AttackCurve => self.attack_curve.set(value),
+            ParamId::ReleaseCurve => self.release_curve.set(value),
+            ParamId::Humanize => self.humanize.set(value),
+            ParamId::ReverbSize => self.reverb_size.set(value),
+            ParamId::ReverbDamp => self.reverb_damp.set(value),
+            ParamId::ReverbMix => self.reverb_mix.set(value),
+            ParamId::GateThreshold => self.gate_threshold.set(value),
+            ParamId::GateAttack => self.gate_attack.set(value),
+            ParamId::GateRelease => self.gate_release.set(value),
+            ParamId::DelaySpread => self.delay_spread.set(value),
+            ParamId::Ceiling => self.ceiling.set(value),
+            ParamId::ScaleSnap => self.scale_snap.set(value),
+            ParamId::ExciteLength => self.excite_length.set(value),
+            ParamId::RelVelAmount => self.rel_vel_amount.set(value),
+            ParamId::Transpose => self.transpose.set(value),
+            ParamId::GlobalMix => self.global_mix.set(value),
+            ParamId::Drift => self.drift.set(value),
+            ParamId::TuningMode => self.tuning_mode.set(value),
+            ParamId::HaasDelay => self.haas_delay.set(value),
+            ParamId::ArpOn => self.arp_on.set(value),
+            ParamId::ArpRate => self.arp_rate.set(value),
+            ParamId::ArpMode => self.arp_mode.set(value),
+            ParamId::ArpOctaves => self.arp_octaves.set(value),
+            ParamId::VelToTimbre => self.vel_to_timbre.set(value),
+            ParamId::DelayPingPong => self.delay_pingpong.set(value),
+            ParamId::AutoGain => self.auto_gain.set(value),
+            ParamId::OneShot => self.one_shot.set(value),
+            ParamId::NoiseSmoothing => self.noise_smoothing.set(value),
+            ParamId::Exciter => self.exciter.set(value),
+            ParamId::VelToBrightness => self.vel_to_brightness.set(value),
+            ParamId::InvertLeft => self.invert_left.set(value),
+            ParamId::InvertRight => self.invert_right.set(value),
+            ParamId::Drone => self.drone.set(value),
+            ParamId::KeyboardPan => self.keyboard_pan.set(value),
+            ParamId::Mpe => self.mpe.set(value),
+            ParamId::CompThreshold => self.comp_threshold.set(value),
+            ParamId::CompRatio => self.comp_ratio.set(value),
+            ParamId::CompAttack => self.comp_attack.set(value),
+            ParamId::CompRelease => self.comp_release.set(value),
+            ParamId::CompMakeup => self.comp_makeup.set(value),
+            ParamId::StringLevel => self.string_level.set(value),
+            ParamId::NoiseLevel => self.noise_level.set(value),
+            ParamId::DecayTime => self.decay_time.set(value),
+            _ => (),
+        }
+    }
+
+    fn get_parameter_name(&self, index: i32) -> String {
+        let id = match ParamId::try_from(index) {
+            Ok(id) => id,
+            Err(()) => return "".to_string(),
+        };
+        if let Some(source) = id.amount() {
+            return source.name().to_string();
+        }
+        if let Some(source) = id.rate() {
+            return format!("{} rate", source.name());
+        }
+        if let Some(source) = id.enable() {
+            return format!("{} on", source.name());
+        }
+        match id {
+            ParamId::DuplicateNotes => "Duplicate notes",
+            ParamId::LfoRate => "LFO rate",
+            ParamId::LfoDepth => "LFO depth",
+            ParamId::LfoShape => "LFO shape",
+            ParamId::LfoTarget => "LFO target",
+            ParamId::VibratoRate => "Vibrato rate",
+            ParamId::VibratoDepth => "Vibrato depth",
+            ParamId::ModWheelTarget => "Mod wheel target",
+            ParamId::PressureTarget => "Aftertouch target",
+            ParamId::MidiChannel => "MIDI channel",
+            ParamId::BitDepth => "Bit depth",
+            ParamId::Downsample => "Downsample",
+            ParamId::DelayTime => "Delay time",
+            ParamId::DelayFeedback => "Delay feedback",
+            ParamId::DelayMix => "Delay mix",
+            ParamId::Oversampling => "Oversampling",
+            ParamId::Drive => "Drive",
+            ParamId::Curve => "Curve",
+            ParamId::RingMod => "Ring mod",
+            ParamId::RingFreq => "Ring frequency",
+            ParamId::PeakLevel => "Output peak",
+            ParamId::RmsLevel => "Output RMS",
+            ParamId::Panic => "Panic",
+            ParamId::Damping => "Damping",
+            ParamId::DampingKeyTrack => "Damping key track",
+            ParamId::OutputMode => "Output mode",
+            ParamId::MidiLearn => "MIDI learn",
+            ParamId::KeyLow => "Lowest key",
+            ParamId::KeyHigh => "Highest key",
+            ParamId::VelocityCurve => "Velocity curve",
+            ParamId::Attack => "Attack",
+            ParamId::Retrigger => "Retrigger",
+            ParamId::LfoSync => "LFO sync",
+            ParamId::DelaySync => "Delay sync",
+            ParamId::VoiceOffset => "Voice offset",
+            ParamId::Noise3d => "Noise mode",
+            ParamId::ChorusRate => "Chorus rate",
+            ParamId::ChorusDepth => "Chorus depth",
+            ParamId::ChorusMix => "Chorus mix",
+            ParamId::TremoloRate => "Tremolo rate",
+            ParamId::TremoloDepth => "Tremolo depth",
+            ParamId::TremoloShape => "Tremolo shape",
+            ParamId::FmSource => "FM source",
+            ParamId::FmTarget => "FM target",
+            ParamId::FmAmount => "FM amount",
+            ParamId::Bypass => "Bypass",
+            ParamId::SourceMode => "Source mode",
+            ParamId::SelectedSource => "Selected source",
+            ParamId::Normalize => "Normalize amounts",
+            ParamId::Freeze => "Freeze",
+            ParamId::Width => "Width",
+            ParamId::HpCutoff => "High-pass cutoff",
+            ParamId::HpResonance => "High-pass resonance",
+            ParamId::AttackCurve => "Attack curve",
+            ParamId::ReleaseCurve => "Release curve",
+            ParamId::Humanize => "Humanize",
+            ParamId::ReverbSize => "Reverb size",
+            ParamId::ReverbDamp => "Reverb damping",
+            ParamId::ReverbMix => "Reverb mix",
+            ParamId::GateThreshold => "Gate threshold",
+            ParamId::GateAttack => "Gate attack",
+            ParamId::GateRelease => "Gate release",
+            ParamId::DelaySpread => "Delay spread",
+            ParamId::Ceiling => "Ceiling",
+            ParamId::ScaleSnap => "Scale",
+            ParamId::ExciteLength => "Excitation",
+            ParamId::RelVelAmount => "Release velocity",
+            ParamId::Transpose => "Transpose",
+            ParamId::GlobalMix => "Mix",
+            ParamId::Drift => "Drift",
+            ParamId::TuningMode => "Tuning",
+            ParamId::HaasDelay => "Haas",
+            ParamId::ArpOn => "Arpeggiator",
+            ParamId::ArpRate => "Arp rate",
+            ParamId::ArpMode => "Arp mode",
+            ParamId::ArpOctaves => "Arp octaves",
+            ParamId::VelToTimbre => "Velocity to timbre",
+            ParamId::DelayPingPong => "Delay ping-pong",
+            ParamId::AutoGain => "Auto gain",
+            ParamId::OneShot => "One-shot",
+            ParamId::NoiseSmoothing => "Noise smoothing",
+            ParamId::Exciter => "Exciter",
+            ParamId::VelToBrightness => "Velocity to brightness",
+            ParamId::InvertLeft => "Invert left",
+            ParamId::InvertRight => "Invert right",
+            ParamId::Drone => "Drone",
+            ParamId::KeyboardPan => "Keyboard pan",
+            ParamId::Mpe => "MPE",
+            ParamId::CompThreshold => "Comp threshold",
+            ParamId::CompRatio => "Comp ratio",
+            ParamId::CompAttack => "Comp attack",
+            ParamId::CompRelease => "Comp release",
+            ParamId::CompMakeup => "Comp makeup",
+            ParamId::StringLevel => "String level",
+            ParamId::NoiseLevel => "Noise level",
+            ParamId::DecayTime => "Decay time",
+            // The amounts, rates and enables, which are named above.
+            _ => "",
+        }
+        .to_string()
+    }
+
+    fn get_parameter_text(&self, index: i32) -> String {
+        let id = match ParamId::try_from(index) {
+            Ok(id) => id,
+            Err(()) => return "".to_string(),
+        };
+        if id.amount().is_some() {
+            return format!("{:.0}%", self.get_parameter(index) * 100.0);
+        }
+        if let Some(source) = id.rate() {
+            return format!("{:.2}x", self.noise_ratio(source));
+        }
+        if let Some(source) = id.enable() {
+            let on = self.source_enabled(source);
+            return if on { "On" } else { "Off" }.to_string();
+        }
+        match id {
+            ParamId::DuplicateNotes => DuplicateNotes::from_parameter(self.duplicate_notes.get())
+                .name()
+                .to_string(),
+            ParamId::LfoRate => format!("{:.2} Hz", self.lfo_rate()),
+            ParamId::LfoDepth => format!("{:.0}%", self.lfo_depth.get() * 100.0),
+            ParamId::LfoShape => self.lfo_shape().name().to_string(),
+            ParamId::LfoTarget => self.lfo_target().name().to_string(),
+            ParamId::VibratoRate => format!("{:.2} Hz", self.vibrato_rate()),
+            ParamId::VibratoDepth => format!("{:.0} cents", self.vibrato_depth()),
+            ParamId::ModWheelTarget => self.mod_wheel_target().name().to_string(),
+            ParamId::PressureTarget => self.pressure_target().name().to_string(),
+            ParamId::MidiChannel => match self.midi_channel() {
+                Some(channel) => (channel + 1).to_string(),
+                None => "Omni".to_string(),
+            },
+            ParamId::BitDepth => match self.bit_depth() {
+                MAX_BIT_DEPTH => "Off".to_string(),
+                bits => format!("{} bits", bits),
+            },
+            ParamId::Downsample => match self.downsample() {
+                1 => "Off".to_string(),
+                factor => format!("1/{}", factor),
+            },
+            ParamId::DelayTime => format!("{:.0} ms", self.delay_time() * 1000.0),
+            ParamId::DelayFeedback => format!("{:.0}%", self.delay_feedback() * 100.0),
+            ParamId::DelayMix => format!("{:.0}%", self.delay_mix.get() * 100.0),
+            ParamId::Oversampling => self.oversampling().name().to_string(),
+            ParamId::Drive => match self.drive() {
+                drive if drive <= 1.0 => "Off".to_string(),
+                drive => format!("+{:.1} dB", 20.0 * drive.log10()),
+            },
+            ParamId::Curve => self.curve().name().to_string(),
+            ParamId::RingMod => format!("{:.0}%", self.ring_mod.get() * 100.0),
+            ParamId::RingFreq => format!("{:.0} Hz", self.ring_freq()),
+            ParamId::PeakLevel => format!("{:.1} dB", 20.0 * self.current_peak().log10()),
+            ParamId::RmsLevel => format!("{:.1} dB", 20.0 * self.current_rms().log10()),
+            ParamId::Panic => if self.panic.get() > 0.5 { "Panic" } else { "Off" }.to_string(),
+            ParamId::Damping => format!("{:.4}", self.damping()),
+            ParamId::DampingKeyTrack => format!("{:+.0}%", self.damping_key_track() * 100.0),
+            ParamId::OutputMode => OutputMode::from_parameter(self.output_mode.get())
+                .name()
+                .to_string(),
+            ParamId::MidiLearn => match self.midi_learn() {
+                Some(index) => self.get_parameter_name(index),
+                None => "Off".to_string(),
+            },
+            ParamId::KeyLow => note_name(self.key_low()),
+            ParamId::KeyHigh => note_name(self.key_high()),
+            ParamId::VelocityCurve => VelocityCurve::from_parameter(self.velocity_curve.get())
+                .name()
+                .to_string(),
+            ParamId::Attack => format!("{:.0} ms", self.attack() * 1000.0),
+            ParamId::Retrigger => if self.retrigger() { "Retrigger" } else { "Legato" }.to_string(),
+            ParamId::LfoSync => self.lfo_sync().name().to_string(),
+            ParamId::DelaySync => self.delay_sync().name().to_string(),
+            ParamId::VoiceOffset => if self.voice_offset() { "On" } else { "Off" }.to_string(),
+            ParamId::Noise3d => if self.noise_3d() { "3D" } else { "2D" }.to_string(),
+            ParamId::ChorusRate => format!("{:.2} Hz", self.chorus_rate()),
+            ParamId::ChorusDepth => format!("{:.1} ms", self.chorus_depth() * 1000.0),
+            ParamId::ChorusMix => format!("{:.0}%", self.chorus_mix.get() * 100.0),
+            ParamId::TremoloRate => format!("{:.2} Hz", self.tremolo_rate()),
+            ParamId::TremoloDepth => format!("{:.0}%", self.tremolo_depth.get() * 100.0),
+            ParamId::TremoloShape => self.tremolo_shape().name().to_string(),
+            ParamId::FmSource => self.fm_source().name().to_string(),
+            ParamId::FmTarget => self.fm_target().name().to_string(),
+            ParamId::FmAmount => format!("{:.0}%", self.fm_amount.get() * 100.0),
+            ParamId::Bypass => if self.bypass.get() > 0.5 { "On" } else { "Off" }.to_string(),
+            ParamId::SourceMode => SourceMode::from_parameter(self.source_mode.get())
+                .name()
+                .to_string(),
+            ParamId::SelectedSource => self.selected_source().name().to_string(),
+            ParamId::Normalize => if self.normalize.get() > 0.5 { "On" } else { "Off" }.to_string(),
+            ParamId::Freeze => if self.freeze() { "On" } else { "Off" }.to_string(),
+            ParamId::Width => format!("{:.0}%", self.width() * 100.0),
+            ParamId::HpCutoff => match self.hp_cutoff() {
+                Some(cutoff) => format!("{:.0} Hz", cutoff),
+                None => "Off".to_string(),
+            },
+            ParamId::HpResonance => format!("Q {:.2}", self.hp_q()),
+            ParamId::AttackCurve => EnvelopeCurve::from_parameter(self.attack_curve.get())
+                .name()
+                .to_string(),
+            ParamId::ReleaseCurve => EnvelopeCurve::from_parameter(self.release_curve.get())
+                .name()
+                .to_string(),
+            ParamId::Humanize => format!("{:.0}%", self.humanize.get() * 100.0),
+            ParamId::ReverbSize => format!("{:.0}%", self.reverb_size.get() * 100.0),
+            ParamId::ReverbDamp => format!("{:.0}%", self.reverb_damp.get() * 100.0),
+            ParamId::ReverbMix => format!("{:.0}%", self.reverb_mix.get() * 100.0),
+            ParamId::GateThreshold => match self.gate_threshold() {
+                Some(threshold) => format!("{:.1} dB", threshold),
+                None => "Off".to_string(),
+            },
+            ParamId::GateAttack => format!("{:.1} ms", self.gate_attack() * 1000.0),
+            ParamId::GateRelease => format!("{:.0} ms", self.gate_release() * 1000.0),
+            ParamId::DelaySpread => format!("{:.0}%", self.delay_spread.get() * 100.0),
+            ParamId::Ceiling => format!("{:+.1} dB", self.ceiling_db()),
+            ParamId::ScaleSnap => self.scale_snap().name().to_string(),
+            ParamId::ExciteLength => format!("{:.1} ms", self.excite_length() * 1000.0),
+            ParamId::RelVelAmount => format!("{:.0}%", self.rel_vel_amount.get() * 100.0),
+            ParamId::Transpose => format!("{:+} st", self.transpose()),
+            ParamId::GlobalMix => format!("{:.0}%", self.global_mix.get() * 100.0),
+            ParamId::Drift => format!("{:.0}%", self.drift.get() * 100.0),
+            ParamId::TuningMode => self.tuning_mode().name().to_string(),
+            ParamId::HaasDelay => match self.haas_delay() {
+                0.0 => "Off".to_string(),
+                delay => format!("{:.1} ms", delay * 1000.0),
+            },
+            ParamId::ArpOn => if self.arp_on() { "On" } else { "Off" }.to_string(),
+            ParamId::ArpRate => self.arp_rate().name().to_string(),
+            ParamId::ArpMode => self.arp_mode().name().to_string(),
+            ParamId::ArpOctaves => self.arp_octaves().to_string(),
+            ParamId::VelToTimbre => format!("{:.0}%", self.vel_to_timbre.get() * 100.0),
+            ParamId::DelayPingPong => if self.delay_pingpong() { "On" } else { "Off" }.to_string(),
+            ParamId::AutoGain => format!("{:.0}%", self.auto_gain.get() * 100.0),
+            ParamId::OneShot => if self.one_shot() { "On" } else { "Off" }.to_string(),
+            ParamId::NoiseSmoothing => {
+                if self.noise_smoothing() { "On" } else { "Off" }.to_string()
+            }
+            ParamId::Exciter => if self.exciter() { "Input" } else { "Noise" }.to_string(),
+            ParamId::VelToBrightness => format!("{:.0}%", self.vel_to_brightness.get() * 100.0),
+            ParamId::InvertLeft => if self.invert()[0] { "On" } else { "Off" }.to_string(),
+            ParamId::InvertRight => if self.invert()[1] { "On" } else { "Off" }.to_string(),
+            ParamId::Drone => if self.drone() { "On" } else { "Off" }.to_string(),
+            ParamId::KeyboardPan => format!("{:.0}%", self.keyboard_pan.get() * 100.0),
+            ParamId::Mpe => if self.mpe() { "On" } else { "Off" }.to_string(),
+            ParamId::CompThreshold => format!("{:.1} dB", self.comp_threshold_db()),
+            ParamId::CompRatio => format!("{:.1}:1", self.comp_ratio()),
+            ParamId::CompAttack => format!("{:.1} ms", self.comp_attack() * 1000.0),
+            ParamId::CompRelease => format!("{:.0} ms", self.comp_release() * 1000.0),
+            ParamId::CompMakeup => format!("{:.1} dB", self.comp_makeup_db()),
+            ParamId::StringLevel => format!("{:.0}%", self.string_level.get() * 100.0),
+            ParamId::NoiseLevel => format!("{:.0}%", self.noise_level.get() * 100.0),
+            ParamId::DecayTime => match self.decay_time() {
+                Some(decay) => format!("{:.2} s", decay),
+                None => "Off".to_string(),
+            },
+            // The amounts, rates and enables, which are shown above.
+            _ => "".to_string(),
+        }
+    }
+
+    /// Presets are saved as every parameter's value in index order, followed by the parameter
+    /// bound to each CC, all as little-endian 32 bit words.
+    fn get_preset_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(PARAMETER_COUNT as u32).to_le_bytes());
+        for index in 0..PARAMETER_COUNT {
+            data.extend_from_slice(&self.get_parameter(index).to_le_bytes());
+        }
+        for binding in self.cc_bindings.iter() {
+            data.extend_from_slice(&(binding.load(Ordering::Relaxed) as i32).to_le_bytes());
+        }
+        data
+    }
+
+    /// Load a preset saved by `get_preset_data`.
+    ///
+    /// Presets saved before parameters were added leave the newer ones untouched, and anything
+    /// cut short is read as far as it goes.
+    fn load_preset_data(&self, data: &[u8]) {
+        let mut words = data
+            .chunks(4)
+            .filter(|word| word.len() == 4)
+            .map(|word| [word[0], word[1], word[2], word[3]]);
+        let count = match words.next() {
+            Some(count) => u32::from_le_bytes(count) as i32,
+            None => return,
+        };
+        for index in 0..count {
+            match words.next() {
+                Some(value) => self.set_parameter(index, f32::from_le_bytes(value)),
+                None => return,
+            }
+        }
+        for (binding, index) in self.cc_bindings.iter().zip(words) {
+            let index = i32::from_le_bytes(index);
+            let index = if (0..PARAMETER_COUNT).contains(&index) { index } else { -1 };
+            binding.store(index as isize, Ordering::Relaxed);
+        }
+    }
+
+    /// The factory presets are built in rather than stored, so a bank is saved as the current
+    /// state alone, the same way as a preset.
+    fn get_bank_data(&self) -> Vec<u8> {
+        self.get_preset_data()
+    }
+
+    fn load_bank_data(&self, data: &[u8]) {
+        self.load_preset_data(data)
+    }
+}
+
+/// How long a voice takes to fade out after its key is released, in seconds.
+const RELEASE: f64 = 0.1;
+
+/// The longest a voice can take to fade in, in seconds.
+const MAX_ATTACK: f64 = 2.0;
+
+/// The furthest the keyboard can be transposed either way, in semitones, and the steps that
+/// spans.
+const MAX_TRANSPOSE: i32 = 24;
+const TRANSPOSE_STEPS: usize = MAX_TRANSPOSE as usize * 2 + 1;
+
+/// How many octaves faster, or slower, the hardest, or gentlest, note-off can make a release at
+/// the full release velocity amount.
+const RELEASE_VELOCITY_RANGE: f64 = 2.0;
+
+/// The tempo the arpeggiator keeps to when the host has not told us, in beats per minute.
+const DEFAULT_TEMPO: f64 = 120.0;
+
+/// The release velocity a note-off sent as a note-on with no velocity is taken to have.
+const DEFAULT_RELEASE_VELOCITY: u8 = 64;
+
+/// How long every voice takes to fade in from silence whatever its attack, in seconds, so the
+/// string and the noise never start with a click.
+const ONSET: f64 = 0.001;
+
+/// How far a full pitch bend takes the voices on its channel in MPE, in semitones. This is
+/// MPE's own default for the note channels.
+const MPE_BEND_RANGE: f64 = 48.0;
+
+/// The furthest humanize can tune a voice either side of its key, in cents.
+const MAX_HUMANIZE_CENTS: f64 = 10.0;
+
+/// The longest humanize can hold back the start of a voice, in seconds.
+const MAX_HUMANIZE_DELAY: f64 = 0.01;
+
+/// The furthest drift can wander a voice either side of its pitch, in cents.
+const MAX_DRIFT_CENTS: f64 = 15.0;
+
+/// How often drift picks a new pitch for each voice to wander towards, in hz.
+const DRIFT_RATE: f64 = 0.5;
+
+/// The most voices that can sound at once. Past it each new note takes over the quietest.
+const MAX_VOICES: usize = 64;
+
+/// How long auto gain takes to come back up once fewer voices are sounding, in seconds. It goes
+/// down at once, so a chord's strike is never louder than a single note's.
+const AUTO_GAIN_RELEASE: f64 = 0.05;
+
+/// How long the output takes to follow the channel volume and expression, in seconds, so a
+/// controller's steps are not heard.
+const VOLUME_SMOOTHING: f64 = 0.005;
+
+/// How many harmonics of a voice's pitch noise smoothing lets through from the stepped
+/// sources.
+const SMOOTHING_HARMONICS: f64 = 8.0;
+
+/// The lowest pitch a string can be plucked at, in hz: the bottom key, humanized flat.
+fn lowest_frequency() -> f64 {
+    midi_pitch_to_freq(0) * cents_to_ratio(-MAX_HUMANIZE_CENTS)
+}
+
+/// A single sounding voice.
+struct Note {
+    /// The midi pitch that triggered the voice.
+    note: u8,
+    /// Set once the key has been let go, after which the voice fades out over `RELEASE`.
+    released: bool,
+    /// How far through its envelope the voice is, which rises to `1.0` over the attack while the
+    /// key is held and falls back to `0.0` once it is let go. See `envelope` for the level.
+    alpha: f64,
+    /// How far the voice has travelled through the coherent noise sources, in cycles, from the
+    /// random point it started at.
+    phase: f64,
+    /// How far the voice has drifted along the third axis of the coherent noise sources.
+    drift: f64,
+    /// The key's poly aftertouch, `0.0..=1.0`.
+    pressure: f64,
+    /// The gain set by the note's velocity.
+    velocity: f64,
+    /// The ratio the voice is tuned away from its key by, to snap it onto the scale and by
+    /// humanize.
+    detune: f64,
+    /// The midi channel the voice was struck on, and the ratio the pitch bends on that channel
+    /// have taken it away from its pitch by in MPE.
+    channel: u8,
+    bend: f64,
+    /// Samples humanize is holding the voice back for before it starts.
+    wait: usize,
+    /// How much faster than `RELEASE` the voice fades once let go, from its release velocity.
+    release_speed: f64,
+    /// The fade in over `ONSET`, from `0.0` on the voice's first sample to `1.0`.
+    onset: f64,
+    /// The pitches drift is wandering from and towards, `-1.0..=1.0` of `MAX_DRIFT_CENTS`, and
+    /// how far between them it has got.
+    wander: [f64; 2],
+    wandered: f64,
+    /// Each channel's stepped noise sources after noise smoothing's low-pass.
+    smoothed: [f32; 2],
+    string: KarplusString,
+}
+
+impl Note {
+    /// A voice for `note` ringing on `string`, which is plucked already.
+    fn new(note: u8, detune: f64, velocity: f64, string: KarplusString) -> Note {
+        Note {
+            note,
+            velocity,
+            detune,
+            channel: 0,
+            bend: 1.0,
+            wait: 0,
+            release_speed: 1.0,
+            onset: 0.0,
+            wander: [0.0; 2],
+            wandered: 0.0,
+            smoothed: [0.0; 2],
+            released: false,
+            alpha: 1.0,
+            phase: 0.0,
+            drift: 0.0,
+            pressure: 0.0,
+            string,
+        }
+    }
+
+    /// Whether the voice has faded all the way out since it was let go, and can be taken
+    /// away.
+    fn finished(&self) -> bool {
+        self.released && self.alpha <= 0.0 && self.wait == 0
+    }
+
+    /// The pitch the voice is tuned to, in hz, before the vibrato and drift.
+    fn frequency(&self) -> f64 {
+        midi_pitch_to_freq(self.note) * self.detune * self.bend
+    }
+
+    /// Move the voice `distance` of the way on towards its next drifting pitch, and return how
+    /// far it has drifted from its own at full drift, in cents. Each new pitch is drawn from
+    /// `rng`.
+    fn wander(&mut self, distance: f64, rng: &mut XorShiftRng) -> f64 {
+        self.wandered += distance;
+        if self.wandered >= 1.0 {
+            self.wandered -= 1.0;
+            self.wander = [self.wander[1], rng.gen::<f64>() * 2.0 - 1.0];
+        }
+        // Eased in and out of each pitch, so the voice turns round smoothly.
+        let eased = self.wandered * self.wandered * (3.0 - 2.0 * self.wandered);
+        let [from, to] = self.wander;
+        (from + (to - from) * eased) * MAX_DRIFT_CENTS
+    }
+
+    /// The voice's level from its envelope alone, with `alpha` read through `attack` while the
+    /// key is held and `release` once it has been let go, and faded in over its onset.
+    fn envelope(&self, attack: EnvelopeCurve, release: EnvelopeCurve) -> f64 {
+        let curve = if self.released { release } else { attack };
+        curve.apply(self.alpha) * self.onset
+    }
+
+    /// The voice's output level, from its `envelope`.
+    ///
+    /// `boost` is the amplitude boost from the controllers shared by every voice, and
+    /// `pressure_target` where aftertouch is routed. Poly aftertouch only reaches the
+    /// amplitude of its own voice; the other destinations are shared and follow channel
+    /// aftertouch alone.
+    fn level(&self, envelope: f64, boost: f64, pressure_target: Destination) -> f64 {
+        let boost = if pressure_target == Destination::Amplitude {
+            open_up(boost, self.pressure)
+        } else {
+            boost
+        };
+        envelope * self.velocity * (1.0 + boost)
+    }
+
+    /// The damping for this voice's string, with `key_track` scaling the loss by pitch.
+    fn damping(&self, damping: f32, key_track: f32) -> f32 {
+        let octaves = (f32::from(self.note) - 60.0) / 12.0;
+        let loss = (1.0 - damping) * (key_track * octaves).exp2();
+        (1.0 - loss).max(0.0)
+    }
+
+    /// The decay time for this voice's string, in seconds, with `key_track` shortening it by
+    /// pitch as `damping` scales the loss.
+    fn decay(&self, decay: f64, key_track: f32) -> f64 {
+        let octaves = (f64::from(self.note) - 60.0) / 12.0;
+        decay / (f64::from(key_track) * octaves).exp2()
+    }
+
+    /// The voice's gain on the left and right, placing it across the stereo field by its key
+    /// with `amount`, `0.0..=1.0`, of spread. The power is kept even, so a voice in the
+    /// middle is at full level on both sides.
+    fn pan(&self, amount: f64) -> [f64; 2] {
+        let position = (amount * (f64::from(self.note) - 64.0) / 63.0).clamp(-1.0, 1.0);
+        let angle = (position + 1.0) * PI / 4.0;
+        [angle.cos() * SQRT_2, angle.sin() * SQRT_2]
+    }
+}
+
+/// How much of each noise source goes into the voices over one block.
+///
+/// Everything but the LFO holds still for a block, so the parameters are read once at the start
+/// and the sources that cannot be heard are left out of the loop altogether.
+struct NoiseBlend {
+    /// Each source's amount before the LFO, indexed by `NoiseSource`.
+    amounts: [f64; NOISE_SOURCES],
+    lfo_target: usize,
+    lfo_depth: f64,
+    lfo_shape: Shape,
+    /// The sources that can be heard at all this block, in the first `active_count` places.
+    active: [NoiseSource; NOISE_SOURCES],
+    active_count: usize,
+}
+
+impl NoiseBlend {
+    /// Every source's amount with the LFO at its current value.
+    fn amounts(&self, lfo: &Phasor) -> [f64; NOISE_SOURCES] {
+        let mut amounts = self.amounts;
+        let modulation = self.lfo_depth * lfo.value(self.lfo_shape);
+        let target = &mut amounts[self.lfo_target];
+        *target = (*target + modulation).clamp(0.0, 1.0);
+        amounts
+    }
+
+    fn active(&self) -> &[NoiseSource] {
+        &self.active[..self.active_count]
+    }
+}
+
+/// How far apart the channels read the coherent noise sources, so each gets its own texture.
+const CHANNEL_SPREAD: f64 = 100.5;
+
+/// The furthest into the coherent noise sources a voice can start when `voice_offset` is on.
+const VOICE_SPREAD: f64 = 10000.0;
+
+/// How far round `VOICE_SPREAD` each strike starts from the last, as a fraction of it. Stepping
+/// by the golden ratio keeps however many strikes come in a row evenly spread apart.
+const STRIKE_STEP: f64 = 0.618_033_988_749_894_8;
+
+/// The seed every random draw starts from unless the synth is reseeded, so that a session plays
+/// back the same each time it is opened.
+pub const SEED: u32 = 0x2545_F491;
+
+/// One of the independent streams `seed` is split into, as the state of an xorshift generator,
+/// by SplitMix64. The synth's own draws are stream `0` and each channel's noise the streams after.
+fn seed_words(seed: u32, stream: u32) -> [u32; 4] {
+    let mut state = u64::from(seed) << 32 | u64::from(stream);
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let (a, b) = (next(), next());
+    // An xorshift generator can't start from all zeroes.
+    [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32 | 1]
+}
+
+/// How fast each voice moves along the third axis of the coherent noise sources in 3D mode, in
+/// cycles per second.
+const NOISE_DRIFT: f64 = 1.0;
+
+/// How often the voices are reported with the `diagnostics` feature on, in seconds.
+#[cfg(feature = "diagnostics")]
+const REPORT_INTERVAL: f64 = 1.0;
+
+/// The part of the signal path kept apart for each side of the stereo output.
+struct Channel {
+    generators: NoiseGenerators,
+    crusher: BitCrusher,
+    high_pass: HighPass,
+    /// One resampler for each `Oversampling` setting, so switching between them never
+    /// allocates.
+    oversamplers: Vec<Oversampler>,
+    /// The same again for bringing the voices down before any effect, for the global mix.
+    dry_oversamplers: Vec<Oversampler>,
+    /// Scratch space the voices are mixed into, one block long at the highest oversampling.
+    mix: Vec<f32>,
+}
+
+impl Channel {
+    /// A channel whose noise is drawn from `seed`.
+    fn new(seed: [u32; 4]) -> Channel {
+        Channel {
+            generators: NoiseGenerators::new(seed),
+            crusher: BitCrusher::new(),
+            high_pass: HighPass::new(),
+            oversamplers: Oversampling::ALL
+                .iter()
+                .map(|oversampling| Oversampler::new(oversampling.factor()))
+                .collect(),
+            dry_oversamplers: Oversampling::ALL
+                .iter()
+                .map(|oversampling| Oversampler::new(oversampling.factor()))
+                .collect(),
+            mix: vec![0.0; DEFAULT_BLOCK_SIZE * MAX_OVERSAMPLING],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.generators.reset();
+        self.crusher.reset();
+        self.high_pass.reset();
+        for oversampler in self.oversamplers.iter_mut().chain(self.dry_oversamplers.iter_mut()) {
+            oversampler.reset();
+        }
+    }
+}
+
+/// The levels carried across each block from where the last one left them, so automating them
+/// sweeps rather than steps.
+struct Ramps {
+    ring_mod: Ramp,
+    chorus_mix: Ramp,
+    delay_mix: Ramp,
+    reverb_mix: Ramp,
+    tremolo_depth: Ramp,
+    width: Ramp,
+    global_mix: Ramp,
+}
+
+impl Ramps {
+    fn new() -> Ramps {
+        Ramps {
+            ring_mod: Ramp::new(),
+            chorus_mix: Ramp::new(),
+            delay_mix: Ramp::new(),
+            reverb_mix: Ramp::new(),
+            tremolo_depth: Ramp::new(),
+            width: Ramp::new(),
+            global_mix: Ramp::new(),
+        }
+    }
+
+    /// Head for the levels in `params` over the next `samples` samples.
+    fn set(&mut self, params: &SynthParameters, samples: usize) {
+        self.ring_mod.set(params.ring_mod.get(), samples);
+        self.chorus_mix.set(params.chorus_mix.get(), samples);
+        self.delay_mix.set(params.delay_mix.get(), samples);
+        self.reverb_mix.set(params.reverb_mix.get(), samples);
+        self.tremolo_depth.set(params.tremolo_depth.get(), samples);
+        self.width.set(params.width(), samples);
+        self.global_mix.set(params.global_mix.get(), samples);
+    }
+
+    /// Jump to the levels next set, with nothing to ramp from.
+    fn reset(&mut self) {
+        self.ring_mod.reset();
+        self.chorus_mix.reset();
+        self.delay_mix.reset();
+        self.reverb_mix.reset();
+        self.tremolo_depth.reset();
+        self.width.reset();
+        self.global_mix.reset();
+    }
+}
+
+/// What a sounding voice is doing, as read back by `SineSynth::active_notes`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteInfo {
+    /// The midi key that struck the voice.
+    pub note: u8,
+    /// How far through its envelope the voice is, from `0.0` to `1.0`.
+    pub alpha: f64,
+    /// The gain set by the strike's velocity.
+    pub velocity: f64,
+    /// Whether the key has been let go, so the voice is fading out.
+    pub released: bool,
+}
+
+pub struct SineSynth {
+    host: HostCallback,
+    sample_rate: f64,
+    /// The host's tempo in beats per minute, if it has told us or is sending midi clock.
+    tempo: Option<f64>,
+    clock: MidiClock,
+    /// Sounding voices, oldest first, never more than `MAX_VOICES`.
+    notes: Vec<Note>,
+    /// Strings not sounding, each with room for the lowest pitch, so a new voice takes one
+    /// over instead of allocating its own. There is one more than `MAX_VOICES`, for the voice
+    /// struck while the one it replaces still holds its string.
+    strings: Vec<KarplusString>,
+    params: Arc<SynthParameters>,
+    /// Draws humanize's nudges, drift's pitches, the arpeggiator's random steps and the noise
+    /// each string is plucked with.
+    rng: XorShiftRng,
+    /// What `rng` and the channels' noise were seeded from, and are seeded from again on a
+    /// reset.
+    seed: u32,
+    /// The voices struck since the plugin was created or reset, which sets where in the
+    /// coherent noise sources the next one starts.
+    strikes: u32,
+    /// The last position of the mod wheel (CC 1), `0.0..=1.0`.
+    mod_wheel: f64,
+    /// The last channel volume (CC 7) and expression (CC 11), `0.0..=1.0`, which together
+    /// turn the output down.
+    volume: f64,
+    expression: f64,
+    /// The gain they set, following them over `VOLUME_SMOOTHING`.
+    channel_gain: f32,
+    /// The last channel aftertouch, `0.0..=1.0`.
+    pressure: f64,
+    lfo: Phasor,
+    vibrato: Phasor,
+    /// The ring modulator's carrier, run at audio rate.
+    ring: Phasor,
+    tremolo: Phasor,
+    /// The gain auto gain is applying to the voices summed.
+    voice_gain: f64,
+    /// The left and right channels. Only the left is rendered in `OutputMode::Mono`.
+    channels: [Channel; 2],
+    /// The left and right echo lines.
+    delays: [Delay; 2],
+    /// The right side's hold-back for the Haas effect.
+    haas: Delay,
+    chorus: Chorus,
+    reverb: Reverb,
+    compressor: Compressor,
+    ramps: Ramps,
+    gate: Gate,
+    arp: Arpeggiator,
+    /// The key the arpeggiator is sounding, and the samples left until its next step.
+    arp_voice: Option<u8>,
+    arp_countdown: usize,
+    /// The bend last sent on each midi channel in MPE, as a ratio, which voices struck on it
+    /// start out with.
+    channel_bends: [f64; 16],
+    /// The oversampling the last block was run at.
+    oversampling: Oversampling,
+    /// How many channels the last block rendered.
+    rendered: usize,
+    /// Whether the last block was bypassed.
+    bypassed: bool,
+    /// Samples processed since the voices were last reported.
+    #[cfg(feature = "diagnostics")]
+    since_report: usize,
+    /// How many times each `Derived` group has been worked out.
+    #[cfg(test)]
+    rebuilds: [usize; Derived::ALL.len()],
+}
+
+impl SineSynth {
+    /// Build the synth with its parameters set as in `config`.
+    pub fn with_config(host: HostCallback, config: &SynthConfig) -> SineSynth {
+        SineSynth {
+            host,
+            sample_rate: 44100.0,
+            tempo: None,
+            clock: MidiClock::new(),
+            // Room for every voice up front, so however many notes the host sends the list
+            // never grows.
+            notes: Vec::with_capacity(MAX_VOICES),
+            strings: (0..=MAX_VOICES)
+                .map(|_| KarplusString::with_room(lowest_frequency(), 44100.0))
+                .collect(),
+            params: Arc::new(SynthParameters::with_config(config)),
+            mod_wheel: 0.0,
+            volume: 1.0,
+            expression: 1.0,
+            channel_gain: 1.0,
+            pressure: 0.0,
+            rng: XorShiftRng::from_seed(seed_words(SEED, 0)),
+            seed: SEED,
+            strikes: 0,
+            lfo: Phasor::new(),
+            vibrato: Phasor::new(),
+            ring: Phasor::new(),
+            tremolo: Phasor::new(),
+            voice_gain: 1.0,
+            channels: [
+                Channel::new(seed_words(SEED, 1)),
+                Channel::new(seed_words(SEED, 2)),
+            ],
+            delays: [Delay::new(44100.0), Delay::new(44100.0)],
+            haas: Delay::with_max_delay(MAX_HAAS_DELAY, 44100.0),
+            chorus: Chorus::new(44100.0),
+            reverb: Reverb::new(44100.0),
+            compressor: Compressor::new(),
+            ramps: Ramps::new(),
+            gate: Gate::new(),
+            arp: Arpeggiator::new(),
+            arp_voice: None,
+            arp_countdown: 0,
+            oversampling: Oversampling::Off,
+            channel_bends: [1.0; 16],
+            rendered: 1,
+            bypassed: false,
+            #[cfg(feature = "diagnostics")]
+            since_report: 0,
+            #[cfg(test)]
+            rebuilds: [0; Derived::ALL.len()],
+        }
+    }
+
+    /// Work out again whatever `Derived` state has had its parameters set since the last block.
+    fn rebuild(&mut self) {
+        let dirty = self.params.dirty.swap(0, Ordering::Relaxed);
+        for &group in Derived::ALL.iter().filter(|group| dirty & group.bit() != 0) {
+            #[cfg(test)]
+            {
+                self.rebuilds[group as usize] += 1;
+            }
+            let params = &self.params;
+            match group {
+                Derived::Filter => {
+                    // While it is off the filter is skipped, and tuned again when it comes on.
+                    if let Some(cutoff) = params.hp_cutoff() {
+                        let q = params.hp_q();
+                        for channel in self.channels.iter_mut() {
+                            channel.high_pass.set(cutoff, q, self.sample_rate);
+                        }
+                    }
+                }
+                Derived::Gate => {
+                    let threshold = params.gate_threshold().map_or(0.0, db_to_gain);
+                    let (attack, release) = (params.gate_attack(), params.gate_release());
+                    self.gate.set(threshold, attack, release, self.sample_rate);
+                }
+                Derived::Compressor => self.compressor.set(
+                    params.comp_threshold_db(),
+                    params.comp_ratio(),
+                    params.comp_attack(),
+                    params.comp_release(),
+                    params.comp_makeup_db(),
+                    self.sample_rate,
+                ),
+            }
+        }
+    }
+
+    /// Log the sounding voices once every `REPORT_INTERVAL`, counting `samples` towards it.
+    ///
+    /// The log macros only format when a logger wants the record, so nothing here allocates.
+    #[cfg(feature = "diagnostics")]
+    fn report_voices(&mut self, samples: usize) {
+        self.since_report += samples;
+        if self.since_report < (REPORT_INTERVAL * self.sample_rate) as usize {
+            return;
+        }
+        self.since_report = 0;
+        info!("{} voices", self.notes.len());
+        for note in self.notes.iter() {
+            let state = if note.released { "released" } else { "held" };
+            debug!("note {}: alpha {:.3}, {}", note.note, note.alpha, state);
+        }
+    }
+
+    /// A snapshot of every sounding voice, oldest first, for displays and tests to look at
+    /// without listening to the output.
+    ///
+    /// This allocates, so it is not for calling from the audio thread.
+    pub fn active_notes(&self) -> Vec<NoteInfo> {
+        self.notes
+            .iter()
+            .map(|note| NoteInfo {
+                note: note.note,
+                alpha: note.alpha,
+                velocity: note.velocity,
+                released: note.released,
+            })
+            .collect()
+    }
+
+    /// Seed every random draw from `seed` in place of `SEED`: the strings' noise, humanize,
+    /// drift, the arpeggiator and the stochastic noise sources, and count the strikes from the
+    /// first again. The same seed, given the same notes in the same order, plays the same.
+    pub fn reseed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.strikes = 0;
+        self.rng.reseed(seed_words(seed, 0));
+        for (stream, channel) in (1..).zip(self.channels.iter_mut()) {
+            channel.generators.reseed(seed_words(seed, stream));
+        }
+    }
+
+    fn time_per_sample(&self) -> f64 {
+        1.0 / self.sample_rate
+    }
+
+    /// Ask the host for its tempo, falling back on its midi clock. Without a host or a clock,
+    /// as in the tests, the last tempo is kept.
+    fn update_tempo(&mut self) {
+        let clock = self.clock.tempo(self.sample_rate);
+        if self.host.raw_callback().is_none() {
+            self.tempo = clock.or(self.tempo);
+            return;
+        }
+        let tempo_valid = TimeInfoFlags::TEMPO_VALID.bits();
+        self.tempo = self
+            .host
+            .get_time_info(tempo_valid)
+            .filter(|info| info.flags & tempo_valid != 0 && info.tempo > 0.0)
+            .map(|info| info.tempo)
+            .or(clock);
+    }
+
+    /// The LFO rate in hz, locked to the tempo if `lfo_sync` is set and the tempo is known.
+    fn lfo_rate(&self) -> f64 {
+        match self.tempo.and_then(|tempo| self.params.lfo_sync().seconds(tempo)) {
+            Some(period) => 1.0 / period,
+            None => self.params.lfo_rate(),
+        }
+    }
+
+    /// The time between echoes in seconds, locked to the tempo if `delay_sync` is set and the
+    /// tempo is known.
+    fn delay_time(&self) -> f64 {
+        match self.tempo.and_then(|tempo| self.params.delay_sync().seconds(tempo)) {
+            Some(time) => time.min(MAX_DELAY),
+            None => self.params.delay_time(),
+        }
+    }
+
+    /// The left and right echo lines' times, in seconds.
+    fn delay_times(&self) -> [f64; 2] {
+        let time = self.delay_time();
+        [time, (time * self.params.delay_spread()).min(MAX_DELAY)]
+    }
+
+    /// The blend of noise sources for the coming block.
+    ///
+    /// In `SourceMode::Single` the selected source is at its full amount, and every other
+    /// source stays at zero whatever is modulating it.
+    fn noise_blend(&self) -> NoiseBlend {
+        let single = match SourceMode::from_parameter(self.params.source_mode.get()) {
+            SourceMode::Blend => None,
+            SourceMode::Single => Some(self.params.selected_source() as usize),
+        };
+        let mut amounts = [0.0; NOISE_SOURCES];
+        for (i, amount) in amounts.iter_mut().enumerate() {
+            // Turned off, a source is left out of the blend whatever its amount.
+            if !self.params.source_enabled(NoiseSource::ALL[i]) {
+                continue;
+            }
+            let value = match single {
+                Some(selected) if selected == i => 1.0,
+                Some(_) => continue,
+                None => f64::from(self.params.amounts[i].get()),
+            };
+            *amount = self.modulate(Destination::Amount(NoiseSource::ALL[i]), value);
+        }
+
+        let total: f64 = amounts.iter().sum();
+        if self.params.normalize.get() > 0.5 && total > 0.0 {
+            for amount in amounts.iter_mut() {
+                *amount /= total;
+            }
+        }
+
+        let lfo_target = self.params.lfo_target() as usize;
+        let lfo_depth = if single.unwrap_or(lfo_target) == lfo_target {
+            self.modulate(Destination::LfoDepth, f64::from(self.params.lfo_depth.get()))
+        } else {
+            0.0
+        };
+
+        let mut blend = NoiseBlend {
+            amounts,
+            lfo_target,
+            lfo_depth,
+            lfo_shape: self.params.lfo_shape(),
+            active: [NoiseSource::White; NOISE_SOURCES],
+            active_count: 0,
+        };
+        for (i, &source) in NoiseSource::ALL.iter().enumerate() {
+            let wobbled = i == lfo_target && lfo_depth > 0.0;
+            if amounts[i] > 0.0 || (wobbled && self.params.source_enabled(source)) {
+                blend.active[blend.active_count] = source;
+                blend.active_count += 1;
+            }
+        }
+        blend
+    }
+
+    /// How far the vibrato bends every voice either side of its played pitch, in cents.
+    fn vibrato_depth(&self) -> f64 {
+        let depth = f64::from(self.params.vibrato_depth.get());
+        self.modulate(Destination::VibratoDepth, depth) * VIBRATO_MAX_DEPTH
+    }
+
+    /// Apply the mod wheel and channel aftertouch to the normalized `value` of `destination`,
+    /// for whichever of them is routed there.
+    ///
+    /// Each controller opens the destination up from its set value towards its maximum, so
+    /// with the controllers all the way down the destination is left alone.
+    fn modulate(&self, destination: Destination, value: f64) -> f64 {
+        let mut value = value;
+        if self.params.mod_wheel_target() == destination {
+            value = open_up(value, self.mod_wheel);
+        }
+        if self.params.pressure_target() == destination {
+            value = open_up(value, self.pressure);
+        }
+        value
+    }
+
+    /// Mix `len` samples of every voice into the `mix` of the first `channels` channels at
+    /// `oversampling`, moving the voices, noise sources and oscillators on as it goes.
+    ///
+    /// The strings and the stochastic noise sources are produced at the host's rate and
+    /// upsampled, while the coherent noise sources are read straight off their coordinates at
+    /// the higher rate. The strings are shared by every channel; the noise is drawn for each.
+    ///
+    /// With the exciter on, `input` holds the `len` samples of audio coming in on each side, or
+    /// nothing for a side that is not connected.
+    fn render_voices(
+        &mut self,
+        len: usize,
+        oversampling: Oversampling,
+        channels: usize,
+        input: [&[f32]; 2],
+    ) {
+        let factor = oversampling.factor();
+        let per_sample = self.time_per_sample();
+        let release_per_sample = per_sample / RELEASE;
+        let attack = self.params.attack();
+        let attack_per_sample = if attack > 0.0 { per_sample / attack } else { 1.0 };
+        let attack_curve = self.params.attack_curve();
+        let onset_per_sample = per_sample / ONSET;
+        let release_curve = self.params.release_curve();
+        let lfo_rate = self.lfo_rate();
+        let vibrato_rate = self.params.vibrato_rate();
+        let pressure_target = self.params.pressure_target();
+        let damping = self.params.damping();
+        let decay_time = self.params.decay_time();
+        let key_track = self.params.damping_key_track();
+        // Only the stereo output has anywhere to pan to.
+        let output_mode = OutputMode::from_parameter(self.params.output_mode.get());
+        let keyboard_pan = if output_mode == OutputMode::Stereo {
+            f64::from(self.params.keyboard_pan.get())
+        } else {
+            0.0
+        };
+        let noise_3d = self.params.noise_3d();
+        let frozen = self.params.freeze();
+        // Frozen, the voices stay where they are in the noise.
+        let drift = if frozen { 0.0 } else { NOISE_DRIFT * per_sample };
+        let pitch_drift = f64::from(self.params.drift.get());
+        let drift_per_sample = DRIFT_RATE * per_sample;
+        let mut ratios = [1.0; NOISE_SOURCES];
+        for (ratio, &source) in ratios.iter_mut().zip(NoiseSource::ALL.iter()) {
+            *ratio = self.params.noise_ratio(source);
+        }
+        let fm_source = self.params.fm_source();
+        let fm_target = self.params.fm_target();
+        // A source turned off is not read even to modulate another.
+        let fm_depth = if self.params.source_enabled(fm_source) {
+            f64::from(self.params.fm_amount.get()) * MAX_FM_DEPTH
+        } else {
+            0.0
+        };
+        let mut enabled = [true; NOISE_SOURCES];
+        for (enabled, &source) in enabled.iter_mut().zip(NoiseSource::ALL.iter()) {
+            *enabled = self.params.source_enabled(source);
+        }
+        let blend = self.noise_blend();
+        let vel_to_timbre = f64::from(self.params.vel_to_timbre.get());
+        let auto_gain = f64::from(self.params.auto_gain.get());
+        let auto_gain_kept = (-per_sample / AUTO_GAIN_RELEASE).exp();
+        // A drone holds on even in one-shot.
+        let one_shot = self.params.one_shot() && !self.params.drone();
+        let smoothing = self.params.noise_smoothing();
+        let exciter = self.params.exciter();
+        let string_level = f64::from(self.params.string_level.get());
+        let noise_level = f64::from(self.params.noise_level.get());
+        let oversampled_rate = self.sample_rate * factor as f64;
+        // The controllers only move between blocks, so nothing in the loop need go back to
+        // the parameters.
+        let vibrato_depth = self.vibrato_depth();
+        let boost = self.modulate(Destination::Amplitude, 0.0);
+
+        for sample_idx in 0..len {
+            let mut amounts = blend.amounts(&self.lfo);
+            for amount in amounts.iter_mut() {
+                *amount *= noise_level;
+            }
+            let vibrato = cents_to_ratio(vibrato_depth * self.vibrato.value(Shape::Sine));
+            let mut dry = [0.0f32; 2];
+            let mut coherent = [[0.0f32; MAX_OVERSAMPLING]; 2];
+            let mut sounding = 0;
+            let excitation = if exciter {
+                let sides = input.iter().filter_map(|side| side.get(sample_idx));
+                let (sum, count) = sides.fold((0.0, 0), |(sum, count), &s| (sum + s, count + 1));
+                if count > 0 {
+                    sum / count as f32
+                } else {
+                    0.0
+                }
+            } else {
+                0.0
+            };
+            for note in &mut self.notes {
+                if note.finished() {
+                    continue;
+                }
+                if note.wait > 0 {
+                    note.wait -= 1;
+                    continue;
+                }
+                sounding += 1;
+                if note.released {
+                    // Stopped at nothing rather than past it, so the voice's last sample is
+                    // silent and taking it away is not heard.
+                    note.alpha = (note.alpha - release_per_sample * note.release_speed).max(0.0);
+                } else {
+                    note.alpha = (note.alpha + attack_per_sample).min(1.0);
+                    // Fully up, a one-shot voice fades straight back out over `RELEASE`.
+                    if one_shot && note.alpha >= 1.0 {
+                        note.released = true;
+                    }
+                }
+                let envelope = note.envelope(attack_curve, release_curve);
+                let level = note.level(envelope, boost, pressure_target);
+                note.onset = (note.onset + onset_per_sample).min(1.0);
+                let cents = if pitch_drift > 0.0 {
+                    // Frozen, the voice stays where it has drifted to.
+                    let distance = if frozen { 0.0 } else { drift_per_sample };
+                    note.wander(distance, &mut self.rng) * pitch_drift
+                } else {
+                    0.0
+                };
+                let pitch = note.frequency() * vibrato * cents_to_ratio(cents);
+                let step = if frozen { 0.0 } else { pitch * per_sample };
+                note.string.retune(pitch, self.sample_rate);
+                let damping = match decay_time {
+                    Some(decay) => note.string.decay_damping(note.decay(decay, key_track)),
+                    None => note.damping(damping, key_track),
+                };
+                // Let in as much as the loop lets go, so the string is no louder at its pitch
+                // than what comes in.
+                let string = if exciter {
+                    let input = excitation * (1.0 - damping).max(0.0);
+                    f64::from(note.string.drive(damping, input))
+                } else {
+                    f64::from(note.string.sample(damping))
+                } * string_level;
+                let mut amounts = amounts;
+                if vel_to_timbre > 0.0 {
+                    let tilt = vel_to_timbre * (2.0 * note.velocity - 1.0);
+                    for (amount, &source) in amounts.iter_mut().zip(NoiseSource::ALL.iter()) {
+                        *amount *= (1.0 + tilt * source.brightness()).max(0.0);
+                    }
+                }
+                let smoothing = if smoothing {
+                    1.0 - (-TAU * SMOOTHING_HARMONICS * note.frequency() / oversampled_rate).exp()
+                } else {
+                    0.0
+                };
+                let pan = if keyboard_pan > 0.0 {
+                    note.pan(keyboard_pan)
+                } else {
+                    [1.0; 2]
+                };
+                for (c, channel) in self.channels[..channels].iter_mut().enumerate() {
+                    let x = c as f64 * CHANNEL_SPREAD;
+                    let level = level * pan[c];
+                    let mut signal = string;
+                    let mut stepped = [0.0f32; MAX_OVERSAMPLING];
+                    for &source in blend.active() {
+                        let (amount, ratio) = (amounts[source as usize], ratios[source as usize]);
+                        if amount <= 0.0 {
+                            continue;
+                        }
+                        if source.is_stochastic() {
+                            signal += amount * channel.generators.get(source, [x, note.phase]);
+                        } else {
+                            let subs = coherent[c][..factor].iter_mut().zip(stepped.iter_mut());
+                            for (i, (sub, stepped)) in subs.enumerate() {
+                                let offset = i as f64 / factor as f64;
+                                let travelled = note.phase + step * offset;
+                                let mut phase = travelled * ratio;
+                                if source == fm_target && fm_depth > 0.0 {
+                                    let point = [x, travelled * ratios[fm_source as usize]];
+                                    phase += fm_depth * channel.generators.get(fm_source, point);
+                                }
+                                let noise = if noise_3d {
+                                    let point = [x, phase, note.drift + drift * offset];
+                                    channel.generators.get_3d(source, point)
+                                } else {
+                                    channel.generators.get(source, [x, phase])
+                                };
+                                if smoothing > 0.0 && source.is_stepped() {
+                                    *stepped += (amount * level * noise) as f32;
+                                } else {
+                                    *sub += (amount * level * noise) as f32;
+                                }
+                            }
+                        }
+                    }
+                    if smoothing > 0.0 {
+                        let smoothed = &mut note.smoothed[c];
+                        let subs = coherent[c][..factor].iter_mut().zip(stepped.iter());
+                        for (sub, &stepped) in subs {
+                            *smoothed += (stepped - *smoothed) * smoothing as f32;
+                            *smoothed = ::flush_denormal(*smoothed);
+                            *sub += *smoothed;
+                        }
+                    }
+                    dry[c] += (signal * level) as f32;
+                }
+                note.phase += step;
+                note.drift += drift;
+            }
+            if !frozen {
+                self.lfo.advance(lfo_rate, self.sample_rate);
+                self.vibrato.advance(vibrato_rate, self.sample_rate);
+            }
+            let target = f64::from(sounding.max(1) as u32).powf(-0.5 * auto_gain);
+            self.voice_gain = if target < self.voice_gain {
+                target
+            } else {
+                target + (self.voice_gain - target) * auto_gain_kept
+            };
+            let gain = self.voice_gain as f32;
+            for (c, channel) in self.channels[..channels].iter_mut().enumerate() {
+                if !frozen {
+                    channel.generators.advance(&enabled);
+                }
+                let sub = &mut channel.mix[sample_idx * factor..(sample_idx + 1) * factor];
+                channel.oversamplers[oversampling as usize].upsample(dry[c] * gain, sub);
+                for (sample, &coherent) in sub.iter_mut().zip(coherent[c].iter()) {
+                    *sample += coherent * gain;
+                }
+            }
+        }
+        // Finished voices are left where they are until the block is done, then hand their
+        // strings back to be plucked again.
+        let mut idx = 0;
+        while idx < self.notes.len() {
+            if self.notes[idx].finished() {
+                self.strings.push(self.notes.remove(idx).string);
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    /// Cut every voice off at once, without a release, as midi all-sound-off does.
+    fn all_sound_off(&mut self) {
+        self.strings.extend(self.notes.drain(..).map(|note| note.string));
+    }
+
+    /// Silence every voice and clear all oscillator, noise and effect state, as if the plugin
+    /// had just been created.
+    fn reset(&mut self) {
+        self.all_sound_off();
+        self.arp.clear();
+        self.arp_voice = None;
+        self.arp_countdown = 0;
+        self.channel_bends = [1.0; 16];
+        self.reseed(self.seed);
+        self.lfo.reset();
+        self.vibrato.reset();
+        self.ring.reset();
+        self.tremolo.reset();
+        self.voice_gain = 1.0;
+        for channel in self.channels.iter_mut() {
+            channel.reset();
+        }
+        for delay in self.delays.iter_mut() {
+            delay.clear();
+        }
+        self.haas.clear();
+        self.chorus.clear();
+        self.reverb.clear();
+        self.compressor.reset();
+        self.gate.reset();
+        self.ramps.reset();
+    }
+
+    /// Process an incoming midi event.
+    ///
+    /// The midi data is split up like so:
+    ///
+    /// `data[0]`: Contains the status and the channel. Source: [source]
+    /// `data[1]`: Contains the supplemental data for the message - so, if this was a NoteOn then
+    ///            this would contain the note.
+    /// `data[2]`: Further supplemental data. Would be velocity in the case of a NoteOn message.
+    ///
+    /// Channel messages on any channel but the one set by the `midi_channel` parameter are
+    /// dropped, unless it is set to omni.
+    ///
+    /// [source]: http://www.midimountain.com/midi/midi_status.htm
+    fn process_midi_event(&mut self, data: [u8; 3]) {
+        if data[0] < 0xF0 {
+            if let Some(channel) = self.params.midi_channel() {
+                if data[0] & 0x0F != channel {
+                    return;
+                }
+            }
+        }
+
+        let arp = self.params.arp_on();
+        match data[0] & 0xF0 {
+            // With the arpeggiator on, keys are only held down for it to play.
+            128 if arp => self.arp_release(data[1]),
+            144 if arp && data[2] == 0 => self.arp_release(data[1]),
+            144 if arp => self.arp.press(data[1], data[2]),
+            128 => self.note_off(data[1], data[2]),
+            // A note-on with no velocity is how running status sends a note-off.
+            144 if data[2] == 0 => self.note_off(data[1], DEFAULT_RELEASE_VELOCITY),
+            144 => self.note_on(data[1], data[2], data[0] & 0x0F),
+            160 => self.poly_pressure(data[1], data[2]),
+            176 => self.control_change(data[1], data[2]),
+            192 => self.params.change_preset(i32::from(data[1])),
+            208 => self.pressure = f64::from(data[1]) / 127.0,
+            224 => self.pitch_bend(data[0] & 0x0F, data[1], data[2]),
+            _ => (),
+        }
+    }
+
+    /// Let go of `note` in the arpeggiator, silencing it once the last key is up.
+    fn arp_release(&mut self, note: u8) {
+        self.arp.release(note);
+        if self.arp.is_empty() {
+            self.arp_stop();
+        }
+    }
+
+    /// Start or stop the transport, as midi start, continue and stop do. Started from the top,
+    /// the arpeggiator starts over from its first step; stopped, it lets go and waits.
+    fn set_playing(&mut self, playing: bool, from_top: bool) {
+        self.clock.set_playing(playing);
+        if !playing || from_top {
+            self.arp_stop();
+        }
+        if from_top {
+            self.arp.restart();
+        }
+    }
+
+    /// Let go of the arpeggiator's voice, so the next key down is played straight away.
+    fn arp_stop(&mut self) {
+        if let Some(voice) = self.arp_voice.take() {
+            self.note_off(voice, DEFAULT_RELEASE_VELOCITY);
+        }
+        self.arp_countdown = 0;
+    }
+
+    /// Let go of the arpeggiator's last key and strike its next.
+    fn arp_step(&mut self) {
+        if let Some(voice) = self.arp_voice.take() {
+            self.note_off(voice, DEFAULT_RELEASE_VELOCITY);
+        }
+        let (mode, octaves) = (self.params.arp_mode(), self.params.arp_octaves());
+        let chance = if mode == ArpMode::Random { self.rng.gen::<f64>() } else { 0.0 };
+        if let Some(note) = self.arp.next(mode, octaves, chance) {
+            self.note_on(note, self.arp.velocity(), 0);
+            self.arp_voice = Some(note);
+        }
+        let tempo = self.tempo.unwrap_or(DEFAULT_TEMPO);
+        let length = self.params.arp_rate().seconds(tempo).unwrap_or(0.0);
+        self.arp_countdown = ((length * self.sample_rate).round() as usize).max(1);
+    }
+
+    /// Bend the voices struck on `channel` by the pitch bend `lsb` and `msb`, in MPE. Without
+    /// MPE, pitch bends are ignored.
+    fn pitch_bend(&mut self, channel: u8, lsb: u8, msb: u8) {
+        if !self.params.mpe() {
+            return;
+        }
+        let value = f64::from(u16::from(msb) << 7 | u16::from(lsb));
+        let bend = cents_to_ratio(100.0 * MPE_BEND_RANGE * (value - 8192.0) / 8192.0);
+        self.channel_bends[usize::from(channel & 0x0F)] = bend;
+        for voice in self.notes.iter_mut().filter(|n| n.channel == channel) {
+            voice.bend = bend;
+        }
+    }
+
+    fn poly_pressure(&mut self, note: u8, pressure: u8) {
+        for voice in self.notes.iter_mut().filter(|n| n.note == note) {
+            voice.pressure = f64::from(pressure) / 127.0;
+        }
+    }
+
+    #[allow(clippy::single_match)]
+    fn control_change(&mut self, controller: u8, value: u8) {
+        if let Some(index) = self.params.cc_binding(controller) {
+            self.params.set_parameter(index, f32::from(value) / 127.0);
+        }
+
+        match controller {
+            1 => self.mod_wheel = f64::from(value) / 127.0,
+            7 => self.volume = f64::from(value) / 127.0,
+            11 => self.expression = f64::from(value) / 127.0,
+            120 => self.all_sound_off(),
+            _ => (),
+        }
+    }
+
+    /// Start a voice for `note`, struck on midi `channel`, unless it falls outside the key
+    /// range.
+    ///
+    /// Note-offs are not filtered the same way, so voices already sounding when the range is
+    /// narrowed are still let go.
+    fn note_on(&mut self, note: u8, velocity: u8, channel: u8) {
+        if note < self.params.key_low() || note > self.params.key_high() {
+            return;
+        }
+        let curve = VelocityCurve::from_parameter(self.params.velocity_curve.get());
+        let velocity = curve.gain(velocity);
+        let held = self.notes.iter().rposition(|n| n.note == note && !n.released);
+        let policy = DuplicateNotes::from_parameter(self.params.duplicate_notes.get());
+        let legato = !self.params.retrigger();
+        let replace = match (held, policy) {
+            (Some(_), DuplicateNotes::Ignore) => return,
+            (Some(idx), DuplicateNotes::Retrigger) => Some(idx),
+            (Some(_), DuplicateNotes::Stack) => None,
+            // In legato, a voice still fading out from the last strike is picked back up.
+            (None, _) if legato => self.notes.iter().rposition(|n| n.note == note),
+            (None, _) => None,
+        };
+
+        // Humanize nudges each strike a little off its pitch and a little late.
+        let humanize = f64::from(self.params.humanize.get());
+        // The voice keeps its key, so the right one is let go on the note-off whatever has
+        // changed since, and is tuned to its transposed pitch on the scale instead.
+        let transposed = (i32::from(note) + self.params.transpose()).clamp(0, 127) as u8;
+        let snapped = self.params.scale_snap().snap(transposed);
+        let mut cents = 100.0 * (f64::from(snapped) - f64::from(note));
+        let mut wait = 0;
+        if humanize > 0.0 {
+            cents += (self.rng.gen::<f64>() * 2.0 - 1.0) * MAX_HUMANIZE_CENTS * humanize;
+            let delay = self.rng.gen::<f64>() * MAX_HUMANIZE_DELAY * humanize;
+            wait = (delay * self.sample_rate) as usize;
+        }
+        let tuning = self.params.tuning_mode();
+        let detune = cents_to_ratio(cents);
+        let mut string = match self.strings.pop() {
+            Some(string) => string,
+            None => KarplusString::with_room(lowest_frequency(), self.sample_rate),
+        };
+        let frequency = midi_pitch_to_freq(note) * detune;
+        string.pluck(frequency, tuning, self.sample_rate, &mut self.rng);
+        if self.params.exciter() {
+            string.silence();
+        } else {
+            string.excite((self.params.excite_length() * self.sample_rate) as usize);
+            // A softer strike plucks with a duller burst.
+            let vel_to_brightness = self.params.vel_to_brightness.get();
+            if vel_to_brightness > 0.0 {
+                string.soften(vel_to_brightness * (1.0 - velocity as f32));
+            }
+        }
+        let mut voice = Note::new(note, detune, velocity, string);
+        voice.channel = channel;
+        if self.params.mpe() {
+            voice.bend = self.channel_bends[usize::from(channel)];
+        }
+        voice.wait = wait;
+        // Each voice sets off from its own pitch, so drift never jumps at the strike.
+        if self.params.drift.get() > 0.0 {
+            voice.wander[1] = self.rng.gen::<f64>() * 2.0 - 1.0;
+        }
+        // Every strike reads its own stretch of the noise, the same ones in the same order
+        // each time the plugin is started afresh.
+        if self.params.voice_offset() {
+            voice.phase = (f64::from(self.strikes) * STRIKE_STEP).fract() * VOICE_SPREAD;
+        }
+        self.strikes = self.strikes.wrapping_add(1);
+        if self.params.attack() > 0.0 {
+            voice.alpha = 0.0;
+        }
+        match replace {
+            Some(idx) => {
+                if legato {
+                    // Picked up from the level it had reached, whichever curve got it there.
+                    let level = self.notes[idx]
+                        .envelope(self.params.attack_curve(), self.params.release_curve());
+                    voice.alpha = self.params.attack_curve().invert(level);
+                }
+                let replaced = std::mem::replace(&mut self.notes[idx], voice);
+                self.strings.push(replaced.string);
+            }
+            None if self.notes.len() < MAX_VOICES => self.notes.push(voice),
+            None => {
+                let idx = self.quietest_voice();
+                let stolen = self.notes.remove(idx);
+                self.strings.push(stolen.string);
+                self.notes.push(voice);
+            }
+        }
+    }
+
+    /// The voice that would be missed least, the quietest, or the oldest of any as quiet.
+    fn quietest_voice(&self) -> usize {
+        let (attack, release) = (self.params.attack_curve(), self.params.release_curve());
+        // Without the onset, or the voice struck last would always be the next to go.
+        let level = |note: &Note| {
+            let curve = if note.released { release } else { attack };
+            curve.apply(note.alpha) * note.velocity
+        };
+        let levels = self.notes.iter().map(level).enumerate();
+        levels.fold((0, f64::INFINITY), |quietest, (idx, level)| {
+            if level < quietest.1 {
+                (idx, level)
+            } else {
+                quietest
+            }
+        })
+        .0
+    }
+
+    /// Release the oldest still-held voice playing `note`.
+    ///
+    /// The same pitch can be struck again before its previous voice has finished, so each
+    /// note-off lets go of exactly one voice rather than every voice of that pitch. How hard it
+    /// was let go, `velocity`, sets how quickly it fades.
+    fn note_off(&mut self, note: u8, velocity: u8) {
+        // One-shot voices end by themselves, and drones only once all sound is cut off.
+        if self.params.one_shot() || self.params.drone() {
+            return;
+        }
+        let attack = self.params.attack_curve();
+        let release = self.params.release_curve();
+        let speed = self.params.release_speed(velocity);
+        if let Some(voice) = self.notes.iter_mut().find(|n| n.note == note && !n.released) {
+            // The release carries on from the level the attack had reached.
+            voice.alpha = release.invert(attack.apply(voice.alpha));
+            voice.released = true;
+            voice.release_speed = speed;
+        }
+    }
+}
+
+pub const TAU: f64 = PI * 2.0;
+
+/// How much scratch space to allocate before the host tells us its block size.
+const DEFAULT_BLOCK_SIZE: usize = 1024;
+
+impl Plugin for SineSynth {
+    fn new(host: HostCallback) -> Self {
+        SineSynth::with_config(host, &SynthConfig::default())
+    }
+
+    fn get_info(&self) -> Info {
+        Info {
+            name: "SineSynth".to_string(),
+            vendor: "DeathDisco".to_string(),
+            unique_id: 6667,
+            category: Category::Synth,
+            inputs: 2,
+            outputs: 2,
+            parameters: PARAMETER_COUNT,
+            initial_delay: 0,
+            // The CC bindings are saved alongside the parameters.
+            preset_chunks: true,
+            presets: PRESETS.len() as i32,
+            ..Info::default()
+        }
+    }
+
+    #[allow(unused_variables)]
+    #[allow(clippy::single_match)]
+    fn process_events(&mut self, events: &Events) {
+        for event in events.events() {
+            match event {
+                // The real-time messages, for hosts that send midi clock instead of time info.
+                Event::Midi(ev) => match ev.data[0] {
+                    0xF8 => self.clock.tick(ev.delta_frames.max(0) as usize, self.sample_rate),
+                    0xFA => self.set_playing(true, true),
+                    0xFB => self.set_playing(true, false),
+                    0xFC => self.set_playing(false, false),
+                    _ => self.process_midi_event(ev.data),
+                },
+                // More events can be handled here.
+                _ => (),
+            }
+        }
+    }
+
+    fn set_sample_rate(&mut self, rate: f32) {
+        self.sample_rate = f64::from(rate);
+        self.params.dirty.store(usize::MAX, Ordering::Relaxed);
+        for delay in self.delays.iter_mut() {
+            delay.resize(self.sample_rate);
+        }
+        self.haas.resize(self.sample_rate);
+        self.chorus.resize(self.sample_rate);
+        self.reverb.resize(self.sample_rate);
+        // Strings keep ringing through a rate change, but their period in samples has to
+        // follow it to stay in tune.
+        for note in self.notes.iter_mut() {
+            note.string.resample(self.sample_rate);
+            note.string.make_room(lowest_frequency(), self.sample_rate);
+        }
+        for string in self.strings.iter_mut() {
+            string.make_room(lowest_frequency(), self.sample_rate);
+        }
+    }
+
+    fn suspend(&mut self) {
+        self.reset();
+    }
+
+    /// Start from silence, so nothing left over from before the suspend glitches in.
+    fn resume(&mut self) {
+        self.reset();
+    }
+
+    /// Long enough for released voices to fade, the chorus to empty and the echoes and the
+    /// reverb to fall below -60dB.
+    fn get_tail_size(&self) -> isize {
+        // As long as the gentlest note-off can make the release.
+        let mut tail = RELEASE / self.params.release_speed(0);
+        if self.params.chorus_mix.get() > 0.0 {
+            tail += MAX_CHORUS_DELAY;
+        }
+        if self.params.delay_mix.get() > 0.0 {
+            let feedback = f64::from(self.params.delay_feedback());
+            let repeats = if feedback > 0.0 {
+                (0.001f64.ln() / feedback.ln()).ceil()
+            } else {
+                0.0
+            };
+            tail += self.delay_times()[1] * (1.0 + repeats);
+        }
+        if self.params.reverb_mix.get() > 0.0 {
+            tail += reverb::decay_time(self.params.reverb_size.get());
+        }
+        tail += self.params.haas_delay();
+        (tail * self.sample_rate).ceil() as isize
+    }
+
+    /// Voices are mixed into scratch space sized here, so `process` never has to allocate.
+    fn set_block_size(&mut self, size: i64) {
+        for channel in self.channels.iter_mut() {
+            channel.mix = vec![0.0; (size as usize).max(1) * MAX_OVERSAMPLING];
+        }
+    }
+
+    /// This runs on the audio thread, so it must not allocate: scratch space is sized up front
+    /// in `set_block_size` and no `Vec` may be grown in here.
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        let samples = buffer.samples();
+        let (inputs, mut outputs) = buffer.split();
+        let output_count = outputs.len();
+        // The clock's ticks for this block have come in already.
+        self.clock.advance(samples);
+        #[cfg(feature = "diagnostics")]
+        self.report_voices(samples);
+
+        let bypassed = self.params.bypass.get() > 0.5;
+        if bypassed && !self.bypassed {
+            // Nothing should be left ringing for when the bypass is lifted.
+            self.reset();
+        }
+        self.bypassed = bypassed;
+        if bypassed {
+            for buf_idx in 0..output_count {
+                let buff = outputs.get_mut(buf_idx);
+                if buf_idx < inputs.len() {
+                    buff.copy_from_slice(inputs.get(buf_idx));
+                } else {
+                    for sample in buff.iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+            }
+            return;
+        }
+
+        let bit_depth = self.params.bit_depth();
+        let oversampling = self.params.oversampling();
+        let factor = oversampling.factor();
+        // The crusher runs at the higher rate, so it holds for longer to sound the same.
+        let downsample = self.params.downsample() * factor;
+        let drive = self.params.drive();
+        let curve = self.params.curve();
+        let ring_freq = self.params.ring_freq();
+        self.update_tempo();
+        let [left_delay, right_delay] = self.delay_times();
+        let delay_samples = [
+            (left_delay * self.sample_rate) as usize,
+            (right_delay * self.sample_rate) as usize,
+        ];
+        let delay_feedback = self.params.delay_feedback();
+        let delay_pingpong = self.params.delay_pingpong();
+        let chorus_rate = self.params.chorus_rate();
+        let chorus_depth = self.params.chorus_depth();
+        let reverb_size = self.params.reverb_size.get();
+        let reverb_damp = self.params.reverb_damp.get();
+        let tremolo_rate = self.params.tremolo_rate();
+        let tremolo_shape = self.params.tremolo_shape();
+        let haas_samples = (self.params.haas_delay() * self.sample_rate).round() as usize;
+        self.ramps.set(&self.params, samples);
+        let ceiling = db_to_gain(self.params.ceiling_db());
+        let channel_gain = (self.volume * self.expression) as f32;
+        let channel_gain_kept = (-1.0 / (VOLUME_SMOOTHING * self.sample_rate)).exp() as f32;
+        let invert = self.params.invert();
+        self.rebuild();
+        let high_pass = self.params.hp_cutoff();
+        let output_mode = OutputMode::from_parameter(self.params.output_mode.get());
+        let channels = output_mode.channels();
+
+        // Whatever was left in a resampler or channel that has not been running is stale.
+        if oversampling != self.oversampling {
+            for channel in self.channels.iter_mut() {
+                channel.oversamplers[oversampling as usize].reset();
+                channel.dry_oversamplers[oversampling as usize].reset();
+            }
+            self.oversampling = oversampling;
+        }
+        if channels > self.rendered {
+            self.channels[1].reset();
+        }
+        self.rendered = channels;
+
+        if self.params.panic.get() > 0.5 {
+            self.reset();
+            self.params.panic.set(0.0);
+        }
+        // Turned off, the arpeggiator lets go of its keys along with its voice.
+        let arp = self.params.arp_on();
+        if !arp && !self.arp.is_empty() {
+            self.arp.clear();
+            self.arp_stop();
+        }
+
+        let (mut peak, mut sum_of_squares) = (0.0f32, 0.0);
+        let mut last_frame = [0.0f32; 2];
+
+        // Blocks larger than the host promised are worked through in pieces that fit the
+        // scratch space rather than growing it.
+        let mut start = 0;
+        while start < samples {
+            let mut len = (samples - start).min(self.channels[0].mix.len() / MAX_OVERSAMPLING);
+            // The block is split at each of the arpeggiator's steps, so they land on time.
+            if arp && !self.arp.is_empty() && self.clock.playing() != Some(false) {
+                if self.arp_countdown == 0 {
+                    self.arp_step();
+                }
+                len = len.min(self.arp_countdown);
+                self.arp_countdown -= len;
+            }
+            let side = |c: usize| {
+                if c < inputs.len() {
+                    &inputs.get(c)[start..start + len]
+                } else {
+                    &[]
+                }
+            };
+            self.render_voices(len, oversampling, channels, [side(0), side(1)]);
+
+            for i in 0..len {
+                let ring_mod = self.ramps.ring_mod.next();
+                let chorus_mix = self.ramps.chorus_mix.next();
+                let delay_mix = self.ramps.delay_mix.next();
+                let reverb_mix = self.ramps.reverb_mix.next();
+                let tremolo_depth = f64::from(self.ramps.tremolo_depth.next());
+                let width = self.ramps.width.next();
+                let global_mix = self.ramps.global_mix.next();
+
+                let carrier = self.ring.value(Shape::Sine) as f32;
+                let ring = 1.0 - ring_mod + ring_mod * carrier;
+                self.ring.advance(ring_freq, self.sample_rate);
+
+                let (mut voices, mut dry) = ([0.0f32; 2], [0.0f32; 2]);
+                let live = self.channels[..channels].iter_mut();
+                for ((voice, dry), channel) in voices.iter_mut().zip(dry.iter_mut()).zip(live) {
+                    let sub = &mut channel.mix[i * factor..(i + 1) * factor];
+                    if global_mix < 1.0 {
+                        *dry = channel.dry_oversamplers[oversampling as usize].downsample(sub);
+                    }
+                    for sample in sub.iter_mut() {
+                        let shaped = shaper::shape(*sample, drive, curve);
+                        *sample = channel.crusher.process(shaped, bit_depth, downsample);
+                    }
+                    *voice = channel.oversamplers[oversampling as usize].downsample(sub) * ring;
+                    if high_pass.is_some() {
+                        *voice = channel.high_pass.process(*voice);
+                    }
+                }
+
+                let frame = output_mode.frame(voices);
+                let mut frame = self.chorus.process(frame, chorus_rate, chorus_depth, chorus_mix);
+                if delay_pingpong {
+                    let echoes = ping_pong(&mut self.delays, frame, delay_samples, delay_feedback);
+                    for (sample, echo) in frame.iter_mut().zip(echoes.iter()) {
+                        *sample += echo * delay_mix;
+                    }
+                } else {
+                    let lines = self.delays.iter_mut().zip(delay_samples.iter());
+                    for (sample, (delay, &samples)) in frame.iter_mut().zip(lines) {
+                        *sample += delay.process(*sample, samples, delay_feedback) * delay_mix;
+                    }
+                }
+                // Left dry, the reverb is not run at all.
+                if reverb_mix > 0.0 {
+                    let wet = self.reverb.process(frame, reverb_size, reverb_damp);
+                    for (sample, wet) in frame.iter_mut().zip(wet.iter()) {
+                        *sample += wet * reverb_mix;
+                    }
+                }
+                // At the top of the cycle the level is untouched, and at the bottom it is down
+                // by the full depth.
+                let tremolo = 1.0 - tremolo_depth * (1.0 - self.tremolo.value(tremolo_shape)) / 2.0;
+                self.tremolo.advance(tremolo_rate, self.sample_rate);
+                for sample in frame.iter_mut() {
+                    *sample *= tremolo as f32;
+                }
+                let mid = (frame[0] + frame[1]) * 0.5;
+                let side = (frame[0] - frame[1]) * 0.5 * width;
+                frame = [mid + side, mid - side];
+                // The line is kept running with the hold-back off, so turning it on never
+                // brings back something stale.
+                let held_back = self.haas.process(frame[1], haas_samples, 0.0);
+                if haas_samples > 0 {
+                    frame[1] = held_back;
+                }
+                frame = self.compressor.process(frame);
+                frame = self.gate.process(frame);
+                // The effects carry on running even when they are mixed out of the output, so
+                // sweeping the mix back up never jumps.
+                if global_mix < 1.0 {
+                    let dry = output_mode.frame(dry);
+                    for (sample, dry) in frame.iter_mut().zip(dry.iter()) {
+                        *sample = dry + (*sample - dry) * global_mix;
+                    }
+                }
+                self.channel_gain = ::flush_denormal(
+                    channel_gain + (self.channel_gain - channel_gain) * channel_gain_kept,
+                );
+                for sample in frame.iter_mut() {
+                    *sample *= self.channel_gain;
+                }
+                for sample in frame.iter_mut() {
+                    // Whatever has gone wrong upstream, nothing over the ceiling, nor anything
+                    // infinite or not a number, makes it out.
+                    *sample = if sample.is_finite() {
+                        sample.clamp(-ceiling, ceiling)
+                    } else {
+                        0.0
+                    };
+                }
+                for (sample, &invert) in frame.iter_mut().zip(invert.iter()) {
+                    if invert {
+                        *sample = -*sample;
+                    }
+                }
+
+                for &sample in frame.iter() {
+                    peak = peak.max(sample.abs());
+                    sum_of_squares += sample * sample;
+                }
+                last_frame = frame;
+
+                // A lone output gets both sides summed, and any more take turns at left and right.
+                if output_count == 1 {
+                    outputs.get_mut(0)[start + i] = (frame[0] + frame[1]) * 0.5;
+                } else {
+                    for buf_idx in 0..output_count {
+                        outputs.get_mut(buf_idx)[start + i] = frame[buf_idx % 2];
+                    }
+                }
+            }
+            start += len;
+        }
+
+        if samples > 0 {
+            self.params.peak_level.set(peak);
+            let rms = (sum_of_squares / (samples * 2) as f32).sqrt();
+            self.params.rms_level.set(rms);
+            for (last, &sample) in self.params.last_output.iter().zip(last_frame.iter()) {
+                last.set(sample);
+            }
+        }
+    }
+
+    fn get_parameter_object(&mut self) -> Arc<dyn PluginParameters> {
+        Arc::clone(&self.params) as Arc<dyn PluginParameters>
+    }
+
+    fn can_do(&self, can_do: CanDo) -> Supported {
+        match can_do {
+            CanDo::ReceiveMidiEvent => Supported::Yes,
+            // rust-vst has no way to pass the host's own bypass call on to us, so it is left to
+            // the host to bypass us itself, or to the `bypass` parameter.
+            CanDo::Bypass => Supported::No,
+            _ => Supported::Maybe,
+        }
+    }
+}
+
+plugin_main!(SineSynth);
+
+#[cfg(test)]
+mod tests {
+    use crusher::MAX_BIT_DEPTH;
+    use delay::MAX_DELAY;
+    use string::{Tuning, MAX_DAMPING, MIN_DAMPING};
+    use lfo::Shape;
+    use midi_pitch_to_freq;
+    use oversample::Oversampling;
+    use scale::Scale;
+    use tempo::Division;
+    use sources::{NoiseSource, NOISE_SOURCES};
+    use {cents_to_ratio, step_to_parameter, Destination, DuplicateNotes, OutputMode, SineSynth};
+    use {SynthConfig, SynthParameters};
+    use {EnvelopeCurve, VelocityCurve};
+    use std::convert::TryFrom;
+    use {MAX_DRIFT_CENTS, MAX_HUMANIZE_CENTS, MAX_HUMANIZE_DELAY, MAX_VOICES, MIDI_CHANNEL_STEPS};
+    use {PARAMETER_COUNT, RELEASE, SEED, TAU};
+    use {db_to_gain, Derived, ParamId, SourceMode};
+    use vst::buffer::{AudioBuffer, SendEventBuffer};
+    use vst::event::MidiEvent;
+    use vst::api::Supported;
+    use vst::plugin::{CanDo, HostCallback, Plugin, PluginParameters};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    #[cfg(feature = "diagnostics")]
+    use std::cell::RefCell;
+
+    /// Counts the allocations made on each thread, so tests can check the audio loop makes none.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|count| count.get())
+    }
+
+    fn count_allocation() {
+        // The counter may already be gone while the thread is shutting down.
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    thread_local! {
+        static LOADS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// The host's `AtomicFloat`, counting the loads made from it on each thread, so tests can
+    /// check how often the audio loop goes back to the parameters.
+    pub struct AtomicFloat(vst::util::AtomicFloat);
+
+    impl AtomicFloat {
+        pub fn new(value: f32) -> AtomicFloat {
+            AtomicFloat(vst::util::AtomicFloat::new(value))
+        }
+
+        pub fn get(&self) -> f32 {
+            LOADS.with(|count| count.set(count.get() + 1));
+            self.0.get()
+        }
+
+        pub fn set(&self, value: f32) {
+            self.0.set(value)
+        }
+    }
+
+    fn loads() -> usize {
+        LOADS.with(|count| count.get())
+    }
+
+    /// The amplitude of the component of `signal` at `frequency`, sampled at 44.1khz.
+    fn magnitude(signal: &[f32], frequency: f64) -> f64 {
+        let (mut sin, mut cos) = (0.0, 0.0);
+        for (i, &sample) in signal.iter().enumerate() {
+            let angle = TAU * frequency * i as f64 / 44100.0;
+            sin += f64::from(sample) * angle.sin();
+            cos += f64::from(sample) * angle.cos();
+        }
+        2.0 * (sin * sin + cos * cos).sqrt() / signal.len() as f64
+    }
+
+    /// The pitch of `signal`, sampled at 44.1khz, from the first period its autocorrelation
+    /// peaks at, for pitches from 110hz to 2khz.
+    fn pitch(signal: &[f32]) -> f64 {
+        let correlation = |lag: usize| -> f64 {
+            let pairs = signal.iter().zip(signal[lag..].iter());
+            pairs.map(|(&a, &b)| f64::from(a * b)).sum::<f64>() / (signal.len() - lag) as f64
+        };
+        let lags: Vec<f64> = (0..=400).map(correlation).collect();
+        // A later period's peak can come out above the first's, as the string is retuned.
+        let highest = lags[20..400].iter().cloned().fold(0.0, f64::max);
+        let is_peak = |lag: usize| lags[lag] >= lags[lag - 1] && lags[lag] >= lags[lag + 1];
+        let first = (20..400).find(|&lag| is_peak(lag) && lags[lag] > highest * 0.9);
+        let peak = first.unwrap();
+        // Between the lags either side, on a parabola through the three.
+        let (before, at, after) = (lags[peak - 1], lags[peak], lags[peak + 1]);
+        44100.0 / (peak as f64 + 0.5 * (before - after) / (before - 2.0 * at + after))
+    }
+
+    /// Run `synth` for `samples` samples and return what it wrote to each output channel.
+    fn render(synth: &mut SineSynth, samples: usize) -> Vec<Vec<f32>> {
+        render_channels(synth, 2, samples)
+    }
+
+    /// Like `render`, but into `channels` outputs.
+    fn render_channels(synth: &mut SineSynth, channels: usize, samples: usize) -> Vec<Vec<f32>> {
+        render_inputs(synth, channels, &vec![vec![0.0f32; samples]; 2])
+    }
+
+    /// Like `render_channels`, but with `inputs` coming in.
+    fn render_inputs(synth: &mut SineSynth, channels: usize, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let samples = inputs[0].len();
+        let mut outputs = vec![vec![0.0f32; samples]; channels];
+        {
+            let input_ptrs: Vec<*const f32> = inputs.iter().map(|c| c.as_ptr()).collect();
+            let mut output_ptrs: Vec<*mut f32> = outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+            let mut buffer = unsafe {
+                let outputs = output_ptrs.as_mut_ptr();
+                AudioBuffer::from_raw(2, channels, input_ptrs.as_ptr(), outputs, samples)
+            };
+            synth.process(&mut buffer);
+        }
+        outputs
+    }
+
+    #[test]
+    fn test_midi_pitch_to_freq() {
+        for i in 0..127 {
+            // expect no panics
+            midi_pitch_to_freq(i);
+        }
+    }
+
+    #[test]
+    fn test_note_off_releases_one_voice_per_strike() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.set_parameter(0, DuplicateNotes::Stack.to_parameter());
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([128, 60, 0]);
+
+        assert_eq!(synth.notes.len(), 2);
+        assert!(synth.notes[0].released);
+        assert!(!synth.notes[1].released);
+
+        // Once the released voice has faded out, the second strike is still sounding.
+        render(&mut synth, 44100);
+        assert_eq!(synth.notes.len(), 1);
+        assert!(!synth.notes[0].released);
+    }
+
+    #[test]
+    fn test_duplicate_note_on_policy() {
+        let expected = [
+            (DuplicateNotes::Retrigger, 1),
+            (DuplicateNotes::Ignore, 1),
+            (DuplicateNotes::Stack, 2),
+        ];
+        for &(policy, voices) in expected.iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.set_parameter(0, policy.to_parameter());
+            synth.process_midi_event([144, 60, 100]);
+            synth.process_midi_event([144, 60, 100]);
+            assert_eq!(synth.notes.len(), voices, "{}", policy.name());
+        }
+    }
+
+    #[test]
+    fn test_duplicate_notes_defaults_to_retrigger() {
+        let synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(0), "Retrigger");
+    }
+
+    #[test]
+    fn test_lfo_modulates_target_amount() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_sample_rate(1000.0);
+        let white = NoiseSource::White as usize;
+        synth.params.amounts[white].set(0.5);
+        synth.params.lfo_target.set(step_to_parameter(white, NOISE_SOURCES));
+        synth.params.lfo_depth.set(0.5);
+        // 20hz * 0.5^2
+        synth.params.lfo_rate.set(0.5);
+        assert_eq!(synth.params.get_parameter_text(11), "5.00 Hz");
+
+        // Count the times the amount rises back through its resting value.
+        let mut cycles = 0;
+        let mut previous = 0.5;
+        for _ in 0..1100 {
+            let amounts = synth.noise_blend().amounts(&synth.lfo);
+            assert_eq!(amounts[NoiseSource::Perlin as usize], 0.0);
+            if previous < 0.5 && amounts[white] >= 0.5 {
+                cycles += 1;
+            }
+            previous = amounts[white];
+            render(&mut synth, 1);
+        }
+        assert_eq!(cycles, 5);
+    }
+
+    #[test]
+    fn test_vibrato_oscillates_around_played_pitch() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_sample_rate(1000.0);
+        synth.params.vibrato_depth.set(0.5);
+        synth.params.vibrato_rate.set(0.5);
+        assert_eq!(synth.params.get_parameter_text(16), "50 cents");
+        synth.process_midi_event([144, 69, 100]);
+
+        let mut cycles = 0;
+        let mut previous = 440.0;
+        let (mut lowest, mut highest) = (440.0, 440.0);
+        for _ in 0..1100 {
+            let phase = synth.notes[0].phase;
+            render(&mut synth, 1);
+            // The distance travelled over one sample is the voice's instantaneous frequency.
+            let freq = (synth.notes[0].phase - phase) * 1000.0;
+            if previous < 440.0 && freq >= 440.0 {
+                cycles += 1;
+            }
+            previous = freq;
+            lowest = freq.min(lowest);
+            highest = freq.max(highest);
+        }
+        assert_eq!(cycles, 5);
+        assert!((highest - 440.0 * cents_to_ratio(50.0)).abs() < 0.01);
+        assert!((lowest - 440.0 * cents_to_ratio(-50.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_vibrato_wobbles_the_string() {
+        // The pitch heard through each 40th of a second of A4.
+        let pitches = |depth: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.damping.set(1.0);
+            synth.params.vibrato_depth.set(depth);
+            synth.params.vibrato_rate.set(0.5);
+            synth.process_midi_event([144, 69, 100]);
+            let output = render(&mut synth, 44100).swap_remove(0);
+            output.chunks_exact(1102).map(pitch).collect::<Vec<f64>>()
+        };
+        // Held within a few cents, what the measurement is off by over such short stretches.
+        let steady = pitches(0.0);
+        let off = |freq: &f64| (freq - 440.0).abs();
+        assert!(steady.iter().map(off).all(|off| off < 1.0), "{:?}", steady);
+
+        // Up and down by the depth, five times over, less what each 40th averages away and give
+        // or take the few cents the measurement is off by.
+        let wobbling = pitches(0.5);
+        let highest = wobbling.iter().cloned().fold(0.0, f64::max);
+        let lowest = wobbling.iter().cloned().fold(f64::INFINITY, f64::min);
+        assert!(highest > 440.0 * cents_to_ratio(45.0), "{:?}", wobbling);
+        assert!(highest < 440.0 * cents_to_ratio(55.0), "{:?}", wobbling);
+        assert!(lowest < 440.0 * cents_to_ratio(-45.0), "{:?}", wobbling);
+        assert!(lowest > 440.0 * cents_to_ratio(-55.0), "{:?}", wobbling);
+        // It sets off upwards, so falls back through the pitch once every cycle.
+        let falls = |pair: &&[f64]| pair[0] >= 440.0 && pair[1] < 440.0;
+        let cycles = wobbling.windows(2).filter(falls).count();
+        assert_eq!(cycles, 5, "{:?}", wobbling);
+    }
+
+    #[test]
+    fn test_mod_wheel_opens_up_destination() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        let perlin = NoiseSource::Perlin as usize;
+        synth.params.amounts[perlin].set(0.2);
+        let target = Destination::Amount(NoiseSource::Perlin).to_parameter();
+        synth.params.mod_wheel_target.set(target);
+        assert_eq!(synth.params.get_parameter_text(17), "Perlin noise");
+
+        synth.process_midi_event([176, 1, 0]);
+        assert!((synth.noise_blend().amounts(&synth.lfo)[perlin] - 0.2).abs() < 1e-6);
+        synth.process_midi_event([176, 1, 127]);
+        assert_eq!(synth.noise_blend().amounts(&synth.lfo)[perlin], 1.0);
+        assert_eq!(synth.noise_blend().amounts(&synth.lfo)[NoiseSource::White as usize], 0.0);
+    }
+
+    #[test]
+    fn test_mod_wheel_defaults_to_vibrato() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_sample_rate(1000.0);
+        render(&mut synth, 50);
+        assert_eq!(synth.vibrato_depth(), 0.0);
+
+        synth.process_midi_event([176, 1, 127]);
+        assert!(synth.vibrato_depth() > 0.0);
+    }
+
+    #[test]
+    fn test_channel_aftertouch_reaches_destination() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        let target = Destination::Amount(NoiseSource::White).to_parameter();
+        synth.params.pressure_target.set(target);
+
+        synth.process_midi_event([208, 127, 0]);
+        assert_eq!(synth.noise_blend().amounts(&synth.lfo)[NoiseSource::White as usize], 1.0);
+        synth.process_midi_event([208, 0, 0]);
+        assert_eq!(synth.noise_blend().amounts(&synth.lfo)[NoiseSource::White as usize], 0.0);
+    }
+
+    #[test]
+    fn test_poly_aftertouch_boosts_its_own_voice() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(18), "Amplitude");
+        // Struck at full velocity, so the voices start from unity.
+        synth.process_midi_event([144, 60, 127]);
+        synth.process_midi_event([144, 64, 127]);
+        synth.process_midi_event([160, 64, 127]);
+
+        let boost = synth.modulate(Destination::Amplitude, 0.0);
+        assert_eq!(synth.notes[0].level(1.0, boost, Destination::Amplitude), 1.0);
+        assert_eq!(synth.notes[1].level(1.0, boost, Destination::Amplitude), 2.0);
+
+        // Channel aftertouch lifts every voice.
+        synth.process_midi_event([208, 127, 0]);
+        let boost = synth.modulate(Destination::Amplitude, 0.0);
+        assert_eq!(synth.notes[0].level(1.0, boost, Destination::Amplitude), 2.0);
+    }
+
+    #[test]
+    fn test_midi_channel_filter() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(19), "Omni");
+        synth.process_midi_event([0x90, 60, 100]);
+        synth.process_midi_event([0x91, 62, 100]);
+        assert_eq!(synth.notes.len(), 2);
+
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.midi_channel.set(step_to_parameter(2, MIDI_CHANNEL_STEPS));
+        assert_eq!(synth.params.get_parameter_text(19), "2");
+        synth.process_midi_event([0x90, 60, 100]);
+        synth.process_midi_event([0x91, 62, 100]);
+        synth.process_midi_event([0xB0, 1, 127]);
+        assert_eq!(synth.notes.len(), 1);
+        assert_eq!(synth.notes[0].note, 62);
+        assert_eq!(synth.mod_wheel, 0.0);
+
+        synth.process_midi_event([0x80, 62, 0]);
+        assert!(!synth.notes[0].released);
+        synth.process_midi_event([0x81, 62, 0]);
+        assert!(synth.notes[0].released);
+    }
+
+    #[test]
+    fn test_low_bit_depth_gives_few_levels() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(22), "Off");
+        synth.params.bit_depth.set(step_to_parameter(1, MAX_BIT_DEPTH as usize));
+        assert_eq!(synth.params.get_parameter_text(22), "2 bits");
+        synth.params.ceiling.set(1.0);
+        synth.process_midi_event([144, 48, 100]);
+        synth.process_midi_event([144, 55, 100]);
+
+        let mut levels: Vec<f32> = Vec::new();
+        for &sample in render(&mut synth, 4410)[0].iter() {
+            if !levels.contains(&sample) {
+                levels.push(sample);
+            }
+        }
+        // Two voices peak somewhere within +/-2, in steps of a half.
+        assert!(levels.len() <= 9, "{:?}", levels);
+        assert!(levels.iter().all(|level| (level * 2.0).fract() == 0.0));
+    }
+
+    #[test]
+    fn test_delay_echoes_a_note() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_sample_rate(1000.0);
+        // 100ms with no feedback
+        synth.params.delay_time.set(0.05);
+        synth.params.delay_feedback.set(0.0);
+        synth.params.delay_mix.set(1.0);
+        synth.params.duplicate_notes.set(DuplicateNotes::Stack.to_parameter());
+        synth.process_midi_event([144, 69, 100]);
+        synth.process_midi_event([128, 69, 0]);
+
+        // The note fades out within RELEASE, then its echo follows 100ms after it started.
+        let output = &render(&mut synth, 300)[0];
+        assert!(output[..100].iter().any(|&s| s != 0.0));
+        assert!(output[100..200].iter().any(|&s| s != 0.0));
+        assert!(output[200..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_tail_covers_echoes() {
+        let synth = SineSynth::new(HostCallback::default());
+        let dry = synth.get_tail_size();
+        synth.params.delay_mix.set(0.5);
+        let echoes = synth.get_tail_size();
+        synth.params.delay_feedback.set(1.0);
+        assert!(dry < echoes);
+        assert!(echoes < synth.get_tail_size());
+
+        // Longer echoes take longer to die away.
+        let short = synth.get_tail_size();
+        synth.params.delay_time.set(synth.params.delay_time.get() * 2.0);
+        assert!(synth.get_tail_size() > short);
+
+        // As does the chorus, if only by its delay.
+        synth.params.delay_mix.set(0.0);
+        synth.params.chorus_mix.set(0.0);
+        assert_eq!(synth.get_tail_size(), 4410);
+        synth.params.chorus_mix.set(0.5);
+        assert_eq!(synth.get_tail_size(), 4410 + 530);
+    }
+
+    #[test]
+    fn test_suspend_clears_voices_and_state() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.delay_mix.set(1.0);
+        synth.params.vibrato_depth.set(1.0);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 64, 100]);
+        render(&mut synth, 1000);
+        assert!(synth.lfo.value(Shape::Sine) != 0.0);
+
+        synth.suspend();
+        assert!(synth.notes.is_empty());
+        assert_eq!(synth.lfo.value(Shape::Sine), 0.0);
+        assert_eq!(synth.vibrato.value(Shape::Sine), 0.0);
+
+        // With the voices gone and the echoes cleared, the plugin is silent on resume.
+        synth.resume();
+        let output = render(&mut synth, 44100);
+        assert!(output.iter().all(|channel| channel.iter().all(|&s| s == 0.0)));
+    }
+
+    #[test]
+    fn test_sample_rate_change_keeps_strings_in_tune() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.process_midi_event([144, 69, 100]);
+        render(&mut synth, 100);
+        let before = synth.notes[0].string.frequency(44100.0);
+
+        synth.set_sample_rate(48000.0);
+        let after = synth.notes[0].string.frequency(48000.0);
+        assert!((after - before).abs() < 1.0, "{} -> {}", before, after);
+        assert!((after - 440.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_process_does_not_allocate() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_block_size(512);
+        synth.params.delay_mix.set(0.5);
+        synth.params.set_parameter(27, step_to_parameter(2, Oversampling::ALL.len()));
+        synth.params.set_parameter(2, 0.5);
+        synth.params.set_parameter(1, 0.5);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 64, 100]);
+
+        // A block longer than the host promised is handled without growing the scratch space.
+        for &samples in [512, 100, 1, 2000].iter() {
+            let inputs = vec![vec![0.0f32; samples]; 2];
+            let mut outputs = vec![vec![0.0f32; samples]; 2];
+            let input_ptrs: Vec<*const f32> = inputs.iter().map(|c| c.as_ptr()).collect();
+            let mut output_ptrs: Vec<*mut f32> = outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+            let mut buffer = unsafe {
+                AudioBuffer::from_raw(2, 2, input_ptrs.as_ptr(), output_ptrs.as_mut_ptr(), samples)
+            };
+            let before = allocations();
+            synth.process(&mut buffer);
+            assert_eq!(allocations(), before, "{} samples", samples);
+        }
+    }
+
+    #[test]
+    fn test_new_voices_do_not_allocate() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_sample_rate(48000.0);
+        synth.set_block_size(64);
+        synth.params.humanize.set(1.0);
+
+        // Played from a thread of its own, as the host's audio thread would, so that nothing
+        // the strikes draw on is set up lazily the first time that thread asks for it.
+        let audio = std::thread::spawn(move || {
+            let inputs = vec![vec![0.0f32; 64]; 2];
+            let mut outputs = vec![vec![0.0f32; 64]; 2];
+            let input_ptrs: Vec<*const f32> = inputs.iter().map(|c| c.as_ptr()).collect();
+            let mut output_ptrs: Vec<*mut f32> =
+                outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+
+            // Past every voice the pool holds, from the bottom of the keyboard to the top, so
+            // strings are taken over from voices let go, finished and stolen.
+            for i in 0..4 * MAX_VOICES {
+                let note = (i * 37 % 128) as u8;
+                let mut buffer = unsafe {
+                    AudioBuffer::from_raw(2, 2, input_ptrs.as_ptr(), output_ptrs.as_mut_ptr(), 64)
+                };
+                let before = allocations();
+                synth.process_midi_event([144, note, 100]);
+                synth.process(&mut buffer);
+                synth.process_midi_event([128, note, 0]);
+                assert_eq!(allocations(), before, "note {} of {}", i, note);
+            }
+        });
+        audio.join().unwrap();
+    }
+
+    #[test]
+    fn test_ring_mod_makes_sidebands() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.ring_mod.set(1.0);
+        synth.params.ring_freq.set(0.7);
+        let carrier = synth.params.ring_freq();
+        // The string is tuned exactly, so A4 rings at 440hz.
+        synth.process_midi_event([144, 69, 100]);
+        let output = &render(&mut synth, 44100)[0];
+
+        // Fully ring modulated, the fundamental is moved out to either side of the carrier.
+        let fundamental = magnitude(output, 440.0);
+        let below = magnitude(output, carrier - 440.0);
+        let above = magnitude(output, carrier + 440.0);
+        assert!(below > fundamental * 4.0, "{} {}", below, fundamental);
+        assert!(above > fundamental * 4.0, "{} {}", above, fundamental);
+    }
+
+    #[test]
+    fn test_meters_follow_output() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.process_midi_event([144, 60, 100]);
+        let output = render(&mut synth, 1000);
+        let peak = output[0].iter().fold(0.0f32, |peak, &s| peak.max(s.abs()));
+        let rms = (output[0].iter().map(|&s| s * s).sum::<f32>() / 1000.0).sqrt();
+        assert!(peak > 0.0);
+        assert!((synth.params.current_peak() - peak).abs() < 1e-6);
+        assert!((synth.params.current_rms() - rms).abs() < 1e-4);
+
+        // Silence reads as silence once the voice is gone.
+        synth.process_midi_event([128, 60, 0]);
+        render(&mut synth, 44100);
+        render(&mut synth, 100);
+        assert_eq!(synth.params.current_peak(), 0.0);
+        assert_eq!(synth.params.current_rms(), 0.0);
+    }
+
+    #[test]
+    fn test_panic_silences_everything() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.delay_mix.set(1.0);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 64, 100]);
+        render(&mut synth, 1000);
+
+        synth.params.set_parameter(34, 1.0);
+        let output = render(&mut synth, 44100);
+        assert!(synth.notes.is_empty());
+        assert!(output.iter().all(|channel| channel.iter().all(|&s| s == 0.0)));
+        // The switch turns itself back off, so the next note plays.
+        assert_eq!(synth.params.get_parameter(34), 0.0);
+        synth.process_midi_event([144, 60, 100]);
+        assert!(render(&mut synth, 100)[0].iter().any(|&s| s != 0.0));
+    }
+
+    /// How many samples it takes for `note`, played alone, to fall 20dB below its first
+    /// 10ms.
+    fn decay_time(synth: &mut SineSynth, note: u8) -> usize {
+        synth.reset();
+        synth.process_midi_event([144, note, 100]);
+        let output = &render(synth, 88200)[0];
+        let windows: Vec<f32> = output
+            .chunks(441)
+            .map(|window| window.iter().map(|&s| s * s).sum::<f32>())
+            .collect();
+        441 * windows.iter().position(|&energy| energy < windows[0] * 0.01).unwrap()
+    }
+
+    #[test]
+    fn test_damping_key_tracking() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.damping.set(0.5);
+        let untracked = decay_time(&mut synth, 48) as f64 / decay_time(&mut synth, 72) as f64;
+
+        // With key tracking, high notes die away faster still against low ones.
+        synth.params.damping_key_track.set(1.0);
+        let tracked = decay_time(&mut synth, 48) as f64 / decay_time(&mut synth, 72) as f64;
+        assert!(tracked > untracked * 2.0, "{} {}", tracked, untracked);
+
+        // And pulling it the other way evens them out.
+        synth.params.damping_key_track.set(0.0);
+        let inverted = decay_time(&mut synth, 48) as f64 / decay_time(&mut synth, 72) as f64;
+        assert!(inverted < untracked * 0.5, "{} {}", inverted, untracked);
+    }
+
+    #[test]
+    fn test_output_modes() {
+        for &mode in OutputMode::ALL.iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.output_mode.set(mode.to_parameter());
+            // A coherent and a stochastic source, so both get decorrelated.
+            synth.params.amounts[NoiseSource::White as usize].set(0.5);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(0.5);
+            synth.process_midi_event([144, 60, 100]);
+            let output = render(&mut synth, 1000);
+            if mode == OutputMode::Stereo {
+                assert!(output[0] != output[1]);
+            } else {
+                assert_eq!(output[0], output[1], "{}", mode.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_midi_learn_binds_cc() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.learn(35);
+        assert_eq!(synth.params.get_parameter_text(38), "Damping");
+        synth.process_midi_event([176, 20, 0]);
+        assert_eq!(synth.params.get_parameter_text(38), "Off");
+
+        synth.process_midi_event([176, 20, 127]);
+        assert_eq!(synth.params.damping.get(), 1.0);
+        synth.process_midi_event([176, 20, 0]);
+        assert_eq!(synth.params.damping.get(), 0.0);
+        // Other CCs are left alone.
+        synth.process_midi_event([176, 21, 127]);
+        assert_eq!(synth.params.damping.get(), 0.0);
+    }
+
+    #[test]
+    fn test_preset_round_trip() {
+        let synth = SineSynth::new(HostCallback::default());
+        synth.params.delay_mix.set(0.25);
+        synth.params.learn(35);
+        synth.params.cc_binding(20);
+        let data = synth.params.get_preset_data();
+
+        let mut loaded = SineSynth::new(HostCallback::default());
+        loaded.params.load_preset_data(&data);
+        assert_eq!(loaded.params.delay_mix.get(), 0.25);
+        loaded.process_midi_event([176, 20, 127]);
+        assert_eq!(loaded.params.damping.get(), 1.0);
+
+        // A preset cut short keeps what it has.
+        let mut short = SineSynth::new(HostCallback::default());
+        short.params.load_preset_data(&data[..8]);
+        assert_eq!(short.params.get_parameter(0), synth.params.get_parameter(0));
+        let damping = short.params.damping.get();
+        short.process_midi_event([176, 20, 127]);
+        assert_eq!(short.params.damping.get(), damping);
+    }
+
+    #[test]
+    fn test_program_change_switches_preset() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.drive.set(0.9);
+        synth.process_midi_event([192, 4, 0]);
+        assert_eq!(synth.params.get_preset_num(), 4);
+        assert_eq!(synth.params.get_preset_name(4), "Echo space");
+        assert_eq!(synth.params.delay_mix.get(), 0.4);
+        // Whatever the preset does not set goes back to its default.
+        assert_eq!(synth.params.drive.get(), 0.0);
+
+        // Programs past the last preset pick the last one.
+        synth.process_midi_event([192, 3, 0]);
+        synth.process_midi_event([192, 100, 0]);
+        assert_eq!(synth.params.get_preset_num(), 4);
+        assert_eq!(synth.params.bit_depth.get(), 1.0);
+
+        // Bypass, freeze and a MIDI learn waiting for its CC are left as they were.
+        synth.params.bypass.set(1.0);
+        synth.params.freeze.set(1.0);
+        synth.params.learn(35);
+        synth.process_midi_event([192, 1, 0]);
+        assert_eq!(synth.params.get_preset_num(), 1);
+        assert_eq!(synth.params.bypass.get(), 1.0);
+        assert!(synth.params.freeze());
+        assert_eq!(synth.params.midi_learn(), Some(35));
+    }
+
+    #[test]
+    fn test_key_range() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(39), "C-1");
+        assert_eq!(synth.params.get_parameter_text(40), "G9");
+
+        synth.params.key_high.set(step_to_parameter(59, 128));
+        assert_eq!(synth.params.get_parameter_text(40), "B3");
+        synth.process_midi_event([144, 60, 100]);
+        assert!(synth.notes.is_empty());
+        synth.process_midi_event([144, 59, 100]);
+        assert_eq!(synth.notes.len(), 1);
+
+        // Narrowing the range leaves a held note to be released as usual.
+        synth.params.key_low.set(step_to_parameter(70, 128));
+        synth.process_midi_event([128, 59, 0]);
+        assert!(synth.notes[0].released);
+    }
+
+    #[test]
+    fn test_velocity_curves() {
+        let gains =
+            |curve: VelocityCurve| -> Vec<f64> { (1..128).map(|v| curve.gain(v)).collect() };
+        let linear = gains(VelocityCurve::Linear);
+        assert_eq!(linear[126], 1.0);
+        assert!((linear[63] - 64.0 / 127.0).abs() < 1e-9);
+        for (curve, bend) in [(VelocityCurve::Soft, 1.0), (VelocityCurve::Hard, -1.0)].iter() {
+            let curved = gains(*curve);
+            // Both rise from nothing to full level, bowing either side of linear on the way.
+            assert!(curved.windows(2).all(|pair| pair[1] > pair[0]), "{}", curve.name());
+            assert_eq!(curved[126], 1.0);
+            for (&curved, &linear) in curved[..126].iter().zip(linear.iter()) {
+                assert!((curved - linear) * bend > 0.0, "{}", curve.name());
+            }
+        }
+        assert!(gains(VelocityCurve::Fixed).iter().all(|&gain| gain == 1.0));
+
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.velocity_curve.set(VelocityCurve::Hard.to_parameter());
+        synth.process_midi_event([144, 60, 64]);
+        assert_eq!(synth.notes[0].velocity, VelocityCurve::Hard.gain(64));
+        // No velocity at all is a note-off.
+        synth.process_midi_event([144, 60, 0]);
+        assert!(synth.notes[0].released);
+    }
+
+    #[test]
+    fn test_attack_restarts_or_continues() {
+        for &retrigger in [true, false].iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            // A one second attack.
+            synth.params.attack.set(0.5f32.sqrt());
+            synth.params.retrigger.set(if retrigger { 1.0 } else { 0.0 });
+            synth.process_midi_event([144, 60, 127]);
+            render(&mut synth, 22050);
+            let halfway = synth.notes[0].alpha;
+            assert!((halfway - 0.5).abs() < 1e-3, "{}", halfway);
+
+            // Striking the held key again.
+            synth.process_midi_event([144, 60, 127]);
+            assert_eq!(synth.notes.len(), 1);
+            let expected = if retrigger { 0.0 } else { halfway };
+            assert_eq!(synth.notes[0].alpha, expected);
+
+            // Striking it again while it fades out.
+            render(&mut synth, 22050);
+            synth.process_midi_event([128, 60, 0]);
+            render(&mut synth, 100);
+            let fading = synth.notes[0].alpha;
+            synth.process_midi_event([144, 60, 127]);
+            let voice = synth.notes.last().unwrap();
+            assert!(!voice.released);
+            if retrigger {
+                assert_eq!(synth.notes.len(), 2);
+                assert_eq!(voice.alpha, 0.0);
+            } else {
+                assert_eq!(synth.notes.len(), 1);
+                assert_eq!(voice.alpha, fading);
+                // And it carries on rising from there.
+                render(&mut synth, 1);
+                assert!(synth.notes[0].alpha > fading);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tempo_sync() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.lfo_sync.set(step_to_parameter(Division::Quarter as usize, 10));
+        synth.params.delay_sync.set(step_to_parameter(Division::DottedEighth as usize, 10));
+        // Without a tempo both run free.
+        assert_eq!(synth.lfo_rate(), synth.params.lfo_rate());
+        assert_eq!(synth.delay_time(), synth.params.delay_time());
+
+        // At 120bpm a quarter note is 22050 samples, and a dotted eighth 16537.5.
+        synth.tempo = Some(120.0);
+        assert_eq!(synth.sample_rate / synth.lfo_rate(), 22050.0);
+        assert_eq!(synth.delay_time() * synth.sample_rate, 16537.5);
+        assert_eq!(synth.params.get_parameter_text(45), "1/8 dotted");
+
+        // Echoes can be no longer than the line, however slow the tempo.
+        synth.params.delay_sync.set(step_to_parameter(Division::Whole as usize, 10));
+        synth.tempo = Some(60.0);
+        assert_eq!(synth.delay_time(), MAX_DELAY);
+    }
+
+    /// The energy of `synth`'s left channel once the strings have died away, with `stacked`
+    /// copies of the same note held and Perlin noise the only thing left sounding.
+    fn stacked_energy(voice_offset: f32, stacked: usize) -> f32 {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.voice_offset.set(voice_offset);
+        synth.params.duplicate_notes.set(DuplicateNotes::Stack.to_parameter());
+        synth.params.damping.set(0.0);
+        synth.params.ceiling.set(1.0);
+        synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+        for _ in 0..stacked {
+            synth.process_midi_event([144, 69, 127]);
+        }
+        render(&mut synth, 44100);
+        render(&mut synth, 44100)[0].iter().map(|&s| s * s).sum()
+    }
+
+    #[test]
+    fn test_voice_offset_decorrelates_stacked_notes() {
+        // Voices reading the same noise add up in amplitude, and so quadruple the energy. Ones
+        // reading different parts of it add up in power, and only double it.
+        let ratio = stacked_energy(0.0, 2) / stacked_energy(0.0, 1);
+        assert!((ratio - 4.0).abs() < 1e-3, "{}", ratio);
+        let ratio = stacked_energy(1.0, 2) / stacked_energy(1.0, 1);
+        assert!(ratio > 1.0 && ratio < 3.0, "{}", ratio);
+    }
+
+    #[test]
+    fn test_damping_is_shown_as_a_coefficient() {
+        let synth = SineSynth::new(HostCallback::default());
+        let params = &synth.params;
+        params.set_parameter(35, 1.0);
+        assert_eq!(params.get_parameter_text(35), "0.9999");
+        params.set_parameter(35, 0.0);
+        assert_eq!(params.get_parameter_text(35), "0.9000");
+        assert!(!params.get_parameter_text(35).ends_with('s'));
+
+        for &value in [-1.0, 2.0].iter() {
+            params.set_parameter(35, value);
+            assert!((MIN_DAMPING..=MAX_DAMPING).contains(&params.damping()));
+        }
+    }
+
+    #[test]
+    fn test_decay_time_holds_at_any_rate() {
+        let decay = ParamId::DecayTime as i32;
+        let synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(decay), "Off");
+        synth.params.set_parameter(decay, 0.4);
+        assert_eq!(synth.params.get_parameter_text(decay), "1.29 s");
+        let setting = synth.params.decay_time().unwrap();
+
+        // How long A4 would take to fall 60 dB at its pitch, from how far it falls between a
+        // tenth of a second in and half a second.
+        let measured = |sample_rate: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.set_sample_rate(sample_rate);
+            synth.params.ceiling.set(1.0);
+            synth.params.set_parameter(decay, 0.4);
+            synth.process_midi_event([144, 69, 100]);
+            let rate = f64::from(sample_rate);
+            let tenth = (rate * 0.1) as usize;
+            let output = render(&mut synth, 6 * tenth).swap_remove(0);
+            let level = |from: usize| {
+                let (mut re, mut im) = (0.0, 0.0);
+                for (i, &sample) in output[from..from + tenth].iter().enumerate() {
+                    let angle = TAU * 440.0 * i as f64 / rate;
+                    re += f64::from(sample) * angle.cos();
+                    im += f64::from(sample) * angle.sin();
+                }
+                (re * re + im * im).sqrt()
+            };
+            let fall = 20.0 * (level(tenth) / level(5 * tenth)).log10();
+            60.0 * 0.4 / fall
+        };
+        for &sample_rate in [44100.0, 96000.0].iter() {
+            let off = measured(sample_rate) / setting - 1.0;
+            assert!(off.abs() < 0.02, "{} {}", sample_rate, off);
+        }
+    }
+
+    /// How closely the left channel repeats itself one noise period later, with a lone A4 and
+    /// only Perlin noise left sounding.
+    fn noise_period_correlation(noise_3d: f32) -> f64 {
+        let mut synth = SineSynth::new(HostCallback::default());
+        // At this rate A4 steps exactly a hundredth of a cycle a sample, so it comes round to
+        // the same place in noise that repeats every 256 cycles after 25600 samples.
+        synth.set_sample_rate(44000.0);
+        synth.params.voice_offset.set(0.0);
+        synth.params.noise_3d.set(noise_3d);
+        synth.params.damping.set(0.0);
+        synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+        synth.process_midi_event([144, 69, 127]);
+        // Let the string die away.
+        render(&mut synth, 44000);
+        let output = &render(&mut synth, 25600 + 4000)[0];
+        let (first, second) = (&output[..4000], &output[25600..]);
+        let dot = |a: &[f32], b: &[f32]| -> f64 {
+            a.iter().zip(b).map(|(&a, &b)| f64::from(a) * f64::from(b)).sum()
+        };
+        dot(first, second) / (dot(first, first) * dot(second, second)).sqrt()
+    }
+
+    #[test]
+    fn test_3d_noise_is_less_periodic() {
+        let flat = noise_period_correlation(0.0);
+        let drifting = noise_period_correlation(1.0);
+        assert!(flat > 0.99, "{}", flat);
+        assert!(drifting < 0.5, "{}", drifting);
+    }
+
+    #[test]
+    fn test_chorus_widens_the_output() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.output_mode.set(OutputMode::Mono.to_parameter());
+        synth.process_midi_event([144, 69, 127]);
+        let dry = render(&mut synth, 4410);
+        assert_eq!(dry[0], dry[1]);
+
+        // The two sides are swept in opposite directions, so even a mono voice comes out wide.
+        synth.params.chorus_mix.set(0.5);
+        let wet = render(&mut synth, 4410);
+        assert!(wet[0].iter().zip(wet[1].iter()).any(|(l, r)| (l - r).abs() > 1e-3));
+    }
+
+    /// The left channel once the strings have died away, with `note` held and only `source`
+    /// sounding.
+    fn noise_only(synth: &mut SineSynth, note: u8, source: NoiseSource) -> Vec<f32> {
+        synth.params.amounts[source as usize].set(1.0);
+        settled_noise(synth, note)
+    }
+
+    /// The left channel once the strings have died away, with `note` held and the noise
+    /// sources as they are.
+    fn settled_noise(synth: &mut SineSynth, note: u8) -> Vec<f32> {
+        // Measured as it is, without the safety ceiling cutting in.
+        synth.params.ceiling.set(1.0);
+        synth.params.voice_offset.set(0.0);
+        synth.params.damping.set(0.0);
+        synth.process_midi_event([144, note, 127]);
+        render(synth, 44100);
+        render(synth, 4410).swap_remove(0)
+    }
+
+    #[test]
+    fn test_noise_ratio_scales_one_source() {
+        let perlin = NoiseSource::Perlin as usize;
+        // Perlin noise read twice as fast under A4 is the same as under A5.
+        let mut doubled = SineSynth::new(HostCallback::default());
+        doubled.params.ratios[perlin].set(0.75);
+        assert_eq!(doubled.params.get_parameter_text(ParamId::PerlinRate as i32), "2.00x");
+        let mut plain = SineSynth::new(HostCallback::default());
+        let octave_up = noise_only(&mut plain, 81, NoiseSource::Perlin);
+        let doubled = noise_only(&mut doubled, 69, NoiseSource::Perlin);
+        for (&a, &b) in doubled.iter().zip(octave_up.iter()) {
+            assert!((a - b).abs() < 1e-5, "{} {}", a, b);
+        }
+
+        // And every other source carries on at the voice's pitch.
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.ratios[perlin].set(0.75);
+        let value = noise_only(&mut synth, 69, NoiseSource::Value);
+        let mut plain = SineSynth::new(HostCallback::default());
+        let unchanged = noise_only(&mut plain, 69, NoiseSource::Value);
+        assert_eq!(value, unchanged);
+    }
+
+    #[test]
+    fn test_tremolo_pulses_the_level() {
+        let steady = |tremolo_depth: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            // 20hz, so a cycle every 2205 samples.
+            synth.params.tremolo_rate.set(1.0);
+            synth.params.tremolo_depth.set(tremolo_depth);
+            noise_only(&mut synth, 69, NoiseSource::Perlin)
+        };
+        let plain = steady(0.0);
+        let mut untouched = SineSynth::new(HostCallback::default());
+        assert_eq!(plain, noise_only(&mut untouched, 69, NoiseSource::Perlin));
+
+        let pulsing = steady(0.6);
+        let gains: Vec<Option<f32>> = plain
+            .iter()
+            .zip(pulsing.iter())
+            .map(|(&plain, &pulsing)| Some(pulsing / plain).filter(|_| plain.abs() > 1e-3))
+            .collect();
+        let lowest = gains.iter().flatten().fold(1.0f32, |lowest, &gain| lowest.min(gain));
+        assert!((lowest - 0.4).abs() < 0.01, "{}", lowest);
+        for &gain in gains.iter().flatten() {
+            assert!(gain > 0.4 - 1e-3 && gain < 1.0 + 1e-3, "{}", gain);
+        }
+        for pair in gains.iter().zip(gains[2205..].iter()) {
+            if let (Some(a), Some(b)) = pair {
+                assert!((a - b).abs() < 1e-3, "{} {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fm_reshapes_its_target() {
+        let spectrum = |fm_amount: f32, fm_target: NoiseSource| -> Vec<f64> {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.fm_amount.set(fm_amount);
+            synth.params.fm_target.set(step_to_parameter(fm_target as usize, NOISE_SOURCES));
+            let output = noise_only(&mut synth, 69, NoiseSource::Worley);
+            (1..50).map(|bin| magnitude(&output, f64::from(bin) * 100.0)).collect()
+        };
+        let plain = spectrum(0.0, NoiseSource::Worley);
+        // Perlin is the default modulator, and is not even audible here.
+        let modulated = spectrum(1.0, NoiseSource::Worley);
+        let change: f64 = plain.iter().zip(modulated.iter()).map(|(a, b)| (a - b).abs()).sum();
+        assert!(change > 0.1 * plain.iter().sum::<f64>(), "{}", change);
+
+        // Aimed at another source, Worley is left alone.
+        assert_eq!(spectrum(1.0, NoiseSource::Value), plain);
+    }
+
+    #[test]
+    fn test_bypass_passes_input_through() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.set_parameter(69, 1.0);
+        synth.process_midi_event([144, 60, 127]);
+        let inputs: Vec<Vec<f32>> = (0..2)
+            .map(|c| (0..512).map(|i| ((i + c * 7) as f32 * 0.1).sin()).collect())
+            .collect();
+        let mut outputs = vec![vec![1.0f32; 512]; 2];
+        {
+            let input_ptrs: Vec<*const f32> = inputs.iter().map(|c| c.as_ptr()).collect();
+            let mut output_ptrs: Vec<*mut f32> = outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+            let mut buffer = unsafe {
+                AudioBuffer::from_raw(2, 2, input_ptrs.as_ptr(), output_ptrs.as_mut_ptr(), 512)
+            };
+            synth.process(&mut buffer);
+        }
+        assert_eq!(outputs, inputs);
+        assert!(synth.notes.is_empty());
+        assert!(matches!(synth.can_do(CanDo::Bypass), Supported::No));
+    }
+
+    #[test]
+    fn test_build_from_config() {
+        let config = SynthConfig {
+            drive: 0.25,
+            attack: 0.5,
+            amounts: {
+                let mut amounts = [0.0; NOISE_SOURCES];
+                amounts[NoiseSource::Pink as usize] = 0.75;
+                amounts
+            },
+            delay_mix: 1.0,
+            ..SynthConfig::default()
+        };
+        let synth = SineSynth::with_config(HostCallback::default(), &config);
+        assert_eq!(synth.params.config(), config);
+        assert_eq!(synth.params.drive.get(), 0.25);
+        assert_eq!(synth.params.get_parameter(42), 0.5);
+        assert_eq!(synth.params.get_parameter(20), 0.75);
+        assert_eq!(synth.params.get_parameter(26), 1.0);
+        // Everything else is left at its default.
+        let plain = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter(0), plain.params.get_parameter(0));
+        assert_eq!(synth.params.get_parameter_text(43), plain.params.get_parameter_text(43));
+
+        // Held to the same ranges as the host, with nothing that is not a number let in.
+        let config = SynthConfig {
+            damping: 2.0,
+            drive: f32::NAN,
+            ..SynthConfig::default()
+        };
+        let params = SynthParameters::with_config(&config);
+        assert_eq!(params.damping.get(), 1.0);
+        assert_eq!(params.drive.get(), plain.params.drive.get());
+    }
+
+    #[test]
+    fn test_param_ids_round_trip() {
+        for (index, &id) in ParamId::ALL.iter().enumerate() {
+            assert_eq!(id as i32, index as i32);
+            assert_eq!(ParamId::try_from(id as i32), Ok(id));
+        }
+        assert_eq!(ParamId::try_from(-1), Err(()));
+        assert_eq!(ParamId::try_from(PARAMETER_COUNT), Err(()));
+        for (&source, (&amount, &rate)) in NoiseSource::ALL
+            .iter()
+            .zip(ParamId::AMOUNTS.iter().zip(ParamId::RATES.iter()))
+        {
+            assert_eq!(amount.amount(), Some(source));
+            assert_eq!(rate.rate(), Some(source));
+        }
+        for (&source, &enable) in NoiseSource::ALL.iter().zip(ParamId::ENABLES.iter()) {
+            assert_eq!(enable.enable(), Some(source));
+        }
+    }
+
+    #[test]
+    fn test_single_source_matches_blending_it_alone() {
+        let mut blended = SineSynth::new(HostCallback::default());
+        let blended = noise_only(&mut blended, 69, NoiseSource::Billow);
+
+        let mut single = SineSynth::new(HostCallback::default());
+        single.params.source_mode.set(SourceMode::Single.to_parameter());
+        let billow = NoiseSource::Billow as usize;
+        single.params.selected_source.set(step_to_parameter(billow, NOISE_SOURCES));
+        // The amounts are ignored, so leaving another source up makes no difference.
+        single.params.amounts[NoiseSource::Worley as usize].set(1.0);
+        assert_eq!(single.noise_blend().amounts(&single.lfo)[billow], 1.0);
+        let single = noise_only(&mut single, 69, NoiseSource::Worley);
+        assert_eq!(single, blended);
+    }
+
+    #[test]
+    fn test_silent_sources_are_left_out() {
+        let synth = SineSynth::new(HostCallback::default());
+        synth.params.amounts[NoiseSource::White as usize].set(0.5);
+        assert_eq!(synth.noise_blend().active(), &[NoiseSource::White]);
+
+        // A source at nothing can still be brought in by the LFO.
+        let fbm = NoiseSource::Fbm as usize;
+        synth.params.lfo_target.set(step_to_parameter(fbm, NOISE_SOURCES));
+        synth.params.lfo_depth.set(0.5);
+        assert_eq!(synth.noise_blend().active(), &[NoiseSource::White, NoiseSource::Fbm]);
+        synth.params.amounts[NoiseSource::White as usize].set(0.0);
+        synth.params.lfo_depth.set(0.0);
+        assert!(synth.noise_blend().active().is_empty());
+    }
+
+    #[test]
+    fn test_normalized_amounts_keep_their_level() {
+        let blend = |normalize: f32, scale: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.normalize.set(normalize);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(0.25 * scale);
+            synth.params.amounts[NoiseSource::Value as usize].set(0.1 * scale);
+            settled_noise(&mut synth, 69)
+        };
+        let energy = |output: &[f32]| output.iter().map(|&s| s * s).sum::<f32>();
+
+        // Doubling every weight leaves the blend exactly as it was.
+        let normalized = blend(1.0, 1.0);
+        assert!(energy(&normalized) > 0.0);
+        assert_eq!(blend(1.0, 2.0), normalized);
+        // Where mixed raw, it quadruples the energy.
+        let ratio = energy(&blend(0.0, 2.0)) / energy(&blend(0.0, 1.0));
+        assert!((ratio - 4.0).abs() < 1e-2, "{}", ratio);
+    }
+
+    #[test]
+    fn test_freeze_holds_the_texture() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.amounts[NoiseSource::Brown as usize].set(1.0);
+        let moving = noise_only(&mut synth, 69, NoiseSource::Perlin);
+        assert!(moving.windows(2).any(|pair| pair[0] != pair[1]));
+
+        // Frozen, every sample is the last one over again.
+        synth.params.freeze.set(1.0);
+        let frozen = render(&mut synth, 4410).swap_remove(0);
+        assert!(frozen.iter().all(|&sample| sample == frozen[0]), "{:?}", &frozen[..4]);
+
+        // And thawed, it moves on from there.
+        synth.params.freeze.set(0.0);
+        let thawed = render(&mut synth, 4410).swap_remove(0);
+        assert!(thawed.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn test_width_scales_the_side_signal() {
+        let side_energy = |width: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.output_mode.set(OutputMode::Stereo.to_parameter());
+            synth.params.width.set(width);
+            synth.params.ceiling.set(1.0);
+            synth.params.voice_offset.set(0.0);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+            synth.process_midi_event([144, 69, 127]);
+            let output = render(&mut synth, 4410);
+            output[0].iter().zip(output[1].iter()).map(|(l, r)| (l - r) * (l - r)).sum::<f32>()
+        };
+        assert_eq!(side_energy(0.0), 0.0);
+        let (neutral, wide) = (side_energy(0.5), side_energy(1.0));
+        assert!(neutral > 0.0);
+        assert!(wide > neutral * 3.0, "{} {}", neutral, wide);
+    }
+
+    #[test]
+    fn test_high_pass_cuts_the_low_end() {
+        // The share of the output's energy left by a low-pass at around 70hz.
+        let low_share = |hp_cutoff: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.hp_cutoff.set(hp_cutoff);
+            let output = noise_only(&mut synth, 69, NoiseSource::White);
+            let (mut low, mut total, mut smoothed) = (0.0, 0.0, 0.0);
+            for &sample in output.iter() {
+                smoothed += 0.01 * (sample - smoothed);
+                low += smoothed * smoothed;
+                total += sample * sample;
+            }
+            low / total
+        };
+        assert_eq!(SineSynth::new(HostCallback::default()).params.get_parameter_text(75), "Off");
+        let (open, cut) = (low_share(0.0), low_share(0.6));
+        assert!(cut < open * 0.1, "{} {}", open, cut);
+    }
+
+    #[test]
+    fn test_filter_is_worked_out_once_a_block_it_changes() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.process_midi_event([144, 60, 127]);
+        let filter = Derived::Filter as usize;
+        // Everything is worked out on the first block, and nothing again until it changes.
+        render(&mut synth, 512);
+        render(&mut synth, 512);
+        assert_eq!(synth.rebuilds, [1; 3]);
+
+        // However long the block, and however many times in it the cutoff moves.
+        for &cutoff in [0.2, 0.4, 0.6].iter() {
+            synth.params.set_parameter(ParamId::HpCutoff as i32, cutoff);
+        }
+        render(&mut synth, 4096);
+        assert_eq!(synth.rebuilds[filter], 2);
+        render(&mut synth, 512);
+        assert_eq!(synth.rebuilds, [2, 1, 1]);
+
+        // The others wait for their own parameters, or for a new sample rate.
+        let gate = ParamId::GateThreshold as i32;
+        synth.params.set_parameter(gate, 0.1);
+        render(&mut synth, 512);
+        assert_eq!(synth.rebuilds, [2, 2, 1]);
+        synth.set_sample_rate(48000.0);
+        render(&mut synth, 512);
+        assert_eq!(synth.rebuilds, [3, 3, 2]);
+    }
+
+    #[cfg(feature = "diagnostics")]
+    thread_local! {
+        static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    }
+
+    /// Keeps the log lines from threads that have asked for them, so tests can read them back.
+    #[cfg(feature = "diagnostics")]
+    struct CaptureLogger;
+
+    #[cfg(feature = "diagnostics")]
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let _ = CAPTURED.try_with(|captured| {
+                if let Some(lines) = captured.borrow_mut().as_mut() {
+                    lines.push(record.args().to_string());
+                }
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_reports_voices() {
+        static LOGGER: CaptureLogger = CaptureLogger;
+        // Another test may have got there first.
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Debug);
+        CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+
+        let mut synth = SineSynth::new(HostCallback::default());
+        for &note in [60, 64, 67].iter() {
+            synth.process_midi_event([144, note, 127]);
+        }
+        // A second and a bit in small blocks is reported once, not once a block, and the
+        // released note is caught before it has faded out.
+        for block in 0..100 {
+            if block == 80 {
+                synth.process_midi_event([128, 64, 0]);
+            }
+            render(&mut synth, 512);
+        }
+
+        let lines = CAPTURED.with(|captured| captured.borrow_mut().take().unwrap());
+        assert_eq!(lines.len(), 4, "{:?}", lines);
+        assert_eq!(lines[0], "3 voices");
+        assert!(lines[1].starts_with("note 60: alpha "), "{}", lines[1]);
+        assert!(lines[2].ends_with(", released"), "{}", lines[2]);
+        assert!(lines[3].ends_with(", held"), "{}", lines[3]);
+    }
+
+    /// The envelope of a single voice under `attack` and `release`, read every 20th of a second
+    /// through a half second attack, then every 100th of a second through its release.
+    fn envelope_trajectory(attack: EnvelopeCurve, release: EnvelopeCurve) -> (Vec<f64>, Vec<f64>) {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.attack.set(0.5);
+        synth.params.attack_curve.set(attack.to_parameter());
+        synth.params.release_curve.set(release.to_parameter());
+        synth.process_midi_event([144, 60, 127]);
+        let read = |synth: &mut SineSynth, samples| {
+            render(synth, samples);
+            synth.notes.first().map_or(0.0, |note| note.envelope(attack, release))
+        };
+        let rising = (0..10).map(|_| read(&mut synth, 2205)).collect();
+        synth.process_midi_event([128, 60, 0]);
+        let falling = (0..10).map(|_| read(&mut synth, 441)).collect();
+        (rising, falling)
+    }
+
+    #[test]
+    fn test_envelope_curves() {
+        let (linear, exponential, logarithmic) =
+            (EnvelopeCurve::Linear, EnvelopeCurve::Exponential, EnvelopeCurve::Logarithmic);
+        let synth = SineSynth::new(HostCallback::default());
+        synth.params.release_curve.set(logarithmic.to_parameter());
+        assert_eq!(synth.params.get_parameter_text(ParamId::ReleaseCurve as i32), "Logarithmic");
+        for &attack in EnvelopeCurve::ALL.iter() {
+            for &release in EnvelopeCurve::ALL.iter() {
+                let (rising, falling) = envelope_trajectory(attack, release);
+                // Each half follows its own curve alone.
+                assert_eq!(rising, envelope_trajectory(attack, linear).0);
+                assert_eq!(falling, envelope_trajectory(linear, release).1);
+                for pair in rising.windows(2) {
+                    assert!(pair[1] > pair[0], "{:?} attack: {:?}", attack, rising);
+                }
+                for pair in falling.windows(2) {
+                    assert!(pair[1] < pair[0] || pair[1] == 0.0, "{:?} release", release);
+                }
+                assert!((rising[9] - 1.0).abs() < 1e-9);
+                assert_eq!(falling[9], 0.0);
+            }
+        }
+
+        // Logarithmic is the quickest to rise and the slowest to fall, exponential the other way.
+        let (lin_up, lin_down) = envelope_trajectory(linear, linear);
+        let (exp_up, exp_down) = envelope_trajectory(exponential, exponential);
+        let (log_up, log_down) = envelope_trajectory(logarithmic, logarithmic);
+        assert!((lin_up[4] - 0.5).abs() < 1e-3, "{}", lin_up[4]);
+        for i in 0..9 {
+            assert!(exp_up[i] < lin_up[i] && lin_up[i] < log_up[i], "{}", i);
+            assert!(exp_down[i] < lin_down[i] && lin_down[i] < log_down[i], "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_release_continues_from_attack() {
+        for &(attack, release) in [
+            (EnvelopeCurve::Logarithmic, EnvelopeCurve::Exponential),
+            (EnvelopeCurve::Exponential, EnvelopeCurve::Logarithmic),
+        ]
+        .iter()
+        {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.attack.set(0.5);
+            synth.params.attack_curve.set(attack.to_parameter());
+            synth.params.release_curve.set(release.to_parameter());
+            synth.process_midi_event([144, 60, 127]);
+            // Let go halfway up the attack.
+            render(&mut synth, 11025);
+            let held = synth.notes[0].envelope(attack, release);
+            synth.process_midi_event([128, 60, 0]);
+            let released = synth.notes[0].envelope(attack, release);
+            assert!((held - released).abs() < 1e-9, "{} then {}", held, released);
+        }
+    }
+
+    #[test]
+    fn test_release_mid_attack_falls_away_smoothly() {
+        for &curve in EnvelopeCurve::ALL.iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.attack.set(0.5);
+            synth.params.attack_curve.set(curve.to_parameter());
+            synth.params.release_curve.set(curve.to_parameter());
+            synth.process_midi_event([144, 60, 127]);
+            // Let go early in the attack, once the onset is over.
+            render(&mut synth, 441);
+            synth.process_midi_event([128, 60, 64]);
+            let mut levels = Vec::new();
+            while !synth.notes.is_empty() {
+                levels.push(synth.notes[0].envelope(curve, curve));
+                render(&mut synth, 1);
+            }
+            assert!(levels[0] > 0.0);
+            for pair in levels.windows(2) {
+                assert!(pair[1] <= pair[0], "{:?} {:?}", curve, pair);
+            }
+            // And from the level reached, no slower than from the top.
+            let longest = (RELEASE * 44100.0) as usize + 2;
+            assert!(levels.len() <= longest, "{}", levels.len());
+        }
+    }
+
+    #[test]
+    fn test_humanize_varies_each_strike() {
+        for &humanize in [0.0, 1.0].iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.duplicate_notes.set(DuplicateNotes::Stack.to_parameter());
+            synth.params.humanize.set(humanize);
+            synth.params.attack.set(0.1);
+            synth.process_midi_event([144, 60, 127]);
+            synth.process_midi_event([144, 60, 127]);
+            let (first, second) = (&synth.notes[0], &synth.notes[1]);
+            let exact = midi_pitch_to_freq(60);
+            if humanize == 0.0 {
+                assert_eq!((first.frequency(), second.frequency()), (exact, exact));
+                assert_eq!((first.wait, second.wait), (0, 0));
+            } else {
+                assert_ne!(first.frequency(), second.frequency());
+                for voice in [first, second].iter() {
+                    let cents = 1200.0 * (voice.frequency() / exact).log2();
+                    assert!(cents.abs() <= MAX_HUMANIZE_CENTS, "{}", cents);
+                    assert!(voice.wait as f64 <= MAX_HUMANIZE_DELAY * 44100.0);
+                }
+            }
+            // Voices held back are kept until they start, even before their attack has begun.
+            render(&mut synth, 16);
+            assert_eq!(synth.notes.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_reverb_rings_past_the_release() {
+        let tails: Vec<(isize, f32, f32)> = [0.0, 0.5]
+            .iter()
+            .map(|&mix| {
+                let mut synth = SineSynth::new(HostCallback::default());
+                synth.params.delay_mix.set(0.0);
+                synth.params.reverb_mix.set(mix);
+                synth.process_midi_event([144, 60, 127]);
+                render(&mut synth, 4410);
+                synth.process_midi_event([128, 60, 0]);
+                // Past the release, the voice is gone and only the reverb is left.
+                render(&mut synth, 8820);
+                let peak = |output: Vec<Vec<f32>>| {
+                    output[0].iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+                };
+                let soon = peak(render(&mut synth, 4410));
+                render(&mut synth, 44100);
+                (synth.get_tail_size(), soon, peak(render(&mut synth, 4410)))
+            })
+            .collect();
+        let (dry, wet) = (tails[0], tails[1]);
+        assert_eq!((dry.1, dry.2), (0.0, 0.0));
+        assert!(wet.1 > 1e-3, "{}", wet.1);
+        assert!(wet.2 < wet.1 * 0.1, "{} then {}", wet.1, wet.2);
+        assert!(wet.0 > dry.0 + 44100);
+    }
+
+    #[test]
+    fn test_gate_shuts_under_threshold() {
+        let level = |threshold: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.delay_mix.set(0.0);
+            synth.params.gate_threshold.set(threshold);
+            synth.params.gate_release.set(0.1);
+            synth.process_midi_event([144, 60, 127]);
+            render(&mut synth, 4410);
+            let output = render(&mut synth, 4410).swap_remove(0);
+            output.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        let synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(ParamId::GateThreshold as i32), "Off");
+        synth.params.gate_threshold.set(0.5);
+        assert_eq!(synth.params.get_parameter_text(ParamId::GateThreshold as i32), "-30.0 dB");
+
+        // A struck note is well over -60dB, but never reaches 0dB.
+        let open = level(0.0);
+        assert!(open > 0.1, "{}", open);
+        assert!(level(0.01) > open * 0.5, "{} against {}", level(0.01), open);
+        assert!(level(1.0) < 1e-3, "{}", level(1.0));
+    }
+
+    #[test]
+    fn test_compressor_turns_loud_notes_down() {
+        let params = SineSynth::new(HostCallback::default()).params;
+        let text = |id: ParamId| params.get_parameter_text(id as i32);
+        assert_eq!(text(ParamId::CompRatio), "1.0:1");
+        params.comp_threshold.set(0.5);
+        assert_eq!(text(ParamId::CompThreshold), "-30.0 dB");
+
+        // With the string silenced only the noise is heard, the same on every run.
+        let level = |ratio: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.ceiling.set(1.0);
+            synth.params.exciter.set(1.0);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+            synth.params.comp_threshold.set(0.5);
+            synth.params.comp_ratio.set(ratio);
+            synth.process_midi_event([144, 60, 127]);
+            let output = render(&mut synth, 8820).swap_remove(0);
+            let settled = &output[4410..];
+            settled.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        let (open, squashed) = (level(0.0), level(1.0));
+        // The loudest peaks are turned down by close to the full ratio, less what the attack
+        // lets through.
+        let over = |level: f32| 20.0 * (level / db_to_gain(-30.0)).log10();
+        let (open, squashed) = (over(open), over(squashed));
+        assert!(open > 10.0, "{}", open);
+        assert!(squashed < open / 4.0, "{} against {}", squashed, open);
+    }
+
+    #[test]
+    fn test_delay_spread_parts_the_echoes() {
+        for &spread in [0.0, 1.0].iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.set_sample_rate(1000.0);
+            // From the same mono voice, 100ms echoes on the left and 150ms on the right.
+            synth.params.output_mode.set(OutputMode::Mono.to_parameter());
+            synth.params.delay_time.set(0.05);
+            synth.params.delay_feedback.set(0.0);
+            synth.params.delay_mix.set(1.0);
+            synth.params.delay_spread.set(spread);
+            synth.process_midi_event([144, 69, 100]);
+            synth.process_midi_event([128, 69, 0]);
+
+            let output = render(&mut synth, 300);
+            let sounding = |channel: usize, from: usize, to: usize| {
+                output[channel][from..to].iter().any(|&s| s != 0.0)
+            };
+            if spread == 0.0 {
+                assert_eq!(output[0], output[1]);
+            } else {
+                assert_eq!(output[0][..100], output[1][..100]);
+                assert!(sounding(0, 100, 200) && !sounding(0, 200, 300));
+                assert!(!sounding(1, 100, 150) && sounding(1, 150, 250));
+            }
+        }
+    }
+
+    #[test]
+    fn test_ceiling_holds_runaway_output() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(ParamId::Ceiling as i32), "+0.5 dB");
+        // Everything that can pile up turned all the way up, then the ceiling brought down.
+        synth.params.duplicate_notes.set(DuplicateNotes::Stack.to_parameter());
+        synth.params.drive.set(1.0);
+        synth.params.delay_mix.set(1.0);
+        synth.params.delay_feedback.set(1.0);
+        synth.params.reverb_mix.set(1.0);
+        synth.params.reverb_size.set(1.0);
+        synth.params.width.set(1.0);
+        synth.params.ceiling.set(0.4);
+        let ceiling = 10.0f32.powf(synth.params.ceiling_db() / 20.0);
+        for note in 40..80 {
+            synth.process_midi_event([144, note, 127]);
+            synth.process_midi_event([176, 1, 127]);
+        }
+        let mut loudest = 0.0f32;
+        for _ in 0..20 {
+            for channel in render(&mut synth, 4410) {
+                loudest = channel.iter().fold(loudest, |peak, s| peak.max(s.abs()));
+            }
+        }
+        assert!(loudest <= ceiling, "{} over {}", loudest, ceiling);
+        // It was pushing at it the whole way, so the ceiling really was what held it.
+        assert_eq!(loudest, ceiling);
+    }
+
+    #[test]
+    fn test_note_starts_from_silence() {
+        for &attack in [0.0, 0.1, 1.0].iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.attack.set(attack);
+            synth.params.delay_mix.set(0.0);
+            synth.params.amounts[NoiseSource::White as usize].set(1.0);
+            synth.process_midi_event([144, 60, 127]);
+            let output = render(&mut synth, 441).swap_remove(0);
+            assert_eq!(output[0], 0.0, "{}", attack);
+            // And rises from there without a jump, even with no attack.
+            for (i, &sample) in output.iter().enumerate().take(10) {
+                assert!(sample.abs() <= i as f32 * 0.1, "{}: {} at {}", attack, sample, i);
+            }
+        }
+        // Past the onset, an instant attack is at full level.
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.process_midi_event([144, 60, 127]);
+        render(&mut synth, 441);
+        let note = &synth.notes[0];
+        assert_eq!(note.envelope(EnvelopeCurve::Linear, EnvelopeCurve::Linear), 1.0);
+    }
+
+    #[test]
+    fn test_any_output_count() {
+        let stereo = |synth: &mut SineSynth| {
+            synth.params.output_mode.set(OutputMode::Stereo.to_parameter());
+            synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+            synth.process_midi_event([144, 60, 127]);
+        };
+
+        // A mono host hears both sides, summed.
+        let mut synth = SineSynth::new(HostCallback::default());
+        stereo(&mut synth);
+        let mono = render_channels(&mut synth, 1, 512);
+        assert_eq!(mono.len(), 1);
+        assert!(mono[0].iter().any(|&s| s != 0.0));
+        assert!(mono[0].iter().all(|s| s.is_finite()));
+
+        // Past two, the outputs take turns at left and right, which differ.
+        let mut synth = SineSynth::new(HostCallback::default());
+        stereo(&mut synth);
+        let surround = render_channels(&mut synth, 4, 512);
+        assert_eq!((&surround[0], &surround[1]), (&surround[2], &surround[3]));
+        assert_ne!(surround[0], surround[1]);
+        assert!(surround.iter().all(|channel| channel.iter().any(|&s| s != 0.0)));
+    }
+
+    #[test]
+    fn test_scale_snap_collapses_a_run() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        let pentatonic = step_to_parameter(Scale::MajorPentatonic as usize, Scale::ALL.len());
+        synth.params.scale_snap.set(pentatonic);
+        assert_eq!(synth.params.get_parameter_text(ParamId::ScaleSnap as i32), "Major pentatonic");
+        for note in 60..72 {
+            synth.process_midi_event([144, note, 127]);
+        }
+        let mut pitches: Vec<f64> = synth.notes.iter().map(|note| note.frequency()).collect();
+        pitches.dedup_by(|a, b| (*a / *b - 1.0).abs() < 1e-9);
+        let degrees = [60, 62, 64, 67, 69, 72];
+        let expected: Vec<f64> = degrees.iter().map(|&note| midi_pitch_to_freq(note)).collect();
+        for (pitch, expected) in pitches.iter().zip(expected.iter()) {
+            assert!((pitch / expected - 1.0).abs() < 1e-9, "{} {}", pitch, expected);
+        }
+        assert_eq!(pitches.len(), expected.len());
+
+        // Each key still lets go of its own voice.
+        synth.process_midi_event([128, 61, 0]);
+        assert!(synth.notes[1].released && !synth.notes[0].released);
+    }
+
+    #[test]
+    fn test_active_notes_follow_the_voices() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        assert!(synth.active_notes().is_empty());
+        // A tenth of a second attack.
+        synth.params.attack.set(0.05f32.sqrt());
+        synth.process_midi_event([144, 60, 127]);
+        synth.process_midi_event([144, 64, 0x40]);
+        render(&mut synth, 2205);
+        let rising = synth.active_notes();
+        assert_eq!(rising.iter().map(|info| info.note).collect::<Vec<_>>(), [60, 64]);
+        assert!(rising.iter().all(|info| info.alpha > 0.4 && info.alpha < 0.6 && !info.released));
+        assert!(rising[0].velocity > rising[1].velocity);
+
+        // Once the attack is over, then with the first let go.
+        render(&mut synth, 4410);
+        synth.process_midi_event([128, 60, 0]);
+        let held = synth.active_notes();
+        assert!(held[0].released && !held[1].released);
+        assert_eq!(held[1].alpha, 1.0);
+
+        // And gone once it has faded out.
+        render(&mut synth, 4410);
+        let left = synth.active_notes();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0], held[1]);
+    }
+
+    #[test]
+    fn test_last_output_is_polled() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.output_mode.set(OutputMode::Stereo.to_parameter());
+        synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+        // Measured as it is, without the safety ceiling cutting in.
+        synth.params.ceiling.set(1.0);
+        synth.process_midi_event([144, 60, 127]);
+        let output = render(&mut synth, 512);
+        assert_ne!(output[0][511], 0.0);
+        assert_eq!(synth.params.last_output(0), output[0][511]);
+        assert_eq!(synth.params.last_output(1), output[1][511]);
+        assert_ne!(output[0][511], output[1][511]);
+
+        // An empty block leaves the last sample of the one before.
+        render(&mut synth, 0);
+        assert_eq!(synth.params.last_output(0), output[0][511]);
+    }
+
+    #[test]
+    fn test_release_velocity_sets_the_fade() {
+        // Samples until a voice let go at `velocity` has faded out.
+        let fade = |amount: f32, velocity: u8| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.rel_vel_amount.set(amount);
+            synth.process_midi_event([144, 60, 127]);
+            render(&mut synth, 64);
+            synth.process_midi_event([128, 60, velocity]);
+            let mut samples = 0;
+            while !synth.notes.is_empty() {
+                render(&mut synth, 1);
+                samples += 1;
+            }
+            samples
+        };
+        // Left alone, every note-off fades over RELEASE.
+        assert_eq!(fade(0.0, 0), 4410);
+        assert_eq!(fade(0.0, 127), 4410);
+        // The middle velocity stays there, and the ends go two octaves either way.
+        assert_eq!(fade(1.0, 64), 4410);
+        assert!((fade(1.0, 127) as i32 - 4410 / 4).abs() <= 1);
+        assert!((fade(1.0, 0) as f64 / 4410.0 - 4.09).abs() < 0.01, "{}", fade(1.0, 0));
+
+        // The tail allows for the slowest.
+        let synth = SineSynth::new(HostCallback::default());
+        synth.params.delay_mix.set(0.0);
+        synth.params.rel_vel_amount.set(1.0);
+        assert!(synth.get_tail_size() as usize >= fade(1.0, 0));
+    }
+
+    #[test]
+    fn test_transpose_shifts_new_notes() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        assert_eq!(synth.params.get_parameter_text(ParamId::Transpose as i32), "+0 st");
+        synth.process_midi_event([144, 60, 127]);
+        synth.params.transpose.set(step_to_parameter(36, 49));
+        assert_eq!(synth.params.get_parameter_text(ParamId::Transpose as i32), "+12 st");
+        synth.process_midi_event([144, 62, 127]);
+        // Only the note struck after the change is moved, and it is still let go by its key.
+        let ratio = synth.notes[1].frequency() / midi_pitch_to_freq(62);
+        assert!((ratio - 2.0).abs() < 1e-9, "{}", ratio);
+        assert_eq!(synth.notes[0].frequency(), midi_pitch_to_freq(60));
+        synth.process_midi_event([128, 62, 0]);
+        assert!(synth.notes[1].released);
+
+        // Pitches pushed off the top of the range are held at it.
+        synth.params.transpose.set(1.0);
+        synth.process_midi_event([144, 120, 127]);
+        let top = synth.notes.last().unwrap().frequency();
+        assert!((top / midi_pitch_to_freq(127) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_global_mix_blends_out_the_effects() {
+        let mut plain = SineSynth::new(HostCallback::default());
+        let plain = noise_only(&mut plain, 69, NoiseSource::Perlin);
+        let processed = |effects: bool, mix: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            if effects {
+                synth.params.drive.set(0.8);
+                synth.params.bit_depth.set(step_to_parameter(3, MAX_BIT_DEPTH as usize));
+                synth.params.ring_mod.set(0.5);
+                synth.params.tremolo_depth.set(1.0);
+            }
+            synth.params.oversampling.set(1.0);
+            synth.params.global_mix.set(mix);
+            noise_only(&mut synth, 69, NoiseSource::Perlin)
+        };
+        let wet = processed(true, 1.0);
+        assert_ne!(wet, plain);
+        // Fully dry is only the voices, whatever the effects are doing.
+        let dry = processed(false, 0.0);
+        assert_eq!(processed(true, 0.0), dry);
+        // And halfway, halfway between.
+        for ((&half, &dry), &wet) in processed(true, 0.5).iter().zip(dry.iter()).zip(wet.iter()) {
+            assert!((half - (dry + wet) * 0.5).abs() < 1e-5, "{} {} {}", half, dry, wet);
+        }
+        // Without oversampling, the voices alone are what they always were.
+        let mut unmixed = SineSynth::new(HostCallback::default());
+        unmixed.params.global_mix.set(0.0);
+        assert_eq!(noise_only(&mut unmixed, 69, NoiseSource::Perlin), plain);
+    }
+
+    #[test]
+    fn test_drift_wanders_held_notes() {
+        for &drift in [0.0, 1.0].iter() {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.set_sample_rate(1000.0);
+            synth.params.drift.set(drift);
+            synth.process_midi_event([144, 69, 100]);
+            let (mut lowest, mut highest) = (440.0f64, 440.0f64);
+            let mut previous = 440.0;
+            for _ in 0..10000 {
+                let phase = synth.notes[0].phase;
+                render(&mut synth, 1);
+                let freq = (synth.notes[0].phase - phase) * 1000.0;
+                if drift > 0.0 {
+                    // Slowly, never in jumps.
+                    assert!((freq - previous).abs() < 0.1, "{} {}", previous, freq);
+                }
+                previous = freq;
+                lowest = freq.min(lowest);
+                highest = freq.max(highest);
+            }
+            if drift == 0.0 {
+                assert!((highest - lowest).abs() < 1e-9);
+            } else {
+                assert!(highest / lowest > cents_to_ratio(1.0), "{} {}", lowest, highest);
+                assert!(highest <= 440.0 * cents_to_ratio(MAX_DRIFT_CENTS) + 1e-6);
+                assert!(lowest >= 440.0 * cents_to_ratio(-MAX_DRIFT_CENTS) - 1e-6);
+            }
+        }
+
+        // The string is heard to follow it, here sent from the bottom of its wander to the top
+        // over the two seconds a leg takes, measured every 10th of a second.
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.damping.set(1.0);
+        synth.params.drift.set(1.0);
+        synth.process_midi_event([144, 69, 100]);
+        synth.notes[0].wander = [-1.0, 1.0];
+        let output = render(&mut synth, 88200).swap_remove(0);
+        let pitches: Vec<f64> = output.chunks_exact(4410).map(pitch).collect();
+        let (first, last) = (pitches[0], pitches[pitches.len() - 1]);
+        assert!(first < 440.0 * cents_to_ratio(-10.0), "{:?}", pitches);
+        assert!(last > 440.0 * cents_to_ratio(10.0), "{:?}", pitches);
+        // Give or take the few cents the measurement wanders by over a stretch.
+        let rising = pitches.windows(2).all(|pair| pair[1] > pair[0] - 1.0);
+        assert!(rising, "{:?}", pitches);
+    }
+
+    #[test]
+    fn test_rounded_tuning_rings_sharp_up_the_keyboard() {
+        // How far out each key's string rings, in cents.
+        let errors = |tuning: Tuning| -> Vec<f64> {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.tuning_mode.set(step_to_parameter(tuning as usize, Tuning::ALL.len()));
+            (21..=108)
+                .map(|note| {
+                    synth.process_midi_event([144, note, 100]);
+                    let string = &synth.notes.last().unwrap().string;
+                    1200.0 * (string.frequency(44100.0) / midi_pitch_to_freq(note)).log2()
+                })
+                .collect()
+        };
+        assert_eq!(SynthParameters::default().tuning_mode(), Tuning::Exact);
+        assert!(errors(Tuning::Exact).iter().all(|cents| cents.abs() < 0.01));
+
+        // Rounding only ever shortens the line, so every key is sharp, by more the higher it
+        // is and the shorter its line.
+        let rounded = errors(Tuning::Rounded);
+        assert!(rounded.iter().all(|&cents| cents > 0.0));
+        let worst = |keys: &[f64]| keys.iter().cloned().fold(0.0, f64::max);
+        assert!(worst(&rounded[..12]) < 3.0, "{:?}", rounded);
+        assert!(worst(&rounded[rounded.len() - 12..]) > 50.0, "{:?}", rounded);
+    }
+
+    #[test]
+    fn test_note_flood_is_capped() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.set_parameter(0, DuplicateNotes::Stack.to_parameter());
+        let flood: Vec<MidiEvent> = (0..5000)
+            .map(|i| MidiEvent {
+                data: [144, (i % 128) as u8, (i % 127 + 1) as u8],
+                delta_frames: 0,
+                live: true,
+                note_length: None,
+                note_offset: None,
+                detune: 0,
+                note_off_velocity: 0,
+            })
+            .collect();
+        let mut events = SendEventBuffer::new(flood.len());
+        events.store_events(flood);
+        synth.process_events(events.events());
+        assert_eq!(synth.notes.len(), MAX_VOICES);
+        assert!(synth.notes.capacity() <= MAX_VOICES);
+        // The newest note is always the one sounding, and the plugin carries on.
+        assert_eq!(synth.notes.last().unwrap().note, (4999 % 128) as u8);
+        render(&mut synth, 512);
+    }
+
+    #[test]
+    fn test_full_voices_lose_the_quietest() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.set_parameter(0, DuplicateNotes::Stack.to_parameter());
+        for i in 0..MAX_VOICES {
+            // All loud but one in the middle.
+            let velocity = if i == 10 { 10 } else { 127 };
+            synth.process_midi_event([144, 60, velocity]);
+        }
+        synth.process_midi_event([144, 72, 127]);
+        assert_eq!(synth.notes.len(), MAX_VOICES);
+        assert!(synth.notes.iter().all(|note| note.velocity == synth.notes[0].velocity));
+        // With every voice as loud, the oldest goes.
+        synth.process_midi_event([144, 74, 127]);
+        assert_eq!(synth.notes[MAX_VOICES - 2].note, 72);
+        assert_eq!(synth.notes[MAX_VOICES - 1].note, 74);
+        assert_eq!(synth.notes.iter().filter(|note| note.note == 60).count(), MAX_VOICES - 2);
+    }
+
+    #[test]
+    fn test_haas_delay_holds_back_the_right() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.ceiling.set(1.0);
+        synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+        synth.process_midi_event([144, 60, 100]);
+        let mono = render(&mut synth, 1000);
+        assert_eq!(mono[0], mono[1]);
+
+        synth.params.haas_delay.set(0.5);
+        assert_eq!(synth.params.get_parameter_text(ParamId::HaasDelay as i32), "15.0 ms");
+        let widened = render(&mut synth, 4410);
+        let lag = (0.015 * 44100.0f64).round() as usize;
+        assert_eq!(widened[1][lag..], widened[0][..widened[0].len() - lag]);
+        assert_ne!(widened[0], widened[1]);
+    }
+
+    #[test]
+    fn test_arpeggiator_plays_held_keys_in_turn() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_sample_rate(1000.0);
+        synth.params.arp_on.set(1.0);
+        assert_eq!(synth.params.get_parameter_text(ParamId::ArpRate as i32), "1/16");
+        for &note in [67, 60, 64].iter() {
+            synth.process_midi_event([144, note, 100]);
+        }
+        assert!(synth.notes.is_empty());
+
+        let sounding = |synth: &SineSynth| -> Vec<u8> {
+            synth.notes.iter().filter(|n| !n.released).map(|n| n.note).collect()
+        };
+        // A sixteenth at 120bpm is 125 samples, and each key sounds alone for all of it.
+        let mut played = Vec::new();
+        for _ in 0..4 {
+            render(&mut synth, 1);
+            let step = sounding(&synth);
+            assert_eq!(step.len(), 1, "{:?}", step);
+            render(&mut synth, 124);
+            assert_eq!(sounding(&synth), step);
+            played.extend(step);
+        }
+        assert_eq!(played, [60, 64, 67, 60]);
+
+        // Letting go of every key silences it.
+        for &note in [60, 64, 67].iter() {
+            synth.process_midi_event([128, note, 64]);
+        }
+        assert!(sounding(&synth).is_empty());
+        render(&mut synth, 1000);
+        assert!(sounding(&synth).is_empty());
+    }
+
+    #[test]
+    fn test_midi_clock_sets_the_tempo() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.set_sample_rate(48000.0);
+        synth.params.arp_on.set(1.0);
+        synth.process_midi_event([144, 60, 100]);
+        let message = |status: u8, delta_frames: i32| MidiEvent {
+            data: [status, 0, 0],
+            delta_frames,
+            live: true,
+            note_length: None,
+            note_offset: None,
+            detune: 0,
+            note_off_velocity: 0,
+        };
+        // 24 ticks to a beat at 125bpm is one every 960 samples, landing all over the blocks.
+        let mut events = SendEventBuffer::new(4);
+        let mut next_tick = 0;
+        for block in 0..100 {
+            let start = block * 512;
+            let mut ticks = Vec::new();
+            while next_tick < start + 512 {
+                ticks.push(message(0xF8, next_tick - start));
+                next_tick += 960;
+            }
+            events.store_events(ticks);
+            synth.process_events(events.events());
+            render(&mut synth, 512);
+        }
+        let tempo = synth.tempo.unwrap();
+        assert!((tempo - 125.0).abs() < 1e-6, "{}", tempo);
+        assert!(synth.arp_voice.is_some());
+
+        // Stopped, the arpeggiator lets go and waits for the transport to start again.
+        events.store_events(vec![message(0xFC, 0)]);
+        synth.process_events(events.events());
+        render(&mut synth, 48000);
+        assert!(synth.arp_voice.is_none());
+        events.store_events(vec![message(0xFA, 0)]);
+        synth.process_events(events.events());
+        render(&mut synth, 1);
+        assert_eq!(synth.arp_voice, Some(60));
+    }
+
+    /// The spectral centroid of `samples` at 44100 samples a second, in hz.
+    fn spectral_centroid(samples: &[f32]) -> f64 {
+        let len = samples.len();
+        let (mut weighted, mut total) = (0.0, 0.0);
+        for bin in 1..len / 2 {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (i, &sample) in samples.iter().enumerate() {
+                let angle = TAU * (bin * i) as f64 / len as f64;
+                re += f64::from(sample) * angle.cos();
+                im -= f64::from(sample) * angle.sin();
+            }
+            let magnitude = (re * re + im * im).sqrt();
+            weighted += magnitude * bin as f64 * 44100.0 / len as f64;
+            total += magnitude;
+        }
+        weighted / total
+    }
+
+    #[test]
+    fn test_velocity_brightens_the_blend() {
+        let centroid = |vel_to_timbre: f32, velocity: u8| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.ceiling.set(1.0);
+            synth.params.damping.set(0.0);
+            synth.params.amounts[NoiseSource::White as usize].set(0.5);
+            synth.params.amounts[NoiseSource::Brown as usize].set(0.5);
+            synth.params.vel_to_timbre.set(vel_to_timbre);
+            synth.process_midi_event([144, 69, velocity]);
+            render(&mut synth, 44100);
+            spectral_centroid(&render(&mut synth, 1024)[0])
+        };
+        let (soft, hard) = (centroid(1.0, 20), centroid(1.0, 127));
+        assert!(hard > soft * 1.5, "{} against {}", hard, soft);
+        // Left at zero, the blend sounds the same however hard it is struck.
+        let (soft, hard) = (centroid(0.0, 20), centroid(0.0, 127));
+        assert!((hard / soft - 1.0).abs() < 0.1, "{} against {}", hard, soft);
+    }
+
+    #[test]
+    fn test_velocity_brightens_the_pluck() {
+        let centroid = |vel_to_brightness: f32, velocity: u8| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.ceiling.set(1.0);
+            synth.params.vel_to_brightness.set(vel_to_brightness);
+            synth.process_midi_event([144, 57, velocity]);
+            spectral_centroid(&render(&mut synth, 1024)[0])
+        };
+        let (soft, hard) = (centroid(1.0, 20), centroid(1.0, 127));
+        assert!(hard > soft * 1.5, "{} against {}", hard, soft);
+        // Left at zero, the pluck is as bright however hard it is struck.
+        let (soft, hard) = (centroid(0.0, 20), centroid(0.0, 127));
+        assert!((hard / soft - 1.0).abs() < 0.1, "{} against {}", hard, soft);
+    }
+
+    #[test]
+    fn test_volume_and_expression_turn_the_output_down() {
+        // Rung from the input rather than plucked, so both play exactly the same.
+        let input: Vec<f32> = (0..4410).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        let play = |level: u8| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.ceiling.set(1.0);
+            synth.params.exciter.set(1.0);
+            synth.process_midi_event([0xB0, 7, level]);
+            synth.process_midi_event([0xB0, 11, level]);
+            synth.process_midi_event([144, 60, 100]);
+            render_inputs(&mut synth, 2, &[input.clone(), input.clone()])
+        };
+        let (full, half) = (play(127), play(64));
+        let expected = (64.0 / 127.0) * (64.0 / 127.0);
+        // Once the gain has settled.
+        let tail = |output: &[Vec<f32>]| output[0][2205..].iter().map(|&s| s.abs()).sum::<f32>();
+        let ratio = tail(&half) / tail(&full);
+        let off = (ratio / expected - 1.0).abs();
+        assert!(off < 1e-3, "{} against {}", ratio, expected);
+    }
+
+    #[test]
+    fn test_invert_flips_one_side() {
+        // Rung from the input rather than plucked, so both play exactly the same.
+        let input: Vec<f32> = (0..1024).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        let play = |invert_left: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.exciter.set(1.0);
+            synth.params.invert_left.set(invert_left);
+            synth.process_midi_event([144, 60, 100]);
+            render_inputs(&mut synth, 2, &[input.clone(), input.clone()])
+        };
+        let (upright, flipped) = (play(0.0), play(1.0));
+        assert!(upright[0].iter().any(|&sample| sample != 0.0));
+        for (&upright, &flipped) in upright[0].iter().zip(flipped[0].iter()) {
+            assert_eq!(flipped, -upright);
+        }
+        assert_eq!(flipped[1], upright[1]);
+    }
+
+    #[test]
+    fn test_non_finite_values_are_kept_out() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        for &value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY].iter() {
+            synth.params.set_parameter(ParamId::Drive as i32, value);
+            assert_eq!(synth.params.drive.get(), 0.0);
+        }
+
+        // However the voices come to blow up, only silence comes out.
+        synth.process_midi_event([144, 60, 127]);
+        synth.process_midi_event([144, 64, 127]);
+        render(&mut synth, 64);
+        synth.notes[0].velocity = f64::INFINITY;
+        synth.notes[1].velocity = f64::NAN;
+        for channel in render(&mut synth, 512) {
+            assert!(channel.iter().all(|&sample| sample == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_auto_gain_evens_out_chords() {
+        // How loud the left channel is with `notes` struck together. Equal power is what the
+        // gain evens out; the peak of a chord is down to how its strings' bursts happen to line up.
+        let level = |notes: &[u8], auto_gain: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.ceiling.set(1.0);
+            synth.params.auto_gain.set(auto_gain);
+            for &note in notes {
+                synth.process_midi_event([144, note, 100]);
+            }
+            let output = &render(&mut synth, 4410)[0];
+            (output.iter().map(|s| s * s).sum::<f32>() / output.len() as f32).sqrt()
+        };
+        let chord = [48, 52, 55, 60, 64, 67, 72, 76, 79, 84];
+        let db = |ratio: f32| 20.0 * ratio.log10();
+        // A note alone is left as it is, give or take its string's pluck.
+        let single = level(&[60], 1.0);
+        assert!(db(level(&[60], 0.0) / single).abs() < 1.0);
+        // Summed straight, the chord is far louder than the one note.
+        assert!(db(level(&chord, 0.0) / single) > 6.0);
+        let evened = db(level(&chord, 1.0) / single);
+        assert!(evened.abs() < 3.0, "{} dB", evened);
+    }
+
+    #[test]
+    fn test_one_shot_voices_end_by_themselves() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.one_shot.set(1.0);
+        synth.process_midi_event([144, 60, 100]);
+        render(&mut synth, 1);
+        assert!(synth.notes[0].released);
+        // Held down, the voice still fades out and is gone once its release is over.
+        render(&mut synth, (RELEASE * 44100.0) as usize + 1);
+        assert!(synth.notes.is_empty());
+
+        // And note-offs change nothing.
+        synth.params.attack.set(0.5);
+        synth.process_midi_event([144, 60, 100]);
+        render(&mut synth, 100);
+        synth.process_midi_event([128, 60, 0]);
+        assert!(!synth.notes[0].released);
+        let attack = (synth.params.attack() * 44100.0) as usize;
+        render(&mut synth, attack + (RELEASE * 44100.0) as usize);
+        assert!(synth.notes.is_empty());
+    }
+
+    #[test]
+    fn test_keyboard_pan_spreads_low_and_high_apart() {
+        // How much louder the right side is than the left, for `note` alone.
+        let stereo = OutputMode::Stereo.to_parameter();
+        let lean = |keyboard_pan: f32, note: u8| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.ceiling.set(1.0);
+            synth.params.output_mode.set(stereo);
+            synth.params.keyboard_pan.set(keyboard_pan);
+            synth.process_midi_event([144, note, 100]);
+            let output = render(&mut synth, 4410);
+            let energy = |side: &[f32]| side.iter().map(|&s| s * s).sum::<f32>();
+            energy(&output[1]) / energy(&output[0])
+        };
+        let (low, high) = (lean(1.0, 36), lean(1.0, 96));
+        assert!(low < 0.5 && high > 2.0, "{} {}", low, high);
+        // Left at zero, both sit in the middle.
+        assert_eq!(lean(0.0, 36), 1.0);
+        assert_eq!(lean(0.0, 96), 1.0);
+    }
+
+    #[test]
+    fn test_mpe_bends_one_note_alone() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.mpe.set(1.0);
+        synth.process_midi_event([0x90, 60, 100]);
+        synth.process_midi_event([0x91, 64, 100]);
+        // A full bend up on the second note's channel.
+        synth.process_midi_event([0xE1, 127, 127]);
+        let frequencies: Vec<f64> = synth.notes.iter().map(|n| n.frequency()).collect();
+        assert_eq!(frequencies[0], midi_pitch_to_freq(60));
+        let bent = frequencies[1] / midi_pitch_to_freq(64);
+        let off = (bent / cents_to_ratio(4800.0) - 1.0).abs();
+        assert!(off < 1e-3, "{}", bent);
+        // A note struck after the bend starts out bent.
+        synth.process_midi_event([0x91, 67, 100]);
+        assert_eq!(synth.notes[2].bend, synth.notes[1].bend);
+
+        // Without MPE, pitch bends are ignored.
+        synth.params.mpe.set(0.0);
+        synth.process_midi_event([0xE0, 0, 0]);
+        assert_eq!(synth.notes[0].frequency(), midi_pitch_to_freq(60));
+
+        // The bent note's string rings at its new pitch, and the other's stays at its own.
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.mpe.set(1.0);
+        synth.process_midi_event([0x90, 60, 100]);
+        synth.process_midi_event([0x91, 69, 100]);
+        // A whole tone up, near enough.
+        synth.process_midi_event([0xE1, 85, 66]);
+        let bent = synth.notes[1].frequency();
+        let output = render(&mut synth, 44100).swap_remove(0);
+        assert!(magnitude(&output, bent) > magnitude(&output, 440.0) * 10.0);
+        let c = midi_pitch_to_freq(60);
+        assert!(magnitude(&output, c) > magnitude(&output, c * cents_to_ratio(200.0)) * 10.0);
+        // And it is still bent once the sample rate changes.
+        synth.set_sample_rate(48000.0);
+        let after = synth.notes[1].string.frequency(48000.0);
+        assert!((after - bent).abs() < 1.0, "{} {}", bent, after);
+    }
+
+    #[test]
+    fn test_voices_fade_all_the_way_out() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.process_midi_event([144, 60, 100]);
+        render(&mut synth, 100);
+        synth.process_midi_event([128, 60, 64]);
+        let (attack, release) = (synth.params.attack_curve(), synth.params.release_curve());
+        let mut levels = Vec::new();
+        while let Some(note) = synth.notes.first() {
+            levels.push(note.envelope(attack, release));
+            render(&mut synth, 1);
+        }
+        // The level comes down in even steps to nothing, with no jump where the voice is taken
+        // away.
+        let steps = levels.windows(2).map(|pair| (pair[0] - pair[1]).abs());
+        let largest = steps.fold(0.0, f64::max);
+        assert!(largest < 2.0 / (RELEASE * 44100.0), "{}", largest);
+        assert!(levels.iter().all(|&level| level >= 0.0));
+        assert!(levels[levels.len() - 1] <= largest);
+    }
+
+    #[test]
+    fn test_repeated_strikes_each_sound_different() {
+        // The onsets of five strikes of the same key on the string, with humanize and the
+        // white noise on so that every generator is drawn from.
+        let onsets = |synth: &mut SineSynth| {
+            synth.params.humanize.set(1.0);
+            synth.params.amounts[NoiseSource::White as usize].set(0.5);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(0.5);
+            (0..5)
+                .map(|_| {
+                    synth.process_midi_event([144, 60, 100]);
+                    let onset = render(synth, 1024).remove(0);
+                    synth.process_midi_event([0xB0, 120, 0]);
+                    onset
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut synth = SineSynth::new(HostCallback::default());
+        let first = onsets(&mut synth);
+        for (i, onset) in first.iter().enumerate() {
+            assert!(onset.iter().any(|&s| s != 0.0), "{}", i);
+            assert!(first[..i].iter().all(|earlier| earlier != onset), "{}", i);
+        }
+        // A fresh synth plays them again just the same, and so does this one once reset.
+        assert_eq!(onsets(&mut SineSynth::new(HostCallback::default())), first);
+        synth.reset();
+        assert_eq!(onsets(&mut synth), first);
+
+        // Another seed plays them differently, but just as reliably.
+        let reseeded = |seed: u32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.reseed(seed);
+            onsets(&mut synth)
+        };
+        assert_ne!(reseeded(SEED + 1), first);
+        assert_eq!(reseeded(SEED + 1), reseeded(SEED + 1));
+        assert_eq!(reseeded(SEED), first);
+
+        // Reseeding partway through a run starts the strikes over as well as the draws.
+        let mut synth = SineSynth::new(HostCallback::default());
+        onsets(&mut synth);
+        synth.reseed(SEED);
+        assert_eq!(onsets(&mut synth), first);
+    }
+
+    #[test]
+    fn test_automation_ramps_across_the_block() {
+        // Two blocks of the ring modulated noise, mixed in by `first` then by `second`.
+        let mixed = |first: f32, second: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.exciter.set(1.0);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(1.0);
+            synth.params.ring_mod.set(1.0);
+            synth.process_midi_event([144, 60, 127]);
+            let global_mix = ParamId::GlobalMix as i32;
+            synth.params.set_parameter(global_mix, first);
+            render(&mut synth, 256);
+            synth.params.set_parameter(global_mix, second);
+            render(&mut synth, 256).swap_remove(0)
+        };
+        let (low, high, ramped) = (mixed(0.2, 0.2), mixed(0.8, 0.8), mixed(0.2, 0.8));
+        let apart = |(low, high): (&f32, &f32)| (low - high).abs() > 1e-3;
+        assert!(low.iter().zip(high.iter()).any(apart));
+        // Each sample a step further from the first point to the second, landing on it.
+        for (i, &ramped) in ramped.iter().enumerate() {
+            let along = (i + 1) as f32 / 256.0;
+            let expected = low[i] + (high[i] - low[i]) * along;
+            let off = (ramped - expected).abs();
+            assert!(off < 1e-5, "{}: {} {}", i, ramped, expected);
+        }
+    }
+
+    #[test]
+    fn test_block_size_leaves_the_audio_alone() {
+        // With the string silenced only the noise is heard, the same on every run.
+        let play = |block: usize| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.exciter.set(1.0);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(0.5);
+            synth.params.vibrato_depth.set(0.5);
+            synth.params.lfo_depth.set(0.5);
+            synth.process_midi_event([0xB0, 1, 100]);
+            synth.process_midi_event([144, 60, 100]);
+            let mut output = Vec::new();
+            while output.len() < 2048 {
+                output.extend(render(&mut synth, block).swap_remove(0));
+            }
+            output.truncate(2048);
+            output
+        };
+        let whole = play(2048);
+        assert!(whole.iter().any(|&sample| sample != 0.0));
+        for &block in [1, 37, 256].iter() {
+            assert_eq!(play(block), whole, "{}", block);
+        }
+    }
+
+    #[test]
+    fn test_parameters_are_read_once_a_block() {
+        // The loads a block makes from the parameters, with a chord held through the effects
+        // and everything the controllers can move.
+        let block_loads = |block: usize| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.amounts[NoiseSource::Perlin as usize].set(0.5);
+            synth.params.amounts[NoiseSource::Pink as usize].set(0.5);
+            synth.params.vibrato_depth.set(0.5);
+            synth.params.lfo_depth.set(0.5);
+            synth.params.delay_mix.set(0.5);
+            synth.params.chorus_mix.set(0.5);
+            synth.params.tremolo_depth.set(0.5);
+            synth.params.drift.set(0.5);
+            synth.params.exciter.set(1.0);
+            synth.process_midi_event([0xB0, 1, 100]);
+            for &note in [60, 64, 67].iter() {
+                synth.process_midi_event([144, note, 100]);
+            }
+            render(&mut synth, block);
+            let before = loads();
+            render(&mut synth, block);
+            loads() - before
+        };
+        // However long the block, so none are made sample by sample.
+        let short = block_loads(64);
+        assert!(short > 0);
+        assert_eq!(block_loads(512), short);
+    }
+
+    #[test]
+    fn test_finished_voices_are_taken_away_by_the_end_of_the_block() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 64, 100]);
+        render(&mut synth, 100);
+        synth.process_midi_event([128, 60, 64]);
+        // The first voice finishes early in the next block, and is gone by its end.
+        let release = (RELEASE * 44100.0) as usize;
+        render(&mut synth, release - 10);
+        assert_eq!(synth.notes.len(), 2);
+        render(&mut synth, 512);
+        assert_eq!(synth.notes.len(), 1);
+        assert_eq!(synth.notes[0].note, 64);
+        assert_eq!(synth.strings.len(), MAX_VOICES);
+    }
+
+    #[test]
+    fn test_string_and_noise_levels_are_apart() {
+        // Rung from the input rather than plucked, so every run plays exactly the same.
+        let input: Vec<f32> = (0..2048).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        let silence = vec![0.0; 2048];
+        let play = |input: &[f32], noise: f32, string_level: f32, noise_level: f32| {
+            let mut synth = SineSynth::new(HostCallback::default());
+            synth.params.ceiling.set(1.0);
+            synth.params.exciter.set(1.0);
+            synth.params.amounts[NoiseSource::Perlin as usize].set(noise);
+            synth.params.string_level.set(string_level);
+            synth.params.noise_level.set(noise_level);
+            synth.process_midi_event([144, 60, 100]);
+            render_inputs(&mut synth, 2, &[input.to_vec(), input.to_vec()]).swap_remove(0)
+        };
+        let scaled = |output: &[f32], by: f32, full: &[f32]| {
+            assert!(full.iter().any(|&sample| sample != 0.0));
+            let mut pairs = output.iter().zip(full.iter());
+            pairs.all(|(&output, &full)| (output - full * by).abs() < 1e-6)
+        };
+        // The string alone follows its own level and not the noise's.
+        let string = play(&input, 0.0, 1.0, 1.0);
+        assert!(scaled(&play(&input, 0.0, 0.5, 1.0), 0.5, &string));
+        assert!(scaled(&play(&input, 0.0, 1.0, 0.25), 1.0, &string));
+        // And the noise alone the other way round.
+        let noise = play(&silence, 1.0, 1.0, 1.0);
+        assert!(scaled(&play(&silence, 1.0, 1.0, 0.5), 0.5, &noise));
+        assert!(scaled(&play(&silence, 1.0, 0.25, 1.0), 1.0, &noise));
+    }
+
+    #[test]
+    fn test_drones_hold_until_cut_off() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.drone.set(1.0);
+        synth.process_midi_event([144, 60, 100]);
+        render(&mut synth, 100);
+        synth.process_midi_event([128, 60, 0]);
+        render(&mut synth, 2 * (RELEASE * 44100.0) as usize);
+        assert!(!synth.notes[0].released);
+        let held = render(&mut synth, 441);
+        assert!(held[0].iter().any(|&sample| sample != 0.0));
+
+        // All-sound-off cuts it off, as does panic.
+        synth.process_midi_event([0xB0, 120, 0]);
+        assert!(synth.notes.is_empty());
+        synth.process_midi_event([144, 60, 100]);
+        synth.params.panic.set(1.0);
+        render(&mut synth, 1);
+        assert!(synth.notes.is_empty());
+    }
+
+    #[test]
+    fn test_noise_smoothing_takes_the_steps_out_of_low_notes() {
+        // How much of the output's energy is in its sample-to-sample changes.
+        let roughness = |output: &[f32]| {
+            let changes: f32 = output.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+            let energy: f32 = output.iter().map(|s| s * s).sum();
+            changes / energy
+        };
+        for &source in [NoiseSource::Value, NoiseSource::Worley].iter() {
+            let mut rough = SineSynth::new(HostCallback::default());
+            let stepped = roughness(&noise_only(&mut rough, 24, source));
+            let mut smooth = SineSynth::new(HostCallback::default());
+            smooth.params.noise_smoothing.set(1.0);
+            let smoothed = roughness(&noise_only(&mut smooth, 24, source));
+            let ratio = smoothed / stepped;
+            assert!(ratio < 0.5, "{:?} {} {}", source, smoothed, stepped);
+        }
+    }
+
+    #[test]
+    fn test_disabled_source_is_left_out() {
+        let perlin = NoiseSource::Perlin as usize;
+        let mut off = SineSynth::new(HostCallback::default());
+        off.params.enabled[perlin].set(0.0);
+        let text = off.params.get_parameter_text(ParamId::PerlinOn as i32);
+        assert_eq!(text, "Off");
+        let output = noise_only(&mut off, 69, NoiseSource::Perlin);
+        let mut silent = SineSynth::new(HostCallback::default());
+        assert_eq!(output, settled_noise(&mut silent, 69));
+
+        // Never read, but its amount is kept for when it is turned back on.
+        assert!(off.noise_blend().active().is_empty());
+        assert_eq!(off.params.amounts[perlin].get(), 1.0);
+        off.params.enabled[perlin].set(1.0);
+        assert_eq!(off.noise_blend().active(), [NoiseSource::Perlin]);
+    }
+
+    #[test]
+    fn test_morph_between_presets() {
+        let params = SynthParameters::default();
+        let (init, drone) = (0, 2);
+        let (perlin, damping) = (ParamId::PerlinAmount as usize, ParamId::Damping as usize);
+        let lfo_target = ParamId::LfoTarget as usize;
+        let values = |params: &SynthParameters| -> Vec<f32> {
+            let values = (0..PARAMETER_COUNT).map(|i| params.get_parameter(i));
+            values.collect()
+        };
+        let preset = |preset: usize| {
+            params.change_preset(preset as i32);
+            values(&params)
+        };
+        let (a, b) = (preset(init), preset(drone));
+        let morphed = |t: f32| {
+            params.morph(init, drone, t);
+            values(&params)
+        };
+        assert_eq!(morphed(0.0), a);
+        assert_eq!(morphed(1.0), b);
+
+        let halfway = morphed(0.5);
+        assert_eq!(halfway[perlin], 0.15);
+        assert_eq!(halfway[damping], (a[damping] + b[damping]) / 2.0);
+        // The LFO's target is a switch, and goes over to the second preset's halfway.
+        assert_ne!(a[lfo_target], b[lfo_target]);
+        assert_eq!(halfway[lfo_target], b[lfo_target]);
+        assert_eq!(morphed(0.49)[lfo_target], a[lfo_target]);
+
+        // A sweep leaves bypass and a MIDI learn waiting for its CC alone.
+        params.bypass.set(1.0);
+        params.learn(35);
+        for step in 0..=10 {
+            params.morph(init, drone, step as f32 / 10.0);
+        }
+        assert_eq!(params.bypass.get(), 1.0);
+        assert_eq!(params.midi_learn(), Some(35));
+    }
+
+    #[test]
+    fn test_exciter_rings_the_input_through_the_strings() {
+        let mut synth = SineSynth::new(HostCallback::default());
+        synth.params.ceiling.set(1.0);
+        synth.params.exciter.set(1.0);
+        synth.process_midi_event([144, 69, 127]);
+        // Plucked silent, the string makes nothing of its own.
+        assert!(render(&mut synth, 4410)[0].iter().all(|&s| s == 0.0));
+
+        // Noise coming in rings out at the string's pitch, and hardly anywhere between its
+        // harmonics.
+        let mut noise = 1u32;
+        let mut white = || {
+            noise ^= noise << 13;
+            noise ^= noise >> 17;
+            noise ^= noise << 5;
+            (f64::from(noise) / f64::from(u32::MAX) * 2.0 - 1.0) as f32 * 0.1
+        };
+        let input: Vec<f32> = (0..44100).map(|_| white()).collect();
+        let output = &render_inputs(&mut synth, 2, &[input.clone(), input.clone()])[0];
+        let (pitch, between) = (magnitude(output, 440.0), magnitude(output, 660.0));
+        assert!(pitch > between * 10.0, "{} {}", pitch, between);
+        let fed = magnitude(&input, 660.0);
+        assert!(between < fed * 0.1, "{} {}", between, fed);
     }
 }
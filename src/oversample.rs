@@ -0,0 +1,220 @@
+//! Polyphase resampling, for running the noise and nonlinear stages above the host's rate.
+
+use std::f64::consts::PI;
+
+/// The most the signal can be oversampled by.
+pub const MAX_OVERSAMPLING: usize = 4;
+
+/// How many taps each polyphase branch of the filter has.
+const TAPS_PER_PHASE: usize = 16;
+
+/// How far above the host's rate the noise and crusher run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Oversampling {
+    Off,
+    Double,
+    Quadruple,
+}
+
+impl Oversampling {
+    pub const ALL: [Oversampling; 3] = [
+        Oversampling::Off,
+        Oversampling::Double,
+        Oversampling::Quadruple,
+    ];
+
+    /// How many samples are run for every one the host asks for.
+    pub fn factor(self) -> usize {
+        match self {
+            Oversampling::Off => 1,
+            Oversampling::Double => 2,
+            Oversampling::Quadruple => MAX_OVERSAMPLING,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Oversampling::Off => "Off",
+            Oversampling::Double => "2x",
+            Oversampling::Quadruple => "4x",
+        }
+    }
+}
+
+/// Converts a signal up to `factor` times its rate and back down again.
+///
+/// Both directions share one windowed-sinc low-pass, which keeps the images left by upsampling
+/// and anything above the host's nyquist out of the result. Only the taps that meet a non-zero
+/// sample are ever summed, so the cost grows with `TAPS_PER_PHASE` rather than the full length
+/// of the filter.
+pub struct Oversampler {
+    factor: usize,
+    /// The low-pass, `factor * TAPS_PER_PHASE` taps long, or empty when `factor` is `1`.
+    filter: Vec<f32>,
+    /// The last `TAPS_PER_PHASE` samples passed to `upsample`.
+    up_history: Vec<f32>,
+    up_position: usize,
+    /// The last `filter.len()` oversampled samples passed to `downsample`.
+    down_history: Vec<f32>,
+    down_position: usize,
+}
+
+impl Oversampler {
+    /// Design the filters for oversampling by `factor`.
+    ///
+    /// The taps and the histories are allocated here, and left the same size from then on.
+    pub fn new(factor: usize) -> Oversampler {
+        // There is nothing to filter without oversampling, so no taps are needed.
+        let taps = if factor > 1 { TAPS_PER_PHASE } else { 0 };
+        let len = factor * taps;
+        // The cutoff sits a little under the host's nyquist so the transition band is mostly
+        // over by the time it gets there.
+        let cutoff = 0.45 / factor as f64;
+        let centre = (len as f64 - 1.0) / 2.0;
+        let mut filter: Vec<f64> = (0..len)
+            .map(|i| {
+                let t = i as f64 - centre;
+                let sinc = if t == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (2.0 * PI * cutoff * t).sin() / (PI * t)
+                };
+                let x = 2.0 * PI * i as f64 / (len as f64 - 1.0);
+                let blackman = 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos();
+                sinc * blackman
+            })
+            .collect();
+        let gain: f64 = filter.iter().sum();
+        for tap in filter.iter_mut() {
+            *tap /= gain;
+        }
+
+        Oversampler {
+            factor,
+            filter: filter.into_iter().map(|tap| tap as f32).collect(),
+            up_history: vec![0.0; taps],
+            up_position: 0,
+            down_history: vec![0.0; len],
+            down_position: 0,
+        }
+    }
+
+    /// Clear the filters' history.
+    pub fn reset(&mut self) {
+        for sample in self.up_history.iter_mut() {
+            *sample = 0.0;
+        }
+        for sample in self.down_history.iter_mut() {
+            *sample = 0.0;
+        }
+    }
+
+    /// Fill `output`, which must be `factor` samples long, with `input` at the higher rate.
+    pub fn upsample(&mut self, input: f32, output: &mut [f32]) {
+        if self.factor == 1 {
+            output[0] = input;
+            return;
+        }
+
+        self.up_history[self.up_position] = input;
+        for (phase, sample) in output.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for i in 0..TAPS_PER_PHASE {
+                let past = (self.up_position + TAPS_PER_PHASE - i) % TAPS_PER_PHASE;
+                sum += self.filter[i * self.factor + phase] * self.up_history[past];
+            }
+            // Each branch only sees one in every `factor` taps, and so that share of the gain.
+            *sample = sum * self.factor as f32;
+        }
+        self.up_position = (self.up_position + 1) % TAPS_PER_PHASE;
+    }
+
+    /// Take `input`, `factor` samples at the higher rate, back down to a single sample.
+    pub fn downsample(&mut self, input: &[f32]) -> f32 {
+        if self.factor == 1 {
+            return input[0];
+        }
+
+        let len = self.filter.len();
+        for &sample in input {
+            self.down_history[self.down_position] = sample;
+            self.down_position = (self.down_position + 1) % len;
+        }
+        let newest = self.down_position + len - 1;
+        self.filter
+            .iter()
+            .enumerate()
+            .map(|(i, tap)| tap * self.down_history[(newest - i) % len])
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crusher::BitCrusher;
+    use oversample::{Oversampler, Oversampling};
+    use std::f64::consts::PI;
+
+    /// Drive a sine at `frequency` through a one bit crusher at `oversampling`, and return the
+    /// fraction of the result's energy that is not the fundamental.
+    fn distortion_share(oversampling: Oversampling, frequency: f64) -> f64 {
+        let factor = oversampling.factor();
+        let mut oversampler = Oversampler::new(factor);
+        let mut crusher = BitCrusher::new();
+        let mut sub = vec![0.0; factor];
+        let output: Vec<f64> = (0..8192)
+            .map(|i| {
+                let input = 0.9 * (2.0 * PI * frequency * i as f64).sin();
+                oversampler.upsample(input as f32, &mut sub);
+                for sample in sub.iter_mut() {
+                    *sample = crusher.process(*sample, 1, 1);
+                }
+                f64::from(oversampler.downsample(&sub))
+            })
+            // Let the filters settle.
+            .skip(512)
+            .collect();
+
+        let (mut sin, mut cos, mut total) = (0.0, 0.0, 0.0);
+        for (i, &sample) in output.iter().enumerate() {
+            let angle = 2.0 * PI * frequency * i as f64;
+            sin += sample * angle.sin();
+            cos += sample * angle.cos();
+            total += sample * sample;
+        }
+        let fundamental = 2.0 * (sin * sin + cos * cos) / output.len() as f64;
+        (total - fundamental) / total
+    }
+
+    #[test]
+    fn test_passes_through_when_off() {
+        let mut oversampler = Oversampler::new(1);
+        let mut sub = [0.0];
+        oversampler.upsample(0.5, &mut sub);
+        assert_eq!(sub, [0.5]);
+        assert_eq!(oversampler.downsample(&sub), 0.5);
+    }
+
+    #[test]
+    fn test_round_trip_keeps_level() {
+        let mut oversampler = Oversampler::new(4);
+        let mut sub = [0.0; 4];
+        let mut last = 0.0;
+        for _ in 0..100 {
+            oversampler.upsample(1.0, &mut sub);
+            last = oversampler.downsample(&sub);
+        }
+        assert!((last - 1.0).abs() < 1e-3, "{}", last);
+    }
+
+    #[test]
+    fn test_oversampling_reduces_aliasing() {
+        // Above a third of nyquist, so even the third harmonic folds back without oversampling.
+        let frequency = 9000.0 / 44100.0;
+        let off = distortion_share(Oversampling::Off, frequency);
+        let double = distortion_share(Oversampling::Double, frequency);
+        let quadruple = distortion_share(Oversampling::Quadruple, frequency);
+        assert!(double < off * 0.5, "off {} 2x {}", off, double);
+        assert!(quadruple < double * 0.5, "2x {} 4x {}", double, quadruple);
+    }
+}
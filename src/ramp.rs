@@ -0,0 +1,81 @@
+//! Linear ramps, for carrying a parameter across a block to where the host has set it rather
+//! than stepping there at the block's start.
+
+/// A value moving in equal steps from where it was to its target.
+pub struct Ramp {
+    value: f32,
+    target: f32,
+    step: f32,
+    /// Samples left until the target is reached.
+    remaining: usize,
+    /// Whether there is anywhere to ramp from. Until the first target is set, or after a reset,
+    /// the ramp jumps straight to it.
+    primed: bool,
+}
+
+impl Ramp {
+    pub fn new() -> Ramp {
+        Ramp {
+            value: 0.0,
+            target: 0.0,
+            step: 0.0,
+            remaining: 0,
+            primed: false,
+        }
+    }
+
+    /// Jump to the next target set, rather than ramping to it.
+    pub fn reset(&mut self) {
+        self.primed = false;
+    }
+
+    /// Head for `target` over the next `samples` samples, from wherever the ramp has got to.
+    pub fn set(&mut self, target: f32, samples: usize) {
+        if !self.primed || samples == 0 {
+            self.primed = true;
+            self.value = target;
+            self.remaining = 0;
+        } else {
+            self.remaining = samples;
+            self.step = (target - self.value) / samples as f32;
+        }
+        self.target = target;
+    }
+
+    /// The value for the next sample. The last sample of the ramp lands on the target exactly.
+    pub fn next(&mut self) -> f32 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.value = if self.remaining == 0 {
+                self.target
+            } else {
+                self.value + self.step
+            };
+        }
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ramp::Ramp;
+
+    #[test]
+    fn test_ramps_between_points() {
+        let mut ramp = Ramp::new();
+        // The first value set is where it starts, with nothing to ramp from.
+        ramp.set(0.2, 4);
+        assert_eq!(ramp.next(), 0.2);
+
+        ramp.set(1.0, 4);
+        let steps: Vec<f32> = (0..6).map(|_| ramp.next()).collect();
+        let expected = [0.4, 0.6, 0.8, 1.0, 1.0, 1.0];
+        for (step, expected) in steps.iter().zip(expected.iter()) {
+            assert!((step - expected).abs() < 1e-6, "{:?}", steps);
+        }
+
+        ramp.reset();
+        ramp.set(0.0, 4);
+        assert_eq!(ramp.next(), 0.0);
+    }
+}
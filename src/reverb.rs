@@ -0,0 +1,185 @@
+//! A Freeverb-style reverb, for putting the output in a room.
+
+/// The lengths of the parallel comb filters at 44100 samples a second, from Freeverb.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+
+/// The lengths of the allpass filters the combs are fed through, at 44100 samples a second.
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+
+/// How much longer the right side's filters are, at 44100 samples a second, so the two sides
+/// differ.
+const STEREO_SPREAD: usize = 23;
+
+/// How far the input is turned down going in, so the eight combs summed stay in range.
+const INPUT_GAIN: f32 = 0.015;
+
+/// The comb feedback at the smallest size, and how much more the largest adds.
+const MIN_FEEDBACK: f64 = 0.7;
+const FEEDBACK_RANGE: f64 = 0.28;
+
+/// The most the combs' feedback can be low-passed by, at full damping.
+const MAX_DAMP: f32 = 0.4;
+
+/// How long the reverb takes to fall by 60dB at `size`, `0.0..=1.0`, in seconds.
+///
+/// This is for the longest comb with no damping, so it is the longest the reverb can ring.
+pub fn decay_time(size: f32) -> f64 {
+    let longest = (COMB_TUNINGS[COMB_TUNINGS.len() - 1] + STEREO_SPREAD) as f64 / 44100.0;
+    longest * 0.001f64.ln() / feedback(size).ln()
+}
+
+fn feedback(size: f32) -> f64 {
+    MIN_FEEDBACK + FEEDBACK_RANGE * f64::from(size)
+}
+
+/// A lossy feedback comb, with a one-pole low-pass in the loop to damp the highs.
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    /// The low-pass's last output.
+    store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damp: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.store = ::flush_denormal(output + (self.store - output) * damp);
+        self.buffer[self.position] = input + self.store * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// A Schroeder allpass, which smears the comb's echoes without colouring them.
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = ::flush_denormal(input + delayed * 0.5);
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+pub struct Reverb {
+    /// The combs and allpasses for each side.
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+}
+
+impl Reverb {
+    /// Create a reverb with its filters tuned for `sample_rate`.
+    pub fn new(sample_rate: f64) -> Reverb {
+        let mut reverb = Reverb {
+            combs: [Vec::new(), Vec::new()],
+            allpasses: [Vec::new(), Vec::new()],
+        };
+        reverb.resize(sample_rate);
+        reverb
+    }
+
+    /// Retune the filters for `sample_rate`, clearing them.
+    ///
+    /// Every comb and allpass gets a new buffer, scaled to the rate.
+    pub fn resize(&mut self, sample_rate: f64) {
+        let scale = |tuning: usize| ((tuning as f64 * sample_rate / 44100.0) as usize).max(1);
+        for side in 0..2 {
+            let spread = side * STEREO_SPREAD;
+            self.combs[side] = COMB_TUNINGS
+                .iter()
+                .map(|&tuning| Comb {
+                    buffer: vec![0.0; scale(tuning + spread)],
+                    position: 0,
+                    store: 0.0,
+                })
+                .collect();
+            self.allpasses[side] = ALLPASS_TUNINGS
+                .iter()
+                .map(|&tuning| Allpass {
+                    buffer: vec![0.0; scale(tuning + spread)],
+                    position: 0,
+                })
+                .collect();
+        }
+    }
+
+    /// Silence the filters without reallocating.
+    pub fn clear(&mut self) {
+        for comb in self.combs.iter_mut().flat_map(|side| side.iter_mut()) {
+            comb.buffer.iter_mut().for_each(|sample| *sample = 0.0);
+            comb.store = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut().flat_map(|side| side.iter_mut()) {
+            allpass.buffer.iter_mut().for_each(|sample| *sample = 0.0);
+        }
+    }
+
+    /// Feed a stereo `frame` into the reverb and return only what comes back out of it.
+    ///
+    /// `size`, `0.0..=1.0`, sets how long it rings, and `damp`, `0.0..=1.0`, how quickly the
+    /// highs die away ahead of the lows.
+    pub fn process(&mut self, frame: [f32; 2], size: f32, damp: f32) -> [f32; 2] {
+        let feedback = feedback(size) as f32;
+        let damp = damp * MAX_DAMP;
+        // Both sides are fed the same, and only come apart in their tunings.
+        let input = (frame[0] + frame[1]) * INPUT_GAIN;
+        let mut output = [0.0; 2];
+        for (side, sample) in output.iter_mut().enumerate() {
+            for comb in self.combs[side].iter_mut() {
+                *sample += comb.process(input, feedback, damp);
+            }
+            for allpass in self.allpasses[side].iter_mut() {
+                *sample = allpass.process(*sample);
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reverb::{decay_time, Reverb};
+
+    /// The reverb's response to an impulse at 1000 samples a second, over `seconds`.
+    fn impulse(size: f32, damp: f32, seconds: usize) -> Vec<f32> {
+        let mut reverb = Reverb::new(1000.0);
+        (0..seconds * 1000)
+            .map(|i| {
+                let input = if i == 0 { 1.0 } else { 0.0 };
+                reverb.process([input, input], size, damp)[0]
+            })
+            .collect()
+    }
+
+    /// The loudest the response gets within each tenth of a second.
+    fn envelope(response: &[f32]) -> Vec<f32> {
+        response
+            .chunks(100)
+            .map(|chunk| chunk.iter().fold(0.0f32, |peak, &s| peak.max(s.abs())))
+            .collect()
+    }
+
+    #[test]
+    fn test_impulse_rings_and_dies_away() {
+        let response = impulse(0.5, 0.5, 4);
+        let envelope = envelope(&response);
+        // Rings on long after the impulse, and is dense rather than a few clear echoes.
+        assert!(envelope[5] > 1e-3, "{:?}", envelope);
+        let busy = response[..1000].iter().filter(|&&s| s.abs() > 1e-4).count();
+        assert!(busy > 500, "{}", busy);
+        // But dies away.
+        assert!(envelope[39] < envelope[5] * 0.05, "{:?}", envelope);
+    }
+
+    #[test]
+    fn test_size_lengthens_decay() {
+        let small = envelope(&impulse(0.1, 0.0, 2));
+        let large = envelope(&impulse(0.9, 0.0, 2));
+        assert!(large[15] > small[15] * 10.0);
+        assert!(decay_time(0.9) > decay_time(0.1));
+    }
+}
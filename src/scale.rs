@@ -0,0 +1,81 @@
+//! Musical scales, for snapping the keys played onto them.
+
+/// A scale rooted on C, or `Off` to leave every key where it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+    Off,
+    Major,
+    Minor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+    WholeTone,
+}
+
+impl Scale {
+    pub const ALL: [Scale; 7] = [
+        Scale::Off,
+        Scale::Major,
+        Scale::Minor,
+        Scale::MajorPentatonic,
+        Scale::MinorPentatonic,
+        Scale::Blues,
+        Scale::WholeTone,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scale::Off => "Off",
+            Scale::Major => "Major",
+            Scale::Minor => "Minor",
+            Scale::MajorPentatonic => "Major pentatonic",
+            Scale::MinorPentatonic => "Minor pentatonic",
+            Scale::Blues => "Blues",
+            Scale::WholeTone => "Whole tone",
+        }
+    }
+
+    /// The scale's degrees, in semitones above C.
+    fn degrees(self) -> &'static [u8] {
+        match self {
+            Scale::Off => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+            Scale::WholeTone => &[0, 2, 4, 6, 8, 10],
+        }
+    }
+
+    /// The midi pitch in the scale closest to `note`, taking the lower of two that are as
+    /// close.
+    pub fn snap(self, note: u8) -> u8 {
+        let octave = note / 12 * 12;
+        // The next octave's root is a candidate too, for keys just under it.
+        let candidates = self.degrees().iter().map(|&degree| octave + degree);
+        candidates
+            .chain(Some(octave + 12))
+            // The top octave is cut short.
+            .filter(|&pitch| pitch <= 127)
+            .min_by_key(|&pitch| (i16::from(pitch) - i16::from(note)).abs())
+            .unwrap_or(note)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scale::Scale;
+
+    #[test]
+    fn test_snaps_to_nearest_degree() {
+        for note in 0..128 {
+            assert_eq!(Scale::Off.snap(note), note);
+        }
+        let major: Vec<u8> = (60..=72).map(|note| Scale::Major.snap(note)).collect();
+        assert_eq!(major, [60, 60, 62, 62, 64, 65, 65, 67, 67, 69, 69, 71, 72]);
+        // B snaps up to the next C in pentatonic, and the top of the range stays in range.
+        assert_eq!(Scale::MajorPentatonic.snap(71), 72);
+        assert!(Scale::ALL.iter().all(|scale| scale.snap(127) <= 127));
+    }
+}
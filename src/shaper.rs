@@ -0,0 +1,127 @@
+//! Waveshaping distortion.
+
+/// The most the waveshaper can boost its input by before shaping it, 40dB.
+pub const MAX_DRIVE: f32 = 100.0;
+
+/// The transfer curve a `shape`d sample is bent through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    /// Smooth, tape-like saturation.
+    Tanh,
+    /// Flat tops as soon as the signal passes full scale.
+    HardClip,
+    /// Softer than `Tanh`, with more of the shoulder left in.
+    Arctan,
+    /// Peaks over full scale are reflected back down, for a harsh, buzzy edge.
+    Foldback,
+}
+
+impl Curve {
+    pub const ALL: [Curve; 4] = [Curve::Tanh, Curve::HardClip, Curve::Arctan, Curve::Foldback];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::Tanh => "Tanh",
+            Curve::HardClip => "Hard clip",
+            Curve::Arctan => "Arctan",
+            Curve::Foldback => "Fold-back",
+        }
+    }
+
+    fn apply(self, x: f32) -> f32 {
+        match self {
+            Curve::Tanh => x.tanh(),
+            Curve::HardClip => x.clamp(-1.0, 1.0),
+            Curve::Arctan => x.atan(),
+            Curve::Foldback => {
+                // A triangle wave through the origin with a period of four, which leaves
+                // `-1.0..=1.0` alone and mirrors everything outside it back in.
+                let x = (x + 1.0).rem_euclid(4.0);
+                if x < 2.0 {
+                    x - 1.0
+                } else {
+                    3.0 - x
+                }
+            }
+        }
+    }
+}
+
+/// Boost `sample` by `drive`, from `1.0` up to `MAX_DRIVE`, and bend it through `curve`.
+///
+/// The result is scaled so a full scale input still comes out at full scale, which keeps the
+/// level roughly steady as the drive goes up. At a drive of `1.0` the sample is passed through
+/// untouched.
+pub fn shape(sample: f32, drive: f32, curve: Curve) -> f32 {
+    if drive <= 1.0 {
+        return sample;
+    }
+    let shaped = curve.apply(sample * drive);
+    match curve {
+        // Fold-back is already bounded, and its value at full scale says nothing about its
+        // level since it can land anywhere on the fold.
+        Curve::Foldback => shaped,
+        _ => shaped / curve.apply(drive),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shaper::{shape, Curve, MAX_DRIVE};
+
+    /// Inputs from well below to well above full scale.
+    fn inputs() -> Vec<f32> {
+        (-400..=400).map(|i| i as f32 / 100.0).collect()
+    }
+
+    #[test]
+    fn test_passes_through_without_drive() {
+        for &curve in Curve::ALL.iter() {
+            for &x in inputs().iter() {
+                assert_eq!(shape(x, 1.0, curve), x);
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_stays_bounded() {
+        for &curve in Curve::ALL.iter() {
+            for &drive in [1.5, 4.0, 20.0, MAX_DRIVE].iter() {
+                for &x in inputs().iter() {
+                    // Even hot input is held in, and anything within full scale stays there.
+                    let limit = if x.abs() <= 1.0 { 1.0 + 1e-6 } else { 2.0 };
+                    let y = shape(x, drive, curve);
+                    assert!(y.abs() <= limit, "{} at {}: {}", curve.name(), drive, x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_saturating_curves_are_monotonic() {
+        for &curve in [Curve::Tanh, Curve::HardClip, Curve::Arctan].iter() {
+            for &drive in [1.5, 4.0, MAX_DRIVE].iter() {
+                let outputs: Vec<f32> = inputs().iter().map(|&x| shape(x, drive, curve)).collect();
+                for pair in outputs.windows(2) {
+                    assert!(pair[1] >= pair[0], "{} at {}", curve.name(), drive);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_scale_keeps_its_level() {
+        for &curve in [Curve::Tanh, Curve::HardClip, Curve::Arctan].iter() {
+            for &drive in [1.5, 4.0, MAX_DRIVE].iter() {
+                let y = shape(1.0, drive, curve);
+                assert!((y - 1.0).abs() < 1e-6, "{}: {}", curve.name(), y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_foldback_reflects_peaks() {
+        assert!((shape(0.75, 2.0, Curve::Foldback) - 0.5).abs() < 1e-6);
+        assert!((shape(-0.75, 2.0, Curve::Foldback) + 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,374 @@
+//! The noise generators that can be blended into each voice.
+
+use noise::{
+    Billow, Cylinders, Fbm, NoiseFn, OpenSimplex, Perlin, RidgedMulti, SuperSimplex, Value, Worley,
+};
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+/// How many noise sources there are, and so how many amount parameters.
+pub const NOISE_SOURCES: usize = 12;
+
+/// How loud every source is brought to, as an RMS level. This is white noise's own, even across
+/// `-1.0..1.0`, so it is the one source played as it comes and the rest are scaled to meet it.
+pub const REFERENCE_RMS: f64 = 0.577_350_269_189_625_8;
+
+/// How many points along each side of the grid the coherent sources are measured over, and
+/// how far apart. The spacing is off the generators' lattice, where gradient noise is always
+/// zero.
+const CALIBRATION_POINTS: usize = 64;
+const CALIBRATION_SPACING: f64 = 0.37;
+
+/// One of the noise generators a voice can mix in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseSource {
+    White,
+    Perlin,
+    Value,
+    Worley,
+    OpenSimplex,
+    SuperSimplex,
+    Billow,
+    Fbm,
+    RidgedMulti,
+    Cylinders,
+    Pink,
+    Brown,
+}
+
+impl NoiseSource {
+    pub const ALL: [NoiseSource; NOISE_SOURCES] = [
+        NoiseSource::White,
+        NoiseSource::Perlin,
+        NoiseSource::Value,
+        NoiseSource::Worley,
+        NoiseSource::OpenSimplex,
+        NoiseSource::SuperSimplex,
+        NoiseSource::Billow,
+        NoiseSource::Fbm,
+        NoiseSource::RidgedMulti,
+        NoiseSource::Cylinders,
+        NoiseSource::Pink,
+        NoiseSource::Brown,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NoiseSource::White => "White noise",
+            NoiseSource::Perlin => "Perlin noise",
+            NoiseSource::Value => "Value noise",
+            NoiseSource::Worley => "Worley noise",
+            NoiseSource::OpenSimplex => "Open simplex noise",
+            NoiseSource::SuperSimplex => "Super simplex noise",
+            NoiseSource::Billow => "Billow noise",
+            NoiseSource::Fbm => "Fbm noise",
+            NoiseSource::RidgedMulti => "Ridged multi noise",
+            NoiseSource::Cylinders => "Cylinders noise",
+            NoiseSource::Pink => "Pink noise",
+            NoiseSource::Brown => "Brown noise",
+        }
+    }
+
+    /// Roughly how bright the source sounds against the others, from `-1.0` for the darkest
+    /// to `1.0` for the brightest.
+    pub fn brightness(self) -> f64 {
+        match self {
+            NoiseSource::White => 1.0,
+            NoiseSource::Worley | NoiseSource::RidgedMulti => 0.5,
+            NoiseSource::Value | NoiseSource::Fbm | NoiseSource::Cylinders => 0.0,
+            NoiseSource::Perlin | NoiseSource::OpenSimplex | NoiseSource::SuperSimplex => -0.5,
+            NoiseSource::Billow | NoiseSource::Pink => -0.5,
+            NoiseSource::Brown => -1.0,
+        }
+    }
+
+    /// Whether the source jumps from one value to the next across its lattice, where the slow
+    /// coordinate of a low note can be heard stepping.
+    pub fn is_stepped(self) -> bool {
+        matches!(self, NoiseSource::Value | NoiseSource::Worley)
+    }
+
+    /// Whether the source is drawn at random each sample rather than read off a coordinate.
+    pub fn is_stochastic(self) -> bool {
+        matches!(
+            self,
+            NoiseSource::White | NoiseSource::Pink | NoiseSource::Brown
+        )
+    }
+}
+
+/// White noise tilted down by 3dB per octave.
+///
+/// This is Paul Kellet's economy filter: three one-pole low-passes spread across the audio
+/// band, summed with a little of the unfiltered input.
+struct PinkNoise {
+    poles: [f64; 3],
+    value: f64,
+}
+
+impl PinkNoise {
+    fn new() -> PinkNoise {
+        PinkNoise {
+            poles: [0.0; 3],
+            value: 0.0,
+        }
+    }
+
+    fn advance(&mut self, white: f64) {
+        self.poles[0] = 0.99765 * self.poles[0] + white * 0.099_046;
+        self.poles[1] = 0.963 * self.poles[1] + white * 0.296_516_4;
+        self.poles[2] = 0.57 * self.poles[2] + white * 1.052_691_3;
+        let sum = self.poles.iter().sum::<f64>() + white * 0.1848;
+        // Bring the level roughly in line with the white noise it is made from.
+        self.value = sum * 0.33;
+    }
+}
+
+/// White noise tilted down by 6dB per octave, by leakily integrating it.
+struct BrownNoise {
+    level: f64,
+}
+
+impl BrownNoise {
+    fn new() -> BrownNoise {
+        BrownNoise { level: 0.0 }
+    }
+
+    fn advance(&mut self, white: f64) {
+        self.level = (self.level + 0.02 * white) / 1.02;
+    }
+
+    fn value(&self) -> f64 {
+        // Bring the level roughly in line with the white noise it is made from.
+        self.level * 10.0
+    }
+}
+
+/// One instance of every noise generator, shared by all voices.
+pub struct NoiseGenerators {
+    /// The stochastic sources draw from their own generator rather than the thread's, which
+    /// can reseed itself from the OS in the middle of the audio loop. It is seeded by the
+    /// synth, so the same seed always plays the same noise.
+    rng: XorShiftRng,
+    perlin: Perlin,
+    value: Value,
+    worley: Worley,
+    open_simplex: OpenSimplex,
+    super_simplex: SuperSimplex,
+    billow: Billow,
+    fbm: Fbm,
+    ridged_multi: RidgedMulti,
+    cylinders: Cylinders,
+    pink: PinkNoise,
+    brown: BrownNoise,
+    /// The offset taken off each source and the gain it is then given, so that every source
+    /// is centred on zero and as loud as the white noise. The generators disagree on both, so
+    /// the same amount of each would otherwise sound nothing alike.
+    levels: [(f64, f64); NOISE_SOURCES],
+}
+
+impl NoiseGenerators {
+    /// Every source, with the stochastic ones drawn from `seed`, which must not be all zero.
+    pub fn new(seed: [u32; 4]) -> NoiseGenerators {
+        let mut generators = NoiseGenerators {
+            rng: XorShiftRng::from_seed(seed),
+            perlin: Perlin::new(),
+            value: Value::new(),
+            worley: Worley::new(),
+            open_simplex: OpenSimplex::new(),
+            super_simplex: SuperSimplex::new(),
+            billow: Billow::new(),
+            fbm: Fbm::new(),
+            ridged_multi: RidgedMulti::new(),
+            cylinders: Cylinders::new(),
+            pink: PinkNoise::new(),
+            brown: BrownNoise::new(),
+            levels: [(0.0, 1.0); NOISE_SOURCES],
+        };
+        for &source in NoiseSource::ALL.iter() {
+            if !source.is_stochastic() {
+                generators.levels[source as usize] = generators.calibrate(source);
+            }
+        }
+        generators
+    }
+
+    /// The offset and gain that bring `source` in line with the white noise, measured over a
+    /// grid of points. The stochastic sources are scaled to match as they are made.
+    fn calibrate(&mut self, source: NoiseSource) -> (f64, f64) {
+        let count = (CALIBRATION_POINTS * CALIBRATION_POINTS) as f64;
+        let values: Vec<f64> = (0..CALIBRATION_POINTS * CALIBRATION_POINTS)
+            .map(|i| {
+                let (x, y) = (i % CALIBRATION_POINTS, i / CALIBRATION_POINTS);
+                let at = |n: usize| n as f64 * CALIBRATION_SPACING;
+                self.raw(source, [at(x), at(y)])
+            })
+            .collect();
+        let mean = values.iter().sum::<f64>() / count;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / count;
+        if variance > 0.0 {
+            (mean, REFERENCE_RMS / variance.sqrt())
+        } else {
+            (mean, 1.0)
+        }
+    }
+
+    /// Start the stochastic sources over from `seed`, which must not be all zero.
+    pub fn reseed(&mut self, seed: [u32; 4]) {
+        self.rng.reseed(seed);
+    }
+
+    /// Clear the filtered noise sources' history.
+    pub fn reset(&mut self) {
+        self.pink = PinkNoise::new();
+        self.brown = BrownNoise::new();
+    }
+
+    /// Move the filtered noise sources on by one sample, those that are `enabled`, indexed by
+    /// `NoiseSource`. The rest are held where they are.
+    ///
+    /// Unlike the other sources they keep state, so this is called once per sample and every
+    /// voice reads the same value.
+    pub fn advance(&mut self, enabled: &[bool; NOISE_SOURCES]) {
+        if enabled[NoiseSource::Pink as usize] {
+            let white = self.white_noise();
+            self.pink.advance(white);
+        }
+        if enabled[NoiseSource::Brown as usize] {
+            let white = self.white_noise();
+            self.brown.advance(white);
+        }
+    }
+
+    fn white_noise(&mut self) -> f64 {
+        (self.rng.gen::<f64>() - 0.5) * 2.0
+    }
+
+    /// Sample `source` at `point`. The stochastic sources have no coordinate and ignore it.
+    pub fn get(&mut self, source: NoiseSource, point: [f64; 2]) -> f64 {
+        let (offset, gain) = self.levels[source as usize];
+        (self.raw(source, point) - offset) * gain
+    }
+
+    /// Sample `source` at `point`, straight from its generator.
+    fn raw(&mut self, source: NoiseSource, point: [f64; 2]) -> f64 {
+        match source {
+            NoiseSource::White => self.white_noise(),
+            NoiseSource::Perlin => self.perlin.get(point),
+            NoiseSource::Value => self.value.get(point),
+            NoiseSource::Worley => self.worley.get(point),
+            NoiseSource::OpenSimplex => self.open_simplex.get(point),
+            NoiseSource::SuperSimplex => self.super_simplex.get(point),
+            NoiseSource::Billow => self.billow.get(point),
+            NoiseSource::Fbm => self.fbm.get(point),
+            NoiseSource::RidgedMulti => self.ridged_multi.get(point),
+            NoiseSource::Cylinders => self.cylinders.get(point),
+            NoiseSource::Pink => self.pink.value,
+            NoiseSource::Brown => self.brown.value(),
+        }
+    }
+
+    /// Sample `source` at a point in three dimensions. The stochastic sources ignore it, as
+    /// with `get`.
+    pub fn get_3d(&mut self, source: NoiseSource, point: [f64; 3]) -> f64 {
+        let (offset, gain) = self.levels[source as usize];
+        let raw = match source {
+            NoiseSource::Perlin => self.perlin.get(point),
+            NoiseSource::Value => self.value.get(point),
+            NoiseSource::Worley => self.worley.get(point),
+            NoiseSource::OpenSimplex => self.open_simplex.get(point),
+            NoiseSource::SuperSimplex => self.super_simplex.get(point),
+            NoiseSource::Billow => self.billow.get(point),
+            NoiseSource::Fbm => self.fbm.get(point),
+            NoiseSource::RidgedMulti => self.ridged_multi.get(point),
+            NoiseSource::Cylinders => self.cylinders.get(point),
+            _ => self.raw(source, [point[0], point[1]]),
+        };
+        (raw - offset) * gain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sources::{NoiseGenerators, NoiseSource, NOISE_SOURCES, REFERENCE_RMS};
+
+    const SEED: [u32; 4] = [1, 2, 3, 4];
+
+    /// The fraction of a source's energy that makes it through a low-pass at around 70hz.
+    fn low_frequency_share(source: NoiseSource) -> f64 {
+        let mut generators = NoiseGenerators::new(SEED);
+        let (mut low, mut total) = (0.0, 0.0);
+        let mut smoothed = 0.0;
+        for _ in 0..1 << 16 {
+            generators.advance(&[true; NOISE_SOURCES]);
+            let sample = generators.get(source, [0.0, 0.0]);
+            smoothed += 0.01 * (sample - smoothed);
+            low += smoothed * smoothed;
+            total += sample * sample;
+        }
+        low / total
+    }
+
+    #[test]
+    fn test_sources_are_equally_loud() {
+        let mut generators = NoiseGenerators::new(SEED);
+        let loudness: Vec<f64> = NoiseSource::ALL
+            .iter()
+            .map(|&source| {
+                // Read along a voice's path rather than the grid the levels were measured on.
+                let samples = 1 << 14;
+                let energy: f64 = (0..samples)
+                    .map(|i| {
+                        generators.advance(&[true; NOISE_SOURCES]);
+                        let sample = generators.get(source, [0.61, f64::from(i) * 0.013]);
+                        sample * sample
+                    })
+                    .sum();
+                (energy / f64::from(samples)).sqrt()
+            })
+            .collect();
+        let white = loudness[0];
+        for (source, &rms) in NoiseSource::ALL.iter().zip(loudness.iter()) {
+            let off = (rms / white - 1.0).abs();
+            assert!(off < 0.25, "{:?} {} white {}", source, rms, white);
+        }
+    }
+
+    #[test]
+    fn test_white_noise_is_at_the_reference_level() {
+        assert!((REFERENCE_RMS - 1.0 / 3f64.sqrt()).abs() < 1e-12);
+        let mut generators = NoiseGenerators::new(SEED);
+        let samples = 1 << 16;
+        let energy: f64 = (0..samples)
+            .map(|_| generators.get(NoiseSource::White, [0.0, 0.0]).powi(2))
+            .sum();
+        let rms = (energy / f64::from(samples)).sqrt();
+        assert!((rms / REFERENCE_RMS - 1.0).abs() < 0.02, "{}", rms);
+    }
+
+    #[test]
+    fn test_disabled_sources_hold_their_state() {
+        let mut generators = NoiseGenerators::new(SEED);
+        let mut enabled = [true; NOISE_SOURCES];
+        for _ in 0..100 {
+            generators.advance(&enabled);
+        }
+        enabled[NoiseSource::Pink as usize] = false;
+        let held = generators.get(NoiseSource::Pink, [0.0, 0.0]);
+        for _ in 0..100 {
+            generators.advance(&enabled);
+            assert_eq!(generators.get(NoiseSource::Pink, [0.0, 0.0]), held);
+        }
+        let brown = generators.get(NoiseSource::Brown, [0.0, 0.0]);
+        generators.advance(&enabled);
+        assert_ne!(generators.get(NoiseSource::Brown, [0.0, 0.0]), brown);
+    }
+
+    #[test]
+    fn test_filtered_noise_tilts_towards_low_frequencies() {
+        let white = low_frequency_share(NoiseSource::White);
+        let pink = low_frequency_share(NoiseSource::Pink);
+        let brown = low_frequency_share(NoiseSource::Brown);
+        assert!(pink > white * 2.0, "pink {} white {}", pink, white);
+        assert!(brown > white * 2.0, "brown {} white {}", brown, white);
+    }
+}
@@ -0,0 +1,521 @@
+//! A Karplus-Strong plucked string.
+
+use rand::{Rng, XorShiftRng};
+use std::f64::consts::PI;
+
+/// The range of damping a string can be set to, from a short pluck to an almost endless ring.
+pub const MIN_DAMPING: f32 = 0.9;
+pub const MAX_DAMPING: f32 = 0.9999;
+
+/// The damping a string is set to unless told otherwise.
+pub const DEFAULT_DAMPING: f32 = 0.996;
+
+/// The range of time a string can be set to take to fall 60 dB, in seconds, in place of its
+/// damping.
+pub const MIN_DECAY: f64 = 0.1;
+pub const MAX_DECAY: f64 = 60.0;
+
+/// The longest the pluck's noise can keep being fed into the line, in seconds.
+pub const MAX_EXCITE_LENGTH: f64 = 0.05;
+
+/// How much of the last sample is kept in each of the burst's, at the softest a pluck can be.
+/// At 44100 samples a second this low-passes it from around 700hz.
+const MAX_SOFTENING: f32 = 0.9;
+
+/// The sample rate the range of damping was tuned at.
+const REFERENCE_RATE: f64 = 44100.0;
+
+/// How a string's period is fitted to its pitch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tuning {
+    /// Made up to the exact length with a fractional delay, so every key is in tune.
+    Exact,
+    /// Cut to a whole number of samples, so the higher keys ring sharp, the lo-fi way.
+    Rounded,
+}
+
+impl Tuning {
+    pub const ALL: [Tuning; 2] = [Tuning::Exact, Tuning::Rounded];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tuning::Exact => "Exact",
+            Tuning::Rounded => "Rounded",
+        }
+    }
+}
+
+/// The shortest fractional delay the allpass is asked for, in samples. Near zero its
+/// coefficient nears one and it rings on a note change, so a sample is taken out of the line
+/// instead.
+const MIN_FRACTION: f64 = 0.1;
+
+/// A delay line one period long, filled with noise when plucked and low-passed on each trip
+/// round the loop so the higher harmonics die away first.
+pub struct KarplusString {
+    buffer: Vec<f32>,
+    /// The next sample to be read.
+    position: usize,
+    /// Samples left of the pluck's burst, during which fresh noise goes back into the line in
+    /// place of the damped feedback.
+    excite: usize,
+    /// An xorshift generator for the burst, seeded from the synth's own at the pluck.
+    noise: u32,
+    /// How much of the last burst sample is kept in the next, low-passing it for a softer
+    /// pluck, and the last one drawn.
+    softening: f32,
+    burst: f32,
+    tuning: Tuning,
+    /// The frequency the string was last tuned to, so `retune` only works it out on a change.
+    tuned: f64,
+    /// What the damping is scaled by so that the string rings as long as it would at
+    /// `REFERENCE_RATE`. The averaging takes less off each trip the higher the rate, so
+    /// without it a note would ring on longer in seconds.
+    compensation: f32,
+    /// The decay time `decay_damping` last worked out the damping for, or `0.0` once the
+    /// string is retuned to another length and it has to be worked out again, and the damping.
+    decay: f64,
+    decay_damping: f32,
+    /// The coefficient of the first-order allpass making up the fraction of a sample the line
+    /// falls short by, and its last input and output. Left out for `Tuning::Rounded`.
+    allpass: f32,
+    allpass_in: f32,
+    allpass_out: f32,
+}
+
+/// The length of delay line that rings at `frequency` at `sample_rate` with `tuning`, and the
+/// fraction of a sample the allpass has to add to it.
+///
+/// The averaging across neighbouring samples takes half a sample off each trip round the loop,
+/// which is added back into the fraction.
+fn period(frequency: f64, tuning: Tuning, sample_rate: f64) -> (usize, f64) {
+    let exact = sample_rate / frequency;
+    match tuning {
+        Tuning::Exact => {
+            let len = ((exact + 0.5 - MIN_FRACTION) as usize).max(2);
+            (len, exact + 0.5 - len as f64)
+        }
+        Tuning::Rounded => ((exact as usize).max(2), 0.0),
+    }
+}
+
+/// The longest line needed for a pitch down to `lowest` hz at `sample_rate`, with either
+/// tuning.
+fn room(lowest: f64, sample_rate: f64) -> usize {
+    let exact = period(lowest, Tuning::Exact, sample_rate).0;
+    exact.max(period(lowest, Tuning::Rounded, sample_rate).0)
+}
+
+/// The damping scale that makes up at `sample_rate` for the averaging's loss at `frequency`
+/// differing from `REFERENCE_RATE`'s. The averaging passes `cos(pi * f / rate)` of the pitch
+/// on each trip.
+fn compensation(frequency: f64, sample_rate: f64) -> f32 {
+    let factor = |rate: f64| (PI * frequency / rate).cos();
+    let here = factor(sample_rate);
+    if here > 0.0 {
+        (factor(REFERENCE_RATE).max(0.0) / here) as f32
+    } else {
+        1.0
+    }
+}
+
+/// The allpass coefficient for a delay of `fraction` samples, at low frequencies.
+fn allpass_coefficient(fraction: f64) -> f32 {
+    ((1.0 - fraction) / (1.0 + fraction)) as f32
+}
+
+impl KarplusString {
+    /// Pluck a string tuned to `frequency` at `sample_rate`, by `tuning`.
+    #[cfg(test)]
+    pub fn new(frequency: f64, tuning: Tuning, sample_rate: f64) -> KarplusString {
+        let mut string = KarplusString::with_room(frequency, sample_rate);
+        let mut rng = XorShiftRng::new_unseeded();
+        string.pluck(frequency, tuning, sample_rate, &mut rng);
+        string
+    }
+
+    /// A silent string with room in its line for any pitch down to `lowest` hz at
+    /// `sample_rate`, so that plucking it never allocates.
+    pub fn with_room(lowest: f64, sample_rate: f64) -> KarplusString {
+        KarplusString {
+            buffer: Vec::with_capacity(room(lowest, sample_rate)),
+            position: 0,
+            excite: 0,
+            noise: 1,
+            softening: 0.0,
+            burst: 0.0,
+            tuning: Tuning::Exact,
+            tuned: 0.0,
+            compensation: 1.0,
+            decay: 0.0,
+            decay_damping: 1.0,
+            allpass: 0.0,
+            allpass_in: 0.0,
+            allpass_out: 0.0,
+        }
+    }
+
+    /// Make room in the line for any pitch down to `lowest` hz at `sample_rate`.
+    ///
+    /// Reserving the room up front means no later pluck down to `lowest` grows the line.
+    pub fn make_room(&mut self, lowest: f64, sample_rate: f64) {
+        let room = room(lowest, sample_rate);
+        if room > self.buffer.len() {
+            self.buffer.reserve(room - self.buffer.len());
+        }
+    }
+
+    /// Pluck the string afresh, tuned to `frequency` at `sample_rate` by `tuning`, with its
+    /// noise drawn from `rng`.
+    ///
+    /// The line is reused, so this only allocates for a pitch lower than the string has room
+    /// for.
+    pub fn pluck(
+        &mut self,
+        frequency: f64,
+        tuning: Tuning,
+        sample_rate: f64,
+        rng: &mut XorShiftRng,
+    ) {
+        let (len, fraction) = period(frequency, tuning, sample_rate);
+        self.buffer.clear();
+        let noise = (0..len).map(|_| (rng.gen::<f32>() - 0.5) * 2.0);
+        self.buffer.extend(noise);
+        self.position = 0;
+        self.excite = 0;
+        self.noise = rng.gen::<u32>() | 1;
+        self.softening = 0.0;
+        self.burst = 0.0;
+        self.tuning = tuning;
+        self.tuned = frequency;
+        self.compensation = compensation(frequency, sample_rate);
+        self.decay = 0.0;
+        self.allpass = allpass_coefficient(fraction);
+        self.allpass_in = 0.0;
+        self.allpass_out = 0.0;
+    }
+
+    /// Keep feeding the line with noise for `samples` samples after the pluck, for a brighter
+    /// onset as the higher harmonics are topped back up while it runs.
+    pub fn excite(&mut self, samples: usize) {
+        self.excite = samples;
+    }
+
+    /// Low-pass the pluck's noise, and the burst fed in after it, by `softness`, `0.0..=1.0`,
+    /// for a duller pluck. Left at `0.0`, the noise is as bright as it was drawn.
+    pub fn soften(&mut self, softness: f32) {
+        self.softening = softness.clamp(0.0, 1.0) * MAX_SOFTENING;
+        if self.softening <= 0.0 {
+            return;
+        }
+        let kept = self.softening;
+        // Two trips round, so the start of the line follows on from its end.
+        let len = self.buffer.len();
+        let mut last = 0.0;
+        for i in 0..2 * len {
+            let sample = &mut self.buffer[i % len];
+            last = *sample + (last - *sample) * kept;
+            if i >= len {
+                *sample = last;
+            }
+        }
+        self.burst = last;
+    }
+
+    /// Empty the line, so the string only rings with what is fed into it by `drive`.
+    pub fn silence(&mut self) {
+        for sample in self.buffer.iter_mut() {
+            *sample = 0.0;
+        }
+        self.allpass_in = 0.0;
+        self.allpass_out = 0.0;
+    }
+
+    /// The pitch the string actually rings at, which `Tuning::Rounded` leaves slightly sharp
+    /// of the frequency it was tuned to.
+    #[cfg(test)]
+    pub fn frequency(&self, sample_rate: f64) -> f64 {
+        let fraction = match self.tuning {
+            Tuning::Exact => f64::from((1.0 - self.allpass) / (1.0 + self.allpass)),
+            Tuning::Rounded => 0.0,
+        };
+        sample_rate / (self.buffer.len() as f64 - 0.5 + fraction)
+    }
+
+    /// Retune the ringing string to `frequency` at `sample_rate`, for a pitch that moves while
+    /// it sounds.
+    ///
+    /// The allpass takes up the fraction of a sample, and the whole samples are put into or
+    /// taken out of the line just behind where it is read. It is never made longer than the
+    /// room it was given, so this never allocates.
+    pub fn retune(&mut self, frequency: f64, sample_rate: f64) {
+        if frequency == self.tuned {
+            return;
+        }
+        self.tuned = frequency;
+        let (len, fraction) = period(frequency, self.tuning, sample_rate);
+        let len = len.min(self.buffer.capacity());
+        self.allpass = allpass_coefficient(fraction);
+        let old_len = self.buffer.len();
+        if len == old_len {
+            return;
+        }
+        // Within a sample of length the damping's factors move too little to hear, so they are
+        // only worked out again, with a `powf` and the `cos` calls, once the line changes.
+        self.compensation = compensation(frequency, sample_rate);
+        self.decay = 0.0;
+        // Lined up from the next sample to be read, so the end of the line is the newest.
+        self.buffer.rotate_left(self.position);
+        self.position = 0;
+        if len > old_len {
+            // Filled in across the join, from the newest sample round to the oldest.
+            let (newest, oldest) = (self.buffer[old_len - 1], self.buffer[0]);
+            let gap = (len - old_len + 1) as f32;
+            let fill = (1..=len - old_len).map(|i| newest + (oldest - newest) * i as f32 / gap);
+            self.buffer.extend(fill);
+        } else {
+            self.buffer.truncate(len);
+        }
+    }
+
+    /// Carry the string over to a new `sample_rate` at the pitch it was last tuned to, bent and
+    /// all, without cutting it off.
+    ///
+    /// What is ringing in the line is stretched over its new length, so the string carries on
+    /// from where it was at the right pitch. This allocates, so it must not be called from the
+    /// audio loop.
+    pub fn resample(&mut self, sample_rate: f64) {
+        let old_len = self.buffer.len();
+        let (new_len, fraction) = period(self.tuned, self.tuning, sample_rate);
+        self.compensation = compensation(self.tuned, sample_rate);
+        self.allpass = allpass_coefficient(fraction);
+        let old: Vec<f32> = (0..old_len)
+            .map(|i| self.buffer[(self.position + i) % old_len])
+            .collect();
+        self.buffer = (0..new_len)
+            .map(|i| {
+                let at = i as f64 * old_len as f64 / new_len as f64;
+                let (idx, frac) = (at as usize, at.fract() as f32);
+                old[idx] + (old[(idx + 1) % old_len] - old[idx]) * frac
+            })
+            .collect();
+        self.position = 0;
+    }
+
+    /// The damping that takes the string's pitch 60 dB down in `decay` seconds, at any sample
+    /// rate. It is only worked out again when `decay` or the pitch changes.
+    pub fn decay_damping(&mut self, decay: f64) -> f32 {
+        if decay != self.decay {
+            self.decay = decay;
+            let kept = 10.0f64.powf(-3.0 / (self.tuned * decay));
+            // Less what the averaging takes off at `REFERENCE_RATE`, which the compensation
+            // holds it to at any other.
+            let averaged = (PI * self.tuned / REFERENCE_RATE).cos();
+            let damping = if averaged > 0.0 { kept / averaged } else { 1.0 };
+            self.decay_damping = damping as f32;
+        }
+        self.decay_damping
+    }
+
+    /// The string's next output sample, with `damping` controlling how much of the signal
+    /// survives each trip round the loop at `REFERENCE_RATE`, and as long a ring in seconds at
+    /// any other.
+    pub fn sample(&mut self, damping: f32) -> f32 {
+        self.drive(damping, 0.0)
+    }
+
+    /// Like `sample`, but with `input` fed into the loop alongside what comes back round it,
+    /// so the string resonates with it.
+    pub fn drive(&mut self, damping: f32, input: f32) -> f32 {
+        let len = self.buffer.len();
+        let out = self.buffer[self.position];
+        let next = self.buffer[(self.position + 1) % len];
+        let feedback = if self.excite > 0 {
+            self.excite -= 1;
+            self.noise ^= self.noise << 13;
+            self.noise ^= self.noise >> 17;
+            self.noise ^= self.noise << 5;
+            let noise = (f64::from(self.noise) / f64::from(u32::MAX) * 2.0 - 1.0) as f32;
+            self.burst = noise + (self.burst - noise) * self.softening;
+            self.burst
+        } else {
+            let damping = (damping * self.compensation).min(1.0);
+            let averaged = ::flush_denormal(damping * 0.5 * (out + next));
+            if self.tuning == Tuning::Rounded {
+                averaged
+            } else {
+                let delayed = self.allpass * (averaged - self.allpass_out) + self.allpass_in;
+                self.allpass_in = averaged;
+                self.allpass_out = ::flush_denormal(delayed);
+                self.allpass_out
+            }
+        };
+        self.buffer[self.position] = feedback + input;
+        self.position = (self.position + 1) % len;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::XorShiftRng;
+    use std::f64::consts::PI;
+    use string::{KarplusString, Tuning, MAX_DAMPING, MAX_EXCITE_LENGTH};
+
+    #[test]
+    fn test_period_repeats() {
+        let mut string = KarplusString::new(100.0, Tuning::Rounded, 1000.0);
+        // The averaging takes half a sample off the period.
+        assert_eq!(string.frequency(1000.0), 1000.0 / 9.5);
+        let first: Vec<f32> = (0..10).map(|_| string.sample(1.0)).collect();
+        let second: Vec<f32> = (0..10).map(|_| string.sample(1.0)).collect();
+        // Each trip round the loop averages neighbouring samples.
+        for i in 0..9 {
+            assert!((second[i] - 0.5 * (first[i] + first[i + 1])).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_resample_keeps_ringing() {
+        let mut string = KarplusString::new(100.0, Tuning::Exact, 1000.0);
+        string.resample(2000.0);
+        assert!((string.frequency(2000.0) - 100.0).abs() < 1e-3);
+        assert!((0..20).any(|_| string.sample(1.0) != 0.0));
+    }
+
+    #[test]
+    fn test_pluck_reuses_the_line() {
+        let mut string = KarplusString::with_room(50.0, 1000.0);
+        let line = string.buffer.as_ptr();
+        let mut rng = XorShiftRng::new_unseeded();
+        for &frequency in [100.0, 50.0, 400.0].iter() {
+            string.pluck(frequency, Tuning::Exact, 1000.0, &mut rng);
+            assert!((string.frequency(1000.0) - frequency).abs() < 1e-3);
+            assert!((0..20).any(|_| string.sample(1.0) != 0.0));
+        }
+        assert_eq!(string.buffer.as_ptr(), line);
+    }
+
+    #[test]
+    fn test_dying_string_flushes_to_zero() {
+        let mut string = KarplusString::new(100.0, Tuning::Exact, 1000.0);
+        for _ in 0..100_000 {
+            let sample = string.sample(0.9);
+            assert!(sample == 0.0 || sample.is_normal(), "{}", sample);
+        }
+        assert!(string.buffer.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_longer_excitation_is_brighter() {
+        // How much the string moves from one sample to the next over its first fifth of a
+        // second, which grows with its high harmonics.
+        let brightness = |excite: f64| {
+            let mut string = KarplusString::new(100.0, Tuning::Exact, 1000.0);
+            string.excite((excite * 1000.0) as usize);
+            let onset: Vec<f32> = (0..200).map(|_| string.sample(0.99)).collect();
+            onset
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).powi(2))
+                .sum::<f32>()
+        };
+        let short = brightness(0.0);
+        let long = brightness(MAX_EXCITE_LENGTH);
+        assert!(long > short * 1.5, "{} against {}", long, short);
+    }
+
+    /// The period the string settles into, in samples, from the peak of its autocorrelation.
+    fn measured_period(mut string: KarplusString) -> f64 {
+        // Past the pluck, once only the lowest harmonics are left.
+        for _ in 0..20000 {
+            string.sample(1.0);
+        }
+        let output: Vec<f32> = (0..4000).map(|_| string.sample(1.0)).collect();
+        let correlation = |lag: usize| -> f64 {
+            let pairs = output.iter().zip(output[lag..].iter());
+            pairs.map(|(&a, &b)| f64::from(a * b)).sum::<f64>() / (output.len() - lag) as f64
+        };
+        let lags: Vec<f64> = (0..200).map(correlation).collect();
+        // Nearly a sine by now, so the second period's peak can come out a hair above the
+        // first's. The first peak about as high as the highest is taken.
+        let highest = lags[20..199].iter().cloned().fold(0.0, f64::max);
+        let is_peak = |lag: usize| lags[lag] >= lags[lag - 1] && lags[lag] >= lags[lag + 1];
+        let first = (20..199).find(|&lag| is_peak(lag) && lags[lag] > highest * 0.99);
+        let peak = first.unwrap();
+        // Between the lags either side, on a parabola through the three.
+        let (before, at, after) = (lags[peak - 1], lags[peak], lags[peak + 1]);
+        peak as f64 + 0.5 * (before - after) / (before - 2.0 * at + after)
+    }
+
+    #[test]
+    fn test_retune_glides_in_place() {
+        for &target in [466.16, 415.3].iter() {
+            let mut string = KarplusString::with_room(200.0, 44100.0);
+            let mut rng = XorShiftRng::new_unseeded();
+            string.pluck(440.0, Tuning::Exact, 44100.0, &mut rng);
+            let line = string.buffer.as_ptr();
+            // A little further each sample, as a bend would take it.
+            for i in 1..=1000 {
+                let frequency = 440.0 + (target - 440.0) * f64::from(i) / 1000.0;
+                string.retune(frequency, 44100.0);
+                string.sample(1.0);
+            }
+            assert_eq!(string.buffer.as_ptr(), line);
+            let period = measured_period(string);
+            let off = (period - 44100.0 / target).abs();
+            assert!(off < 0.05, "{} {}", target, period);
+        }
+    }
+
+    #[test]
+    fn test_bends_keep_the_decay_until_the_length_changes() {
+        let mut string = KarplusString::new(440.0, Tuning::Exact, 44100.0);
+        let damping = string.decay_damping(2.0);
+        // 100.23 samples and 100.11 are cut to the same length, and a semitone up is not.
+        string.retune(440.5, 44100.0);
+        assert_eq!(string.decay, 2.0);
+        assert_eq!(string.decay_damping(2.0), damping);
+        string.retune(466.16, 44100.0);
+        assert_eq!(string.decay, 0.0);
+        assert!(string.decay_damping(2.0) != damping);
+    }
+
+    #[test]
+    fn test_rings_as_long_at_any_rate() {
+        // How far the string falls between a tenth of a second in and six tenths, in dB.
+        let fall = |sample_rate: f64| {
+            let mut string = KarplusString::new(1000.0, Tuning::Exact, sample_rate);
+            let tenth = (sample_rate * 0.1) as usize;
+            // At the pitch alone, which the damping is made up for.
+            let mut level = |skip: usize| {
+                for _ in 0..skip {
+                    string.sample(MAX_DAMPING);
+                }
+                let (mut re, mut im) = (0.0, 0.0);
+                for i in 0..tenth {
+                    let angle = 2.0 * PI * 1000.0 * i as f64 / sample_rate;
+                    let sample = f64::from(string.sample(MAX_DAMPING));
+                    re += sample * angle.cos();
+                    im += sample * angle.sin();
+                }
+                (re * re + im * im).sqrt()
+            };
+            let early = level(tenth);
+            let late = level(4 * tenth);
+            20.0 * (early / late).log10()
+        };
+        let (reference, high) = (fall(44100.0), fall(96000.0));
+        assert!(reference > 5.0, "{}", reference);
+        assert!((high - reference).abs() < 0.1, "{} {}", high, reference);
+    }
+
+    #[test]
+    fn test_exact_tuning_rings_in_tune() {
+        // 44100 over 440 is 100.23 samples.
+        let exact = measured_period(KarplusString::new(440.0, Tuning::Exact, 44100.0));
+        assert!((exact - 44100.0 / 440.0).abs() < 0.05, "{}", exact);
+        // Cut to 100 samples, less the averaging's half.
+        let rounded = measured_period(KarplusString::new(440.0, Tuning::Rounded, 44100.0));
+        assert!((rounded - 99.5).abs() < 0.05, "{}", rounded);
+    }
+}
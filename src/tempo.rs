@@ -0,0 +1,181 @@
+//! Note divisions, for locking rates and times to the host's tempo, and a follower for hosts
+//! that only send midi clock.
+
+/// How many midi clock ticks there are to each quarter note.
+const TICKS_PER_BEAT: f64 = 24.0;
+
+/// The slowest tempo the clock is followed at, in beats per minute. Ticks further apart than
+/// this have stopped coming, rather than slowed down.
+const MIN_CLOCK_TEMPO: f64 = 20.0;
+
+/// How much of the way each tick's spacing moves the clock's estimate, to ride out jitter.
+const CLOCK_SMOOTHING: f64 = 0.2;
+
+/// A length of time in musical terms, or `Free` to leave it in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Division {
+    Free,
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    DottedQuarter,
+    DottedEighth,
+    QuarterTriplet,
+    EighthTriplet,
+}
+
+impl Division {
+    pub const ALL: [Division; 10] = [
+        Division::Free,
+        Division::Whole,
+        Division::Half,
+        Division::Quarter,
+        Division::Eighth,
+        Division::Sixteenth,
+        Division::DottedQuarter,
+        Division::DottedEighth,
+        Division::QuarterTriplet,
+        Division::EighthTriplet,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Division::Free => "Free",
+            Division::Whole => "1/1",
+            Division::Half => "1/2",
+            Division::Quarter => "1/4",
+            Division::Eighth => "1/8",
+            Division::Sixteenth => "1/16",
+            Division::DottedQuarter => "1/4 dotted",
+            Division::DottedEighth => "1/8 dotted",
+            Division::QuarterTriplet => "1/4 triplet",
+            Division::EighthTriplet => "1/8 triplet",
+        }
+    }
+
+    /// The division's length in quarter notes, or `None` if it is `Free`.
+    pub fn beats(self) -> Option<f64> {
+        match self {
+            Division::Free => None,
+            Division::Whole => Some(4.0),
+            Division::Half => Some(2.0),
+            Division::Quarter => Some(1.0),
+            Division::Eighth => Some(0.5),
+            Division::Sixteenth => Some(0.25),
+            Division::DottedQuarter => Some(1.5),
+            Division::DottedEighth => Some(0.75),
+            Division::QuarterTriplet => Some(2.0 / 3.0),
+            Division::EighthTriplet => Some(1.0 / 3.0),
+        }
+    }
+
+    /// The division's length in seconds at `tempo` beats per minute, or `None` if it is
+    /// `Free`.
+    pub fn seconds(self, tempo: f64) -> Option<f64> {
+        self.beats().map(|beats| beats * 60.0 / tempo)
+    }
+}
+
+/// The tempo and transport read off a stream of midi clock, start, continue and stop messages.
+pub struct MidiClock {
+    /// Samples processed, up to the start of the block whose events are coming in.
+    now: u64,
+    /// When the last tick came, in samples.
+    last: Option<u64>,
+    /// The samples between ticks, smoothed.
+    interval: Option<f64>,
+    /// Whether the transport is running, or `None` until it has been started or stopped.
+    playing: Option<bool>,
+}
+
+impl MidiClock {
+    pub fn new() -> MidiClock {
+        MidiClock {
+            now: 0,
+            last: None,
+            interval: None,
+            playing: None,
+        }
+    }
+
+    /// Move on past a block of `samples`, once its events have been handled.
+    pub fn advance(&mut self, samples: usize) {
+        self.now += samples as u64;
+    }
+
+    /// Count a tick `offset` samples into the coming block, at `sample_rate`.
+    pub fn tick(&mut self, offset: usize, sample_rate: f64) {
+        let at = self.now + offset as u64;
+        if let Some(last) = self.last {
+            let gap = at.saturating_sub(last) as f64;
+            self.interval = match self.interval {
+                // After a gap the clock has started over, and the old spacing means nothing.
+                _ if gap > max_tick_gap(sample_rate) => None,
+                Some(interval) => Some(interval + (gap - interval) * CLOCK_SMOOTHING),
+                None => Some(gap),
+            };
+        }
+        self.last = Some(at);
+    }
+
+    /// Start or stop the transport.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = Some(playing);
+    }
+
+    pub fn playing(&self) -> Option<bool> {
+        self.playing
+    }
+
+    /// The clock's tempo in beats per minute at `sample_rate`, or `None` if it has not been
+    /// ticking lately.
+    pub fn tempo(&self, sample_rate: f64) -> Option<f64> {
+        let last = self.last?;
+        if self.now.saturating_sub(last) as f64 > max_tick_gap(sample_rate) {
+            return None;
+        }
+        let interval = self.interval.filter(|&interval| interval > 0.0)?;
+        Some(60.0 * sample_rate / (TICKS_PER_BEAT * interval))
+    }
+}
+
+/// The most samples there can be between two ticks of a clock that is still running.
+fn max_tick_gap(sample_rate: f64) -> f64 {
+    60.0 * sample_rate / (TICKS_PER_BEAT * MIN_CLOCK_TEMPO)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempo::{Division, MidiClock};
+
+    #[test]
+    fn test_division_lengths() {
+        assert_eq!(Division::Free.seconds(120.0), None);
+        assert_eq!(Division::Quarter.seconds(120.0), Some(0.5));
+        assert_eq!(Division::Whole.seconds(60.0), Some(4.0));
+        assert_eq!(Division::DottedEighth.seconds(120.0), Some(0.375));
+        let triplet = Division::QuarterTriplet.seconds(120.0).unwrap();
+        assert!((triplet * 3.0 - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_clock_follows_ticks() {
+        let mut clock = MidiClock::new();
+        assert_eq!(clock.tempo(48000.0), None);
+        // 24 ticks to a beat, 1000 samples apart at 48khz, is 120 beats a minute.
+        for _ in 0..48 {
+            clock.tick(0, 48000.0);
+            clock.advance(1000);
+        }
+        let tempo = clock.tempo(48000.0).unwrap();
+        assert!((tempo - 120.0).abs() < 1e-9, "{}", tempo);
+
+        // Held up for a second, the clock has stopped.
+        clock.advance(48000);
+        assert_eq!(clock.tempo(48000.0), None);
+        clock.tick(0, 48000.0);
+        assert_eq!(clock.tempo(48000.0), None);
+    }
+}